@@ -0,0 +1,320 @@
+//! Types of the socket protocol spoken between wpmd and its clients, which third-party clients
+//! can depend on without pulling in the process manager
+
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Revision of the wpmd socket protocol, bumped whenever [`SocketMessage`] or the
+/// responses sent back to wpmctl change shape
+pub const PROTOCOL_VERSION: u32 = 25;
+
+/// A [`SocketMessage`] tagged with the protocol revision of the client which sent it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocketEnvelope {
+    pub protocol_version: u32,
+    pub message: SocketMessage,
+    /// Name of the socket the client listens on for the response, which is unique to each client
+    /// so that concurrent clients never receive each other's responses; clients which don't set
+    /// it are answered on the shared wpmctl socket
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+}
+
+/// Messages which act on units, like [`SocketMessage::Start`], are answered with a JSON array of
+/// [`UnitResult`] with the outcome for each unit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SocketMessage {
+    Start(Vec<String>),
+    /// Start units without starting the units they require
+    StartWithoutDependencies(Vec<String>),
+    Stop(Vec<String>),
+    /// Stop units without running their ExecStop commands
+    ForceStop(Vec<String>),
+    /// Track already running processes for units instead of starting new ones
+    Adopt(Vec<String>),
+    /// Status of a unit, including the given number of recent log lines
+    Status(String, usize),
+    /// Table of all units, with the requested columns and sort order
+    State(StateOptions),
+    /// Explanation of the current state of a unit, built from its recorded events
+    Why(String),
+    /// The environment ExecStart of a unit is started with, including the variables inherited
+    /// from wpmd
+    Show(String),
+    /// Aggregate statistics of the daemon and of all registered units
+    Stats,
+    Reload(Option<PathBuf>),
+    Reset(Vec<String>),
+    /// Kill running processes of the given units which wpm doesn't track, for example after
+    /// wpmd crashed, answered with a line for each unit
+    KillOrphans(Vec<String>),
+    /// Resume automatic restarts after they were paused for exceeding the restart budget
+    ResetRestartBudget,
+    /// Start units and their dependencies, and stop every other running unit
+    Isolate(Vec<String>),
+    /// Save the set of running units under a name
+    SnapshotSave(String),
+    /// Isolate the set of units saved under a name
+    SnapshotRestore(String),
+    Restart(Vec<String>),
+    RestartWithDependents(Vec<String>),
+    ProtocolVersion,
+    /// Kinds and states of the given registered units, answered with a JSON array of
+    /// [`UnitStateReport`]
+    UnitStates(Vec<String>),
+    /// Recent resource usage samples of the given units, or of all units if none are given,
+    /// answered with a JSON object of [`UsageSample`] arrays keyed by unit name
+    UsageHistory(Vec<String>),
+    /// Replace the daemon's log filter, using the same syntax as RUST_LOG
+    LogLevel(String),
+    /// Stop a unit if it is running and unregister it, deleting its unit file unless asked not to
+    Remove(String, RemoveOptions),
+    /// Apply an action to each of the given units, continuing past failures, answered with a
+    /// JSON array of [`UnitResult`]
+    Batch(UnitAction, Vec<String>),
+    /// The directories wpmd was started with, answered with a JSON [`DaemonDirectories`]
+    Directories,
+    /// Apply an action to units on behalf of wpmd itself, for example for scheduled restarts,
+    /// which isn't answered as no client is waiting for it
+    Scheduled(UnitAction, Vec<String>),
+}
+
+/// The directories wpmd loads units from and keeps its data, store and unit logs in, which
+/// can be overridden by its command line flags
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonDirectories {
+    pub units: PathBuf,
+    pub data: PathBuf,
+    pub store: PathBuf,
+    pub logs: PathBuf,
+}
+
+/// An action which can be applied to a batch of units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnitAction {
+    Start,
+    StartWithoutDependencies,
+    Stop,
+    ForceStop,
+    Restart,
+    RestartWithDependents,
+}
+
+/// The outcome of an action for a single unit of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitResult {
+    pub name: String,
+    /// Why the action failed for this unit, or `None` if it succeeded
+    pub error: Option<String>,
+    /// Whether the unit had to be stopped before it was started again, for restarts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartBranch>,
+}
+
+/// What a restart did for a unit, depending on whether it was running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RestartBranch {
+    /// The unit was running, so it was stopped and started again
+    Restarted,
+    /// The unit wasn't running, so it was only started
+    Started,
+}
+
+impl Display for RestartBranch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestartBranch::Restarted => write!(f, "restarted"),
+            RestartBranch::Started => write!(f, "started"),
+        }
+    }
+}
+
+/// A column of the state table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StateColumn {
+    Name,
+    Kind,
+    State,
+    Pid,
+    Timestamp,
+    Reason,
+    Uptime,
+}
+
+impl StateColumn {
+    /// The columns shown when none are requested
+    pub const DEFAULT: [StateColumn; 6] = [
+        StateColumn::Name,
+        StateColumn::Kind,
+        StateColumn::State,
+        StateColumn::Pid,
+        StateColumn::Timestamp,
+        StateColumn::Reason,
+    ];
+
+    pub const ALL: [StateColumn; 7] = [
+        StateColumn::Name,
+        StateColumn::Kind,
+        StateColumn::State,
+        StateColumn::Pid,
+        StateColumn::Timestamp,
+        StateColumn::Reason,
+        StateColumn::Uptime,
+    ];
+}
+
+impl Display for StateColumn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateColumn::Name => write!(f, "name"),
+            StateColumn::Kind => write!(f, "kind"),
+            StateColumn::State => write!(f, "state"),
+            StateColumn::Pid => write!(f, "pid"),
+            StateColumn::Timestamp => write!(f, "timestamp"),
+            StateColumn::Reason => write!(f, "reason"),
+            StateColumn::Uptime => write!(f, "uptime"),
+        }
+    }
+}
+
+impl FromStr for StateColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StateColumn::ALL
+            .into_iter()
+            .find(|column| column.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let valid = StateColumn::ALL.map(|column| column.to_string());
+                format!("unknown column '{s}' (valid columns: {})", valid.join(", "))
+            })
+    }
+}
+
+/// Which columns of the state table to show, which units to show, and which column to sort the
+/// rows by
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateOptions {
+    /// Columns to show in order, or [`StateColumn::DEFAULT`] if empty
+    pub columns: Vec<StateColumn>,
+    pub sort: Option<StateColumn>,
+    /// States of the units to show, or every unit if empty
+    pub states: Vec<UnitState>,
+    /// Show only the names of the units, one per line, instead of a table
+    pub quiet: bool,
+    /// Show the units as a JSON array of [`UnitStateEntry`] instead of a table
+    pub json: bool,
+    /// Number of recent log lines to include for each unit in JSON output, up to
+    /// [`MAX_STATE_LOG_LINES`]
+    pub log_lines: usize,
+}
+
+/// Upper bound on the log lines included for each unit in JSON state output, so that the
+/// response stays small enough to be polled
+pub const MAX_STATE_LOG_LINES: usize = 50;
+
+/// A row of the state table in JSON state output
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UnitStateEntry {
+    pub name: String,
+    pub kind: Option<ServiceKind>,
+    pub state: UnitState,
+    pub pid: Option<u32>,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub reason: Option<String>,
+    pub uptime_secs: Option<u64>,
+    /// Recent lines of the unit's log, only included when they were requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<Vec<String>>,
+}
+
+/// What to delete along with a unit which is removed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RemoveOptions {
+    /// Keep the unit file, so that the unit is registered again on the next reload
+    pub keep_file: bool,
+    /// Delete the log of the unit
+    pub purge_logs: bool,
+    /// Delete the files in the store which belong to the unit and aren't used by other units
+    pub purge_store: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnitState {
+    Running,
+    Stopped,
+    Completed,
+    Failed,
+    Terminated,
+    LoadError,
+}
+
+impl Display for UnitState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnitState::Running => write!(f, "Running"),
+            UnitState::Stopped => write!(f, "Stopped"),
+            UnitState::Completed => write!(f, "Completed"),
+            UnitState::Failed => write!(f, "Failed"),
+            UnitState::Terminated => write!(f, "Terminated"),
+            UnitState::LoadError => write!(f, "LoadError"),
+        }
+    }
+}
+
+/// The kind and state of a unit, sent to wpmctl when it waits for units to settle
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UnitStateReport {
+    pub name: String,
+    pub kind: Option<ServiceKind>,
+    pub state: UnitState,
+    /// The units which this unit requires
+    #[serde(default)]
+    pub requires: Vec<String>,
+}
+
+/// How wpmd tracks the process of a unit
+#[derive(Default, Serialize, Deserialize, Copy, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum ServiceKind {
+    #[default]
+    Simple,
+    Oneshot,
+    Forking,
+}
+
+impl Display for ServiceKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceKind::Simple => write!(f, "Simple"),
+            ServiceKind::Oneshot => write!(f, "Oneshot"),
+            ServiceKind::Forking => write!(f, "Forking"),
+        }
+    }
+}
+
+/// Memory and CPU usage of a unit's process at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageSample {
+    pub timestamp: DateTime<Utc>,
+    /// Memory usage in bytes, which is the working set on Windows
+    pub memory: u64,
+    /// CPU usage since the previous sample in percent of a single logical processor
+    pub cpu_percent: f32,
+}
+
+impl UsageSample {
+    pub fn now(memory: u64, cpu_percent: f32) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            memory,
+            cpu_percent,
+        }
+    }
+}
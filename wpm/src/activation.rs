@@ -0,0 +1,139 @@
+use std::ffi::c_void;
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use windows_sys::core::GUID;
+use windows_sys::core::HRESULT;
+use windows_sys::Win32::Foundation::CloseHandle;
+use windows_sys::Win32::System::Com::CoCreateInstance;
+use windows_sys::Win32::System::Com::CoInitializeEx;
+use windows_sys::Win32::System::Com::CoUninitialize;
+use windows_sys::Win32::System::Com::CLSCTX_LOCAL_SERVER;
+use windows_sys::Win32::System::Com::COINIT_APARTMENTTHREADED;
+use windows_sys::Win32::System::Threading::GetProcessId;
+use windows_sys::Win32::UI::Shell::ApplicationActivationManager;
+use windows_sys::Win32::UI::Shell::ShellExecuteExW;
+use windows_sys::Win32::UI::Shell::AO_NONE;
+use windows_sys::Win32::UI::Shell::SEE_MASK_FLAG_NO_UI;
+use windows_sys::Win32::UI::Shell::SEE_MASK_NOCLOSEPROCESS;
+use windows_sys::Win32::UI::Shell::SHELLEXECUTEINFOW;
+use windows_sys::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+/// windows-sys doesn't include COM interfaces, so the IID and the vtable entries up to
+/// ActivateApplication are declared here
+const IID_IAPPLICATION_ACTIVATION_MANAGER: GUID =
+    GUID::from_u128(0x2e941141_7f97_4756_ba1d_9decde894a3d);
+
+#[repr(C)]
+struct IApplicationActivationManagerVtbl {
+    query_interface:
+        unsafe extern "system" fn(*mut c_void, *const GUID, *mut *mut c_void) -> HRESULT,
+    add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+    activate_application:
+        unsafe extern "system" fn(*mut c_void, *const u16, *const u16, i32, *mut u32) -> HRESULT,
+}
+
+fn wide(value: &OsStr) -> Vec<u16> {
+    value.encode_wide().chain(Some(0)).collect()
+}
+
+fn hresult_error(hresult: HRESULT) -> std::io::Error {
+    std::io::Error::from_raw_os_error(hresult)
+}
+
+/// Launches a packaged (UWP or Store) app by its application user model ID and returns the
+/// PID of the activated process
+pub fn activate_application(aumid: &str, arguments: &str) -> std::io::Result<u32> {
+    let aumid = wide(OsStr::new(aumid));
+    let arguments = wide(OsStr::new(arguments));
+
+    unsafe {
+        let initialized = CoInitializeEx(std::ptr::null(), COINIT_APARTMENTTHREADED as u32);
+        if initialized < 0 {
+            return Err(hresult_error(initialized));
+        }
+
+        let mut manager: *mut c_void = std::ptr::null_mut();
+        let created = CoCreateInstance(
+            &ApplicationActivationManager,
+            std::ptr::null_mut(),
+            CLSCTX_LOCAL_SERVER,
+            &IID_IAPPLICATION_ACTIVATION_MANAGER,
+            &mut manager,
+        );
+
+        let result = if created < 0 {
+            Err(hresult_error(created))
+        } else {
+            let vtbl = *(manager as *const *const IApplicationActivationManagerVtbl);
+            let mut pid = 0;
+            let activated = ((*vtbl).activate_application)(
+                manager,
+                aumid.as_ptr(),
+                arguments.as_ptr(),
+                AO_NONE,
+                &mut pid,
+            );
+
+            ((*vtbl).release)(manager);
+
+            if activated < 0 {
+                Err(hresult_error(activated))
+            } else {
+                Ok(pid)
+            }
+        };
+
+        CoUninitialize();
+        result
+    }
+}
+
+/// Opens a file through the shell, which resolves shell links (.lnk) to their targets, and
+/// returns the PID of the launched process
+pub fn open_with_shell(
+    path: &Path,
+    arguments: &str,
+    working_directory: Option<&Path>,
+) -> std::io::Result<u32> {
+    let verb = wide(OsStr::new("open"));
+    let file = wide(path.as_os_str());
+    let parameters = wide(OsStr::new(arguments));
+    let directory = working_directory.map(|directory| wide(directory.as_os_str()));
+
+    unsafe {
+        let mut info = std::mem::zeroed::<SHELLEXECUTEINFOW>();
+        info.cbSize = size_of::<SHELLEXECUTEINFOW>() as u32;
+        info.fMask = SEE_MASK_NOCLOSEPROCESS | SEE_MASK_FLAG_NO_UI;
+        info.lpVerb = verb.as_ptr();
+        info.lpFile = file.as_ptr();
+        info.lpParameters = parameters.as_ptr();
+        info.lpDirectory = directory
+            .as_ref()
+            .map_or(std::ptr::null(), |directory| directory.as_ptr());
+        info.nShow = SW_SHOWNORMAL;
+
+        if ShellExecuteExW(&mut info) == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // the shell doesn't return a process handle when the target was handed off to an
+        // already running process, e.g. through DDE or app activation
+        if info.hProcess.is_null() {
+            return Err(std::io::Error::other(format!(
+                "{} did not start a new process",
+                path.display()
+            )));
+        }
+
+        let pid = GetProcessId(info.hProcess);
+        CloseHandle(info.hProcess);
+
+        if pid == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(pid)
+    }
+}
@@ -0,0 +1,34 @@
+use chrono::DateTime;
+use chrono::Utc;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// Source of the timestamps recorded for units and their events, which can be replaced so that
+/// tests don't depend on the time they run at
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The clock of the system, which is used unless another clock is set
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+static CLOCK: RwLock<Option<Arc<dyn Clock>>> = RwLock::new(None);
+
+/// Replaces the clock for the rest of the process
+pub fn set_clock(clock: Arc<dyn Clock>) {
+    *CLOCK.write() = Some(clock);
+}
+
+/// The current time according to the clock
+pub fn now() -> DateTime<Utc> {
+    CLOCK
+        .read()
+        .as_ref()
+        .map_or_else(Utc::now, |clock| clock.now())
+}
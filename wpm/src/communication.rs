@@ -1,12 +1,55 @@
+use crate::SocketEnvelope;
 use crate::SocketMessage;
+use crate::PROTOCOL_VERSION;
 use interprocess::local_socket::traits::Stream as StreamExt;
 use interprocess::local_socket::GenericNamespaced;
 use interprocess::local_socket::Stream;
 use interprocess::local_socket::ToNsName;
 use std::io::Write;
+use std::sync::OnceLock;
+
+/// Name of the socket wpmd listens on when no other name is configured
+pub const DEFAULT_SOCKET_NAME: &str = "wpmd.sock";
+
+static SOCKET_NAME: OnceLock<String> = OnceLock::new();
+
+/// Sets the name of the socket of the wpmd instance to talk to, which must happen before any
+/// messages are sent and can only be done once
+pub fn set_socket_name(name: &str) {
+    if SOCKET_NAME.set(name.to_string()).is_err() {
+        tracing::warn!("the socket name has already been set");
+    }
+}
+
+/// Name of the socket wpmd listens on
+pub fn daemon_socket() -> &'static str {
+    SOCKET_NAME
+        .get()
+        .map_or(DEFAULT_SOCKET_NAME, String::as_str)
+}
+
+/// Name of the socket wpmd answers clients on which don't send the name of their own socket,
+/// which is derived from the daemon socket so that clients of different wpmd instances never
+/// receive each other's responses
+pub fn client_socket() -> String {
+    match daemon_socket() {
+        DEFAULT_SOCKET_NAME => String::from("wpmctl.sock"),
+        name => format!("wpmctl.{name}"),
+    }
+}
+
+/// Name of the socket this process listens on for responses from wpmd, which is unique to the
+/// process so that concurrent clients never receive each other's responses
+pub fn reply_socket() -> String {
+    format!("{}.{}", client_socket(), std::process::id())
+}
 
 pub fn send_message(to: &str, message: SocketMessage) -> Result<(), std::io::Error> {
-    let json = serde_json::to_string(&message)?;
+    let json = serde_json::to_string(&SocketEnvelope {
+        protocol_version: PROTOCOL_VERSION,
+        message,
+        reply_to: Some(reply_socket()),
+    })?;
     let name = to.to_ns_name::<GenericNamespaced>()?;
     let connection = Stream::connect(name)?;
     let (_, mut sender) = connection.split();
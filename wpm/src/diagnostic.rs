@@ -0,0 +1,95 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A unit file parse failure with the location of the error and the offending source line
+#[derive(Debug, Clone)]
+pub struct ParseDiagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub source_line: String,
+    pub span_len: usize,
+}
+
+impl ParseDiagnostic {
+    pub fn from_toml(path: &Path, source: &str, error: &toml::de::Error) -> Self {
+        let span = error.span().unwrap_or(0..0);
+        Self::new(path, source, span, error.message().trim())
+    }
+
+    pub fn from_json(path: &Path, source: &str, error: &serde_json::Error) -> Self {
+        let line_start = source
+            .split_inclusive('\n')
+            .take(error.line().saturating_sub(1))
+            .map(str::len)
+            .sum::<usize>();
+
+        let offset = line_start + error.column().saturating_sub(1);
+
+        // serde_json appends the position to its messages, which is shown separately here
+        let message = error.to_string();
+        let suffix = format!(" at line {} column {}", error.line(), error.column());
+        let message = message.strip_suffix(&suffix).unwrap_or(&message);
+
+        Self::new(path, source, offset..offset + 1, message)
+    }
+
+    fn new(path: &Path, source: &str, span: Range<usize>, message: &str) -> Self {
+        let start = floor_char_boundary(source, span.start);
+
+        let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |index| start + index);
+
+        let end = floor_char_boundary(source, span.end.clamp(start, line_end));
+
+        Self {
+            path: path.to_path_buf(),
+            line: source[..start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+            message: message.to_string(),
+            source_line: source[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_string(),
+            span_len: source[start..end].chars().count().max(1),
+        }
+    }
+}
+
+fn floor_char_boundary(source: &str, index: usize) -> usize {
+    let mut index = index.min(source.len());
+    while !source.is_char_boundary(index) {
+        index -= 1;
+    }
+
+    index
+}
+
+impl Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        writeln!(
+            f,
+            "{}:{}:{}: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message
+        )?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line_number} | {}", self.source_line)?;
+        write!(
+            f,
+            "{gutter} | {}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(self.span_len)
+        )
+    }
+}
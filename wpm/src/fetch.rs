@@ -0,0 +1,224 @@
+use crate::process_manager::ProcessManagerError;
+use crate::reqwest_client;
+use crate::unit::redacted_url;
+use crate::unit::store_ref_for_url;
+use crate::unit::Definition;
+use crate::unit::Executable;
+use parking_lot::Mutex;
+use parking_lot::RwLock;
+use reqwest::blocking::Response;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
+use url::Url;
+
+/// Maximum number of downloads running at the same time while prefetching units
+const MAX_CONCURRENT_DOWNLOADS: usize = 4;
+
+static DOWNLOAD_POLICY: OnceLock<DownloadPolicy> = OnceLock::new();
+
+/// Timeouts and retries applied to every download of a remote executable or resource
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadPolicy {
+    /// Time allowed to establish a connection (WPM_DOWNLOAD_CONNECT_TIMEOUT_SEC, default: 10s)
+    pub connect_timeout: Duration,
+    /// Time allowed for a whole request, including reading the body (WPM_DOWNLOAD_TIMEOUT_SEC, default: 300s)
+    pub timeout: Duration,
+    /// Number of retries after a failed attempt (WPM_DOWNLOAD_RETRIES, default: 3)
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every following retry (WPM_DOWNLOAD_BACKOFF_SEC, default: 2s)
+    pub backoff: Duration,
+}
+
+impl DownloadPolicy {
+    fn from_env() -> Self {
+        fn var(name: &str, default: u64) -> u64 {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        }
+
+        Self {
+            connect_timeout: Duration::from_secs(var("WPM_DOWNLOAD_CONNECT_TIMEOUT_SEC", 10)),
+            timeout: Duration::from_secs(var("WPM_DOWNLOAD_TIMEOUT_SEC", 300)),
+            retries: var("WPM_DOWNLOAD_RETRIES", 3) as u32,
+            backoff: Duration::from_secs(var("WPM_DOWNLOAD_BACKOFF_SEC", 2)),
+        }
+    }
+}
+
+pub fn download_policy() -> &'static DownloadPolicy {
+    DOWNLOAD_POLICY.get_or_init(DownloadPolicy::from_env)
+}
+
+fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error.is_request()
+        || error.is_body()
+        || error
+            .status()
+            .is_some_and(|status| status.is_server_error() || status.as_u16() == 429)
+}
+
+/// Downloads the contents of remote executables, resources and unit files, which can be
+/// replaced to fetch them through an artifact proxy or to serve fakes in tests
+pub trait Fetcher: Send + Sync {
+    fn fetch(&self, url: &Url) -> Result<Vec<u8>, ProcessManagerError>;
+}
+
+/// Sends a GET request with the shared reqwest client, retrying transient failures (timeouts,
+/// connection errors and server errors) with an exponential backoff according to the
+/// [`DownloadPolicy`]
+pub struct ReqwestFetcher;
+
+impl Fetcher for ReqwestFetcher {
+    fn fetch(&self, url: &Url) -> Result<Vec<u8>, ProcessManagerError> {
+        let policy = download_policy();
+        let mut delay = policy.backoff;
+        let mut attempt = 0;
+
+        loop {
+            let result = reqwest_client()
+                .get(url.to_string())
+                .send()
+                .and_then(Response::error_for_status)
+                .and_then(Response::bytes)
+                // the url is logged without the query and credentials which may hold secrets
+                .map_err(reqwest::Error::without_url);
+
+            match result {
+                Ok(bytes) => return Ok(bytes.to_vec()),
+                Err(error) if attempt < policy.retries && is_transient(&error) => {
+                    attempt += 1;
+                    tracing::warn!(
+                        "failed to download {}, retrying in {}s ({attempt}/{}): {error}",
+                        redacted_url(url),
+                        delay.as_secs(),
+                        policy.retries
+                    );
+
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+}
+
+static FETCHER: RwLock<Option<Arc<dyn Fetcher>>> = RwLock::new(None);
+
+/// Replaces the fetcher used for every download for the rest of the process
+pub fn set_fetcher(fetcher: Arc<dyn Fetcher>) {
+    *FETCHER.write() = Some(fetcher);
+}
+
+/// Downloads the contents of the given url with the configured [`Fetcher`], which is the
+/// [`ReqwestFetcher`] unless another one has been set
+pub fn fetch_bytes(url: &Url) -> Result<Vec<u8>, ProcessManagerError> {
+    let fetcher = FETCHER.read().clone();
+
+    match fetcher {
+        Some(fetcher) => fetcher.fetch(url),
+        None => ReqwestFetcher.fetch(url),
+    }
+}
+
+enum Download {
+    Resource { url: Url, path: PathBuf },
+    Executable(Executable),
+}
+
+impl Download {
+    /// The URL of the download as it is written to logs
+    fn url(&self) -> String {
+        match self {
+            Download::Resource { url, .. } => redacted_url(url).to_string(),
+            Download::Executable(Executable::Remote(remote)) => {
+                redacted_url(&remote.url).to_string()
+            }
+            Download::Executable(executable) => executable.to_string(),
+        }
+    }
+
+    fn fetch(&self) -> Result<(), ProcessManagerError> {
+        match self {
+            Download::Resource { url, path } => download_resource(url, path),
+            Download::Executable(executable) => executable.download_remote_executable(),
+        }
+    }
+}
+
+/// Downloads a remote resource to its location in the store
+pub fn download_resource(url: &Url, path: &Path) -> Result<(), ProcessManagerError> {
+    let bytes = fetch_bytes(url)?;
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Downloads every remote executable and resource referenced by the given definitions
+/// which isn't already in the store, using a bounded number of concurrent downloads.
+///
+/// Failures are logged and otherwise ignored here; they surface again when the
+/// individual definitions try to resolve their executables and resources.
+pub fn prefetch(definitions: &[Definition]) {
+    let mut seen = HashSet::new();
+    let mut downloads = vec![];
+
+    for definition in definitions {
+        for (_, url) in definition.resources.iter().flatten() {
+            if let Ok(path) = store_ref_for_url(url) {
+                if !path.is_file() && seen.insert(path.clone()) {
+                    downloads.push(Download::Resource {
+                        url: url.clone(),
+                        path,
+                    });
+                }
+            }
+        }
+
+        // scoop is not safe to run concurrently, so scoop executables are still installed
+        // one at a time when each definition is loaded
+        let executable = &definition.service.exec_start.executable;
+        if matches!(executable, Executable::Remote(_)) {
+            if let Ok(path) = executable.cached_executable_path() {
+                if !path.is_file() && seen.insert(path) {
+                    downloads.push(Download::Executable(executable.clone()));
+                }
+            }
+        }
+    }
+
+    if downloads.is_empty() {
+        return;
+    }
+
+    tracing::info!(
+        "prefetching {} remote executables and resources",
+        downloads.len()
+    );
+
+    let workers = downloads.len().min(MAX_CONCURRENT_DOWNLOADS);
+    let queue = Mutex::new(downloads);
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let Some(download) = queue.lock().pop() else {
+                    break;
+                };
+
+                let url = download.url();
+                match download.fetch() {
+                    Ok(()) => tracing::info!("downloaded {url}"),
+                    Err(error) => tracing::warn!("failed to download {url}: {error}"),
+                }
+            });
+        }
+    });
+}
@@ -1,7 +1,10 @@
+use crate::migration::CURRENT_FORMAT_VERSION;
+use crate::process_manager::ProcessManagerError;
 use crate::unit::CommandHealthcheck;
 use crate::unit::Definition;
 use crate::unit::Executable;
 use crate::unit::Healthcheck;
+use crate::unit::OnExistingProcess;
 use crate::unit::ProcessHealthcheck;
 use crate::unit::RemoteExecutable;
 use crate::unit::RestartStrategy;
@@ -11,26 +14,138 @@ use crate::unit::Service;
 use crate::unit::ServiceCommand;
 use crate::unit::ServiceKind;
 use crate::unit::Unit;
+use regex::NoExpand;
+use regex::Regex;
 use schemars::schema_for;
+use std::fmt::Display;
+use std::fmt::Formatter;
 use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::OnceLock;
 use url::Url;
 
+/// File format of generated example units
+#[derive(Debug, Clone, Copy)]
+pub enum ExampleFormat {
+    Json,
+    Toml,
+}
+
+impl Display for ExampleFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExampleFormat::Json => write!(f, "json"),
+            ExampleFormat::Toml => write!(f, "toml"),
+        }
+    }
+}
+
+/// URL of the published JSON Schema for wpm units
+pub const SCHEMA_URL: &str =
+    "https://raw.githubusercontent.com/LGUG2Z/wpm/refs/heads/master/schema.unit.json";
+
+static SCHEMA_FIELD: OnceLock<Regex> = OnceLock::new();
+
+/// Points the contents of a unit file at the published JSON Schema, with a `$schema` field in
+/// JSON unit files and a taplo directive at the top of TOML unit files, replacing any existing
+/// pointer and leaving the rest of the file as it is, including its line endings
+pub fn with_schema_pointer(contents: &str, format: ExampleFormat) -> String {
+    let newline = if contents.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    match format {
+        ExampleFormat::Json => {
+            let field = format!("\"$schema\": \"{SCHEMA_URL}\"");
+            let pattern = SCHEMA_FIELD.get_or_init(|| {
+                Regex::new(r#""\$schema"\s*:\s*(?:null|"(?:[^"\\]|\\.)*")"#).unwrap()
+            });
+
+            if pattern.is_match(contents) {
+                return pattern.replace(contents, NoExpand(&field)).into_owned();
+            }
+
+            let Some(start) = contents.find('{') else {
+                return contents.to_string();
+            };
+
+            // the field is indented like the first field of the unit file
+            let (head, tail) = contents.split_at(start + 1);
+            let indent = tail
+                .lines()
+                .skip(1)
+                .find(|line| !line.trim().is_empty())
+                .map(|line| &line[..line.len() - line.trim_start().len()])
+                .unwrap_or("  ");
+
+            format!("{head}{newline}{indent}{field},{tail}")
+        }
+        ExampleFormat::Toml => {
+            let directive = format!("#:schema {SCHEMA_URL}");
+            let mut lines = contents.lines().collect::<Vec<_>>();
+
+            match lines.iter().position(|line| line.starts_with("#:schema")) {
+                Some(index) => lines[index] = &directive,
+                None if lines.is_empty() => lines.push(&directive),
+                None => {
+                    lines.insert(0, "");
+                    lines.insert(0, &directive);
+                }
+            }
+
+            let mut formatted = lines.join(newline);
+            if contents.ends_with('\n') || contents.is_empty() {
+                formatted.push_str(newline);
+            }
+
+            formatted
+        }
+    }
+}
+
 impl Definition {
     pub fn schemagen() -> String {
         let schema = schema_for!(Self);
         serde_json::to_string_pretty(&schema).unwrap()
     }
 
-    pub fn examplegen(path: Option<PathBuf>) {
-        let examples = vec![
+    /// Generates a taplo configuration which associates TOML unit files with the JSON Schema,
+    /// to be saved as .taplo.toml in a unit directory
+    pub fn taplogen() -> String {
+        format!(
+            r#"# taplo configuration for wpm unit files: https://taplo.tamasfe.dev/configuration/file.html
+include = ["*.toml"]
+exclude = ["taplo.toml", ".taplo.toml"]
+
+[[rule]]
+include = ["*.toml"]
+exclude = ["taplo.toml", ".taplo.toml"]
+
+[rule.schema]
+url = "{SCHEMA_URL}"
+"#
+        )
+    }
+
+    /// Starter unit definitions for common tools
+    pub fn examples() -> Vec<Self> {
+        vec![
             Self {
                 schema: None,
+                format_version: Some(CURRENT_FORMAT_VERSION),
+                deprecations: vec![],
+                path: None,
+                socket: None,
                 unit: Unit {
                     name: "kanata".to_string(),
                     description: Some("Software keyboard remapper".to_string()),
+                    documentation: None,
                     requires: None,
+                    requires_windows_service: None,
+                    restart_dependents: false,
                 },
                 resources: Some(
                     [(
@@ -58,6 +173,9 @@ impl Definition {
                         environment: None,
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     },
                     environment: None,
                     environment_file: None,
@@ -65,19 +183,42 @@ impl Definition {
                     healthcheck: Some(Healthcheck::default()),
                     restart: Default::default(),
                     restart_sec: None,
+                    reset_sec: None,
+                    start_retry_sec: None,
+                    restart_on_memory_above: None,
+                    alert_on_cpu_above: None,
+                    kill_on_daemon_exit: false,
+                    on_existing_process: OnExistingProcess::Ignore,
+                    pass_environment: None,
+                    clean_environment: false,
+                    output_encoding: None,
+                    strip_ansi: false,
+                    resolve_shims: false,
+                    sensitive: None,
+                    script: None,
+                    script_kind: None,
                     exec_stop: None,
                     exec_stop_post: None,
                     autostart: false,
+                    autostart_priority: None,
+                    autostart_on: None,
                     exec_start_pre: None,
                     exec_start_post: None,
                 },
             },
             Self {
                 schema: None,
+                format_version: Some(CURRENT_FORMAT_VERSION),
+                deprecations: vec![],
+                path: None,
+                socket: None,
                 unit: Unit {
                     name: "komorebi-bar".to_string(),
                     description: Some("Status bar for komorebi".to_string()),
+                    documentation: None,
                     requires: Some(vec!["komorebi".to_string()]),
+                    requires_windows_service: None,
+                    restart_dependents: false,
                 },
                 resources: Some(
                     [(
@@ -107,14 +248,33 @@ impl Definition {
                         environment: None,
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     },
                     working_directory: None,
                     healthcheck: Some(Healthcheck::default()),
                     restart: Default::default(),
                     restart_sec: None,
+                    reset_sec: None,
+                    start_retry_sec: None,
+                    restart_on_memory_above: None,
+                    alert_on_cpu_above: None,
+                    kill_on_daemon_exit: false,
+                    on_existing_process: OnExistingProcess::Ignore,
+                    pass_environment: None,
+                    clean_environment: false,
+                    output_encoding: None,
+                    strip_ansi: false,
+                    resolve_shims: false,
+                    sensitive: None,
+                    script: None,
+                    script_kind: None,
                     exec_stop: None,
                     exec_stop_post: None,
                     autostart: false,
+                    autostart_priority: None,
+                    autostart_on: None,
                     exec_start_pre: None,
                     exec_start_post: None,
                     environment_file: None,
@@ -122,10 +282,17 @@ impl Definition {
             },
             Self {
                 schema: None,
+                format_version: Some(CURRENT_FORMAT_VERSION),
+                deprecations: vec![],
+                path: None,
+                socket: None,
                 unit: Unit {
                     name: "komorebi".to_string(),
                     description: Some("Tiling window management for Windows".to_string()),
+                    documentation: None,
                     requires: Some(vec!["whkd".to_string(), "kanata".to_string()]),
+                    requires_windows_service: None,
+                    restart_dependents: false,
                 },
                 resources: Some(
                     [(
@@ -154,6 +321,9 @@ impl Definition {
                         )]),
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     },
                     environment: None,
                     environment_file: None,
@@ -167,12 +337,29 @@ impl Definition {
                     })),
                     restart: Default::default(),
                     restart_sec: None,
+                    reset_sec: None,
+                    start_retry_sec: None,
+                    restart_on_memory_above: None,
+                    alert_on_cpu_above: None,
+                    kill_on_daemon_exit: false,
+                    on_existing_process: OnExistingProcess::Ignore,
+                    pass_environment: None,
+                    clean_environment: false,
+                    output_encoding: None,
+                    strip_ansi: false,
+                    resolve_shims: false,
+                    sensitive: None,
+                    script: None,
+                    script_kind: None,
                     exec_stop: Some(vec![ServiceCommand {
                         executable: Executable::Local(PathBuf::from("komorebic.exe")),
                         arguments: Some(vec!["stop".to_string()]),
                         environment: None,
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     }]),
                     exec_stop_post: Some(vec![ServiceCommand {
                         executable: Executable::Local(PathBuf::from("komorebic.exe")),
@@ -180,24 +367,39 @@ impl Definition {
                         environment: None,
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     }]),
                     autostart: false,
+                    autostart_priority: None,
+                    autostart_on: None,
                     exec_start_pre: Some(vec![ServiceCommand {
                         executable: Executable::Local(PathBuf::from("komorebic.exe")),
                         arguments: Some(vec!["fetch-asc".to_string()]),
                         environment: None,
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     }]),
                     exec_start_post: None,
                 },
             },
             Self {
                 schema: None,
+                format_version: Some(CURRENT_FORMAT_VERSION),
+                deprecations: vec![],
+                path: None,
+                socket: None,
                 unit: Unit {
                     name: "whkd".to_string(),
                     description: Some("Simple hotkey daemon for Windows".to_string()),
+                    documentation: None,
                     requires: None,
+                    requires_windows_service: None,
+                    restart_dependents: false,
                 },
                 resources: Some(
                     [(
@@ -223,6 +425,9 @@ impl Definition {
                         environment: None,
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     },
                     environment: None,
                     environment_file: None,
@@ -230,19 +435,42 @@ impl Definition {
                     healthcheck: Some(Healthcheck::default()),
                     restart: RestartStrategy::OnFailure,
                     restart_sec: Some(2),
+                    reset_sec: None,
+                    start_retry_sec: None,
+                    restart_on_memory_above: None,
+                    alert_on_cpu_above: None,
+                    kill_on_daemon_exit: false,
+                    on_existing_process: OnExistingProcess::Ignore,
+                    pass_environment: None,
+                    clean_environment: false,
+                    output_encoding: None,
+                    strip_ansi: false,
+                    resolve_shims: false,
+                    sensitive: None,
+                    script: None,
+                    script_kind: None,
                     exec_stop: None,
                     exec_stop_post: None,
                     autostart: false,
+                    autostart_priority: None,
+                    autostart_on: None,
                     exec_start_pre: None,
                     exec_start_post: None,
                 },
             },
             Self {
                 schema: None,
+                format_version: Some(CURRENT_FORMAT_VERSION),
+                deprecations: vec![],
+                path: None,
+                socket: None,
                 unit: Unit {
                     name: "mousemaster".to_string(),
                     description: Some("A keyboard driven interface for mouseless mouse manipulation".to_string()),
+                    documentation: None,
                     requires: Some(vec!["whkd".to_string(), "kanata".to_string()]),
+                    requires_windows_service: None,
+                    restart_dependents: false,
                 },
                 resources: Some(
                     [(
@@ -257,7 +485,8 @@ impl Definition {
                     exec_start: ServiceCommand {
                         executable: Executable::Remote(RemoteExecutable {
                             url: Url::from_str("https://github.com/petoncle/mousemaster/releases/download/73/mousemaster.exe").unwrap(),
-                            hash: "55009596854109e0e7fb6ded3f5a1098e4ab211bed8e3d975d81c4bd8a849aa5".to_string()
+                            hash: "55009596854109e0e7fb6ded3f5a1098e4ab211bed8e3d975d81c4bd8a849aa5".to_string(),
+                            verify_on_start: None,
                         }),
                         arguments: Some(vec![
                             "--configuration-file={{ Resources.CONFIGURATION_FILE }}".to_string(),
@@ -266,29 +495,58 @@ impl Definition {
                         environment: None,
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     },
                     environment: None,
                     environment_file: None,
                     working_directory: None,
                     healthcheck: Some(Healthcheck::Process(ProcessHealthcheck {
                         target: None,
+                        pid_file: None,
+                        command_line: None,
+                        match_parent: false,
                         delay_sec: 2,
                     })),
                     restart: RestartStrategy::OnFailure,
                     restart_sec: Some(2),
+                    reset_sec: None,
+                    start_retry_sec: None,
+                    restart_on_memory_above: None,
+                    alert_on_cpu_above: None,
+                    kill_on_daemon_exit: false,
+                    on_existing_process: OnExistingProcess::Ignore,
+                    pass_environment: None,
+                    clean_environment: false,
+                    output_encoding: None,
+                    strip_ansi: false,
+                    resolve_shims: false,
+                    sensitive: None,
+                    script: None,
+                    script_kind: None,
                     exec_stop: None,
                     exec_stop_post: None,
                     autostart: false,
+                    autostart_priority: None,
+                    autostart_on: None,
                     exec_start_pre: None,
                     exec_start_post: None,
                 },
             },
             Self {
                 schema: None,
+                format_version: Some(CURRENT_FORMAT_VERSION),
+                deprecations: vec![],
+                path: None,
+                socket: None,
                 unit: Unit {
                     name: "komokana".to_string(),
                     description: Some("Automatic application-aware keyboard layer switching for Windows".to_string()),
+                    documentation: None,
                     requires: Some(vec!["komorebi".to_string(), "kanata".to_string()]),
+                    requires_windows_service: None,
+                    restart_dependents: false,
                 },
                 resources: Some(
                     [(
@@ -318,6 +576,9 @@ impl Definition {
                         environment: None,
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     },
                     environment: None,
                     environment_file: None,
@@ -325,19 +586,42 @@ impl Definition {
                     healthcheck: Some(Healthcheck::default()),
                     restart: RestartStrategy::OnFailure,
                     restart_sec: Some(2),
+                    reset_sec: None,
+                    start_retry_sec: None,
+                    restart_on_memory_above: None,
+                    alert_on_cpu_above: None,
+                    kill_on_daemon_exit: false,
+                    on_existing_process: OnExistingProcess::Ignore,
+                    pass_environment: None,
+                    clean_environment: false,
+                    output_encoding: None,
+                    strip_ansi: false,
+                    resolve_shims: false,
+                    sensitive: None,
+                    script: None,
+                    script_kind: None,
                     exec_stop: None,
                     exec_stop_post: None,
                     autostart: false,
+                    autostart_priority: None,
+                    autostart_on: None,
                     exec_start_pre: None,
                     exec_start_post: None,
                 },
             },
             Self {
                 schema: None,
+                format_version: Some(CURRENT_FORMAT_VERSION),
+                deprecations: vec![],
+                path: None,
+                socket: None,
                 unit: Unit {
                     name: "desktop".to_string(),
                     description: Some("Everything I need to work on Windows".to_string()),
+                    documentation: None,
                     requires: Some(vec!["komorebi".to_string(), "komorebi-bar".to_string(), "mousemaster".to_string()]),
+                    requires_windows_service: None,
+                    restart_dependents: false,
                 },
                 resources: None,
                 service: Service {
@@ -351,6 +635,9 @@ impl Definition {
                         environment: None,
                         environment_file: None,
                         retry_limit: None,
+                        timeout_sec: None,
+                        shell: None,
+                        shell_kind: None,
                     },
                     environment: None,
                     environment_file: None,
@@ -358,52 +645,116 @@ impl Definition {
                     healthcheck: None,
                     restart: Default::default(),
                     restart_sec: None,
+                    reset_sec: None,
+                    start_retry_sec: None,
+                    restart_on_memory_above: None,
+                    alert_on_cpu_above: None,
+                    kill_on_daemon_exit: false,
+                    on_existing_process: OnExistingProcess::Ignore,
+                    pass_environment: None,
+                    clean_environment: false,
+                    output_encoding: None,
+                    strip_ansi: false,
+                    resolve_shims: false,
+                    sensitive: None,
+                    script: None,
+                    script_kind: None,
                     exec_stop: None,
                     exec_stop_post: None,
                     autostart: false,
+                    autostart_priority: None,
+                    autostart_on: None,
                     exec_start_pre: None,
                     exec_start_post: None,
                 },
             },
-        ];
+        ]
+    }
+
+    /// Returns the example units with the given names, or all example units if no names are given
+    pub fn named_examples(names: &[String]) -> Result<Vec<Self>, ProcessManagerError> {
+        let examples = Self::examples();
+
+        if names.is_empty() {
+            return Ok(examples);
+        }
+
+        let mut selected = vec![];
+        for name in names {
+            match examples.iter().find(|example| &example.unit.name == name) {
+                Some(example) => selected.push(example.clone()),
+                None => {
+                    let available = examples
+                        .iter()
+                        .map(|example| example.unit.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+
+                    return Err(ProcessManagerError::UnknownExample {
+                        name: name.clone(),
+                        available,
+                    });
+                }
+            }
+        }
+
+        Ok(selected)
+    }
+
+    /// Writes this definition to a unit file named after the unit in the given directory
+    pub fn write_example(
+        &self,
+        directory: &Path,
+        format: ExampleFormat,
+    ) -> std::io::Result<PathBuf> {
+        let path = directory.join(format!("{}.{format}", self.unit.name));
+
+        let contents = match format {
+            ExampleFormat::Json => {
+                let mut example = self.clone();
+                example.schema = Some(SCHEMA_URL.to_string());
+                serde_json::to_string_pretty(&example)?
+            }
+            ExampleFormat::Toml => with_schema_pointer(
+                &toml::to_string_pretty(self).map_err(std::io::Error::other)?,
+                ExampleFormat::Toml,
+            ),
+        };
+
+        std::fs::write(&path, contents)?;
+
+        Ok(path)
+    }
+
+    /// Writes the example units with the given names, or all of them, as JSON to the given
+    /// directory, or as JSON and TOML to the examples directory
+    pub fn examplegen(path: Option<PathBuf>, names: &[String]) -> Result<(), ProcessManagerError> {
+        let examples = Self::named_examples(names)?;
 
         let formats = if path.is_some() {
-            vec!["json"]
+            vec![ExampleFormat::Json]
         } else {
-            vec!["json", "toml"]
+            vec![ExampleFormat::Json, ExampleFormat::Toml]
         };
 
         for format in formats {
             let parent = if let Some(path) = &path {
                 path
             } else {
-                &Path::new("examples").join(format)
+                &Path::new("examples").join(format.to_string())
             };
 
-            std::fs::create_dir_all(parent).unwrap();
+            std::fs::create_dir_all(parent)?;
 
             for example in &examples {
-                match format {
-                    "json" => {
-                        let mut example = example.clone();
-                        example.schema = Some("https://raw.githubusercontent.com/LGUG2Z/wpm/refs/heads/master/schema.unit.json".to_string());
-
-                        std::fs::write(
-                            parent.join(format!("{}.json", example.unit.name)),
-                            serde_json::to_string_pretty(&example).unwrap(),
-                        )
-                        .unwrap();
-                    }
-                    "toml" => {
-                        std::fs::write(
-                            parent.join(format!("{}.toml", example.unit.name)),
-                            toml::to_string_pretty(&example).unwrap(),
-                        )
-                        .unwrap();
-                    }
-                    _ => {}
-                }
+                example.write_example(parent, format)?;
+            }
+
+            if matches!(format, ExampleFormat::Toml) {
+                std::fs::write(parent.join(".taplo.toml"), Self::taplogen())?;
             }
         }
+
+        Ok(())
     }
 }
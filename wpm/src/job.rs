@@ -0,0 +1,81 @@
+use std::ffi::c_void;
+use std::sync::OnceLock;
+use windows_sys::Win32::Foundation::CloseHandle;
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::JobObjects::AssignProcessToJobObject;
+use windows_sys::Win32::System::JobObjects::CreateJobObjectW;
+use windows_sys::Win32::System::JobObjects::JobObjectExtendedLimitInformation;
+use windows_sys::Win32::System::JobObjects::SetInformationJobObject;
+use windows_sys::Win32::System::JobObjects::JOBOBJECT_EXTENDED_LIMIT_INFORMATION;
+use windows_sys::Win32::System::JobObjects::JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+use windows_sys::Win32::System::Threading::OpenProcess;
+use windows_sys::Win32::System::Threading::PROCESS_SET_QUOTA;
+use windows_sys::Win32::System::Threading::PROCESS_TERMINATE;
+
+/// Handle of the job object which kills its processes when it is closed, stored as an integer
+/// because raw handles can't be shared between threads
+static KILL_ON_CLOSE_JOB: OnceLock<Option<usize>> = OnceLock::new();
+
+/// The job object handle is never closed, so the system only closes it when wpmd exits,
+/// whether that is a clean shutdown or a crash
+fn kill_on_close_job() -> Option<HANDLE> {
+    KILL_ON_CLOSE_JOB
+        .get_or_init(|| unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job.is_null() {
+                tracing::error!(
+                    "failed to create job object: {}",
+                    std::io::Error::last_os_error()
+                );
+
+                return None;
+            }
+
+            let mut info = std::mem::zeroed::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>();
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+
+            let configured = SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                &info as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION as *const c_void,
+                size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            );
+
+            if configured == 0 {
+                tracing::error!(
+                    "failed to configure job object: {}",
+                    std::io::Error::last_os_error()
+                );
+
+                CloseHandle(job);
+                return None;
+            }
+
+            Some(job as usize)
+        })
+        .map(|job| job as HANDLE)
+}
+
+/// Assigns a process to a job object which kills it when wpmd exits, along with any processes
+/// it spawns after being assigned
+pub fn kill_on_daemon_exit(pid: u32) -> std::io::Result<()> {
+    let job =
+        kill_on_close_job().ok_or_else(|| std::io::Error::other("no job object available"))?;
+
+    unsafe {
+        let process = OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid);
+        if process.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let assigned = AssignProcessToJobObject(job, process);
+        let error = std::io::Error::last_os_error();
+        CloseHandle(process);
+
+        if assigned == 0 {
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
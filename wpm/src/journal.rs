@@ -0,0 +1,140 @@
+use crate::clock;
+use crate::time_format;
+use crate::unit_status::ExitReason;
+use crate::unit_status::HealthcheckFailure;
+use chrono::DateTime;
+use chrono::Utc;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// Number of events kept for each unit, after which the oldest events are dropped
+const MAX_EVENTS_PER_UNIT: usize = 64;
+
+static JOURNAL: Mutex<BTreeMap<String, VecDeque<JournalEntry>>> = Mutex::new(BTreeMap::new());
+
+/// Something which happened to a unit and which helps to explain its current state
+#[derive(Debug, Clone)]
+pub enum UnitEvent {
+    StartRequested,
+    /// A required unit could not be started
+    DependencyFailed {
+        dependency: String,
+        error: String,
+    },
+    /// The unit could not be started before its healthcheck ran
+    StartFailed(String),
+    HealthcheckFailed(Option<HealthcheckFailure>),
+    /// A start attempt failed and the unit is started again after StartRetrySec
+    StartRetry {
+        attempt: u8,
+        attempts: u8,
+        delay_sec: u64,
+    },
+    Started(u32),
+    Adopted(u32),
+    Completed,
+    Exited(ExitReason),
+    RestartScheduled(u64),
+    /// An automatic restart was skipped because the restart budget is exhausted
+    RestartSuppressed,
+    /// The working set stayed above the RestartOnMemoryAbove threshold, in megabytes
+    MemoryThresholdExceeded {
+        megabytes: u64,
+        threshold: u64,
+    },
+    /// The CPU usage stayed above the AlertOnCpuAbove threshold, in percent
+    CpuThresholdExceeded {
+        percent: f32,
+        threshold: f32,
+    },
+    Stopped,
+    ForceStopped,
+    /// The unit left the Failed state once its ResetSec had elapsed
+    FailureReset,
+}
+
+impl Display for UnitEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnitEvent::StartRequested => write!(f, "start requested"),
+            UnitEvent::DependencyFailed { dependency, error } => {
+                write!(f, "required unit {dependency} failed to start: {error}")
+            }
+            UnitEvent::StartFailed(error) => write!(f, "failed to start: {error}"),
+            UnitEvent::HealthcheckFailed(None) => write!(f, "failed healthcheck"),
+            UnitEvent::HealthcheckFailed(Some(failure)) => {
+                write!(f, "failed healthcheck ({})", failure.summary())
+            }
+            UnitEvent::StartRetry {
+                attempt,
+                attempts,
+                delay_sec,
+            } => write!(
+                f,
+                "start attempt {attempt} of {attempts} failed, retrying in {delay_sec}s"
+            ),
+            UnitEvent::Started(pid) => write!(f, "started as process {pid}"),
+            UnitEvent::Adopted(pid) => write!(f, "adopted existing process {pid}"),
+            UnitEvent::Completed => write!(f, "completed successfully"),
+            UnitEvent::Exited(reason) => write!(f, "exited: {reason}"),
+            UnitEvent::RestartScheduled(seconds) => write!(f, "restart scheduled in {seconds}s"),
+            UnitEvent::RestartSuppressed => write!(
+                f,
+                "not restarted because automatic restarts are paused (restart budget exhausted)"
+            ),
+            UnitEvent::MemoryThresholdExceeded {
+                megabytes,
+                threshold,
+            } => write!(
+                f,
+                "working set of {megabytes}MB stayed above the threshold of {threshold}MB"
+            ),
+            UnitEvent::CpuThresholdExceeded { percent, threshold } => write!(
+                f,
+                "cpu usage of {percent:.0}% stayed above the threshold of {threshold}%"
+            ),
+            UnitEvent::Stopped => write!(f, "stopped"),
+            UnitEvent::ForceStopped => write!(f, "force stopped"),
+            UnitEvent::FailureReset => write!(f, "reset after failing"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub timestamp: DateTime<Utc>,
+    pub event: UnitEvent,
+}
+
+impl Display for JournalEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", time_format::display(self.timestamp), self.event)
+    }
+}
+
+/// Records an event for a unit
+pub fn record(name: &str, event: UnitEvent) {
+    let mut journal = JOURNAL.lock();
+    let entries = journal.entry(name.to_string()).or_default();
+
+    if entries.len() == MAX_EVENTS_PER_UNIT {
+        entries.pop_front();
+    }
+
+    entries.push_back(JournalEntry {
+        timestamp: clock::now(),
+        event,
+    });
+}
+
+/// The recorded events of a unit, oldest first
+pub fn entries(name: &str) -> Vec<JournalEntry> {
+    JOURNAL
+        .lock()
+        .get(name)
+        .map(|entries| entries.iter().cloned().collect())
+        .unwrap_or_default()
+}
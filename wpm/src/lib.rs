@@ -1,20 +1,51 @@
 #![warn(clippy::all)]
 
+use crate::fetch::download_policy;
 use regex::Regex;
-use serde::Deserialize;
-use serde::Serialize;
 use std::path::PathBuf;
 use std::sync::OnceLock;
 use tracing::warn;
 
+#[cfg(windows)]
+mod activation;
+pub mod clock;
 pub mod communication;
-mod generators;
+pub mod diagnostic;
+pub mod fetch;
+pub mod generators;
+#[cfg(windows)]
+mod job;
+pub mod journal;
+pub mod log_files;
+mod log_output;
+pub mod migration;
+pub mod platform;
 pub mod process_manager;
 mod process_manager_status;
+pub mod restart_budget;
+pub mod startup_slots;
+pub mod state_hooks;
+pub mod support_bundle;
+pub mod sync;
+pub mod time_format;
 pub mod unit;
 pub mod unit_status;
+pub mod usage_history;
+pub mod validation;
+#[cfg(windows)]
+mod windows_service;
+
+pub use wpm_protocol::DaemonDirectories;
+pub use wpm_protocol::RemoveOptions;
+pub use wpm_protocol::SocketEnvelope;
+pub use wpm_protocol::SocketMessage;
+pub use wpm_protocol::UnitAction;
+pub use wpm_protocol::PROTOCOL_VERSION;
 
 static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+static STORE_DIR: OnceLock<PathBuf> = OnceLock::new();
+static LOG_DIR: OnceLock<PathBuf> = OnceLock::new();
+static UNITS_DIR: OnceLock<PathBuf> = OnceLock::new();
 static REQWEST_CLIENT: OnceLock<reqwest::blocking::Client> = OnceLock::new();
 
 static RESOURCE_REGEX: OnceLock<Regex> = OnceLock::new();
@@ -25,60 +56,185 @@ pub fn resource_regex<'regex>() -> &'regex Regex {
 pub fn reqwest_client() -> reqwest::blocking::Client {
     REQWEST_CLIENT
         .get_or_init(|| {
+            let policy = download_policy();
             let builder = reqwest::blocking::Client::builder();
-            builder.user_agent("wpm").build().unwrap()
+            builder
+                .user_agent("wpm")
+                .connect_timeout(policy.connect_timeout)
+                .timeout(policy.timeout)
+                .build()
+                .unwrap()
         })
         .clone()
 }
 
-pub fn wpm_data_dir() -> PathBuf {
-    DATA_DIR
-        .get_or_init(|| {
-            let wpm_dir = dirs::data_local_dir()
-                .expect("could not find the system's local data dir")
-                .join("wpm");
+/// Removes ANSI escape sequences, which can end up in unit logs and in responses through unit
+/// log lines
+pub fn strip_ansi(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            output.push(c);
+            continue;
+        }
+
+        match chars.peek() {
+            // CSI sequences end with a byte in the range @ to ~
+            Some('[') => {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC sequences end with BEL or ST
+            Some(']') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                chars.next();
+            }
+        }
+    }
+
+    output
+}
 
-            std::fs::create_dir_all(&wpm_dir)
-                .expect("could not ensure creation of the wpm local data dir");
+/// Sets a directory which would otherwise be resolved when it is first used, creating it if it
+/// doesn't exist yet
+fn set_dir(lock: &OnceLock<PathBuf>, kind: &str, path: PathBuf) -> std::io::Result<()> {
+    std::fs::create_dir_all(&path)?;
 
-            let log_dir = wpm_dir.join("logs");
+    if let Err(path) = lock.set(path) {
+        if lock.get() != Some(&path) {
+            tracing::warn!("the {kind} directory has already been set");
+        }
+    }
 
-            std::fs::create_dir_all(&log_dir)
-                .expect("could not ensure creation of the wpm logs local data dir");
+    Ok(())
+}
 
-            let store_dir = wpm_dir.join("store");
+/// Resolves a directory from the path it was set to, then the given environment variable, and
+/// the default otherwise, creating it if it doesn't exist yet
+fn resolve_dir(
+    lock: &OnceLock<PathBuf>,
+    variable: &str,
+    default: impl FnOnce() -> std::io::Result<PathBuf>,
+) -> std::io::Result<PathBuf> {
+    if let Some(dir) = lock.get() {
+        return Ok(dir.clone());
+    }
+
+    let dir = match std::env::var_os(variable) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => default()?,
+    };
+
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(lock.get_or_init(|| dir).clone())
+}
 
-            std::fs::create_dir_all(&store_dir)
-                .expect("could not ensure creation of the wpm store local data dir");
+/// Overrides the data directory for the rest of the process, which takes precedence over
+/// `WPM_DATA_HOME`
+pub fn set_data_dir(path: PathBuf) -> std::io::Result<()> {
+    set_dir(&DATA_DIR, "data", path)
+}
 
-            wpm_dir
-        })
-        .clone()
+/// Overrides the store directory for the rest of the process, which takes precedence over
+/// `WPM_STORE_DIR`
+pub fn set_store_dir(path: PathBuf) -> std::io::Result<()> {
+    set_dir(&STORE_DIR, "store", path)
+}
+
+/// Overrides the log directory for the rest of the process, which takes precedence over
+/// `WPM_LOG_DIR`
+pub fn set_log_dir(path: PathBuf) -> std::io::Result<()> {
+    set_dir(&LOG_DIR, "log", path)
+}
+
+/// The directory wpm keeps its data in: `WPM_DATA_HOME` if it is set, and `wpm` in the local
+/// data directory of the system otherwise
+pub fn wpm_data_dir() -> std::io::Result<PathBuf> {
+    resolve_dir(&DATA_DIR, "WPM_DATA_HOME", || {
+        dirs::data_local_dir()
+            .map(|dir| dir.join("wpm"))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "could not find the system's local data dir",
+                )
+            })
+    })
 }
 
-pub fn wpm_store_dir() -> PathBuf {
-    wpm_data_dir().join("store")
+/// The directory remote executables and scripts are stored in: `WPM_STORE_DIR` if it is set,
+/// and `store` in the data directory otherwise
+pub fn wpm_store_dir() -> std::io::Result<PathBuf> {
+    resolve_dir(&STORE_DIR, "WPM_STORE_DIR", || {
+        Ok(wpm_data_dir()?.join("store"))
+    })
 }
 
-pub fn wpm_log_dir() -> PathBuf {
-    wpm_data_dir().join("logs")
+/// The directory unit logs are written to: `WPM_LOG_DIR` if it is set, and `logs` in the data
+/// directory otherwise
+pub fn wpm_log_dir() -> std::io::Result<PathBuf> {
+    resolve_dir(&LOG_DIR, "WPM_LOG_DIR", || Ok(wpm_data_dir()?.join("logs")))
 }
 
+pub fn wpm_snapshots_dir() -> std::io::Result<PathBuf> {
+    let dir = wpm_data_dir()?.join("snapshots");
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+/// Overrides the directory unit files are loaded from for the rest of the process, which must
+/// happen before the directory is first used
+pub fn set_units_dir(path: PathBuf) {
+    if UNITS_DIR.set(path).is_err() {
+        tracing::warn!("the unit directory has already been set");
+    }
+}
+
+/// The directory unit files are loaded from: the directory passed to `set_units_dir`, then
+/// `WPM_CONFIG_HOME` if it is set, and `~/.config/wpm` otherwise
 pub fn wpm_units_dir() -> PathBuf {
-    dirs::home_dir()
-        .expect("could not find home dir")
-        .join(".config")
-        .join("wpm")
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub enum SocketMessage {
-    Start(Vec<String>),
-    Stop(Vec<String>),
-    Status(String),
-    State,
-    Reload(Option<PathBuf>),
-    Reset(Vec<String>),
-    Restart(Vec<String>),
-    RestartWithDependents(Vec<String>),
+    UNITS_DIR
+        .get_or_init(|| match std::env::var_os("WPM_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => dirs::home_dir()
+                .expect("could not find home dir")
+                .join(".config")
+                .join("wpm"),
+        })
+        .clone()
+}
+
+/// The directories of this process, which wpmd sends to clients so that they read the same
+/// files as the daemon
+pub fn wpm_directories() -> std::io::Result<DaemonDirectories> {
+    Ok(DaemonDirectories {
+        units: wpm_units_dir(),
+        data: wpm_data_dir()?,
+        store: wpm_store_dir()?,
+        logs: wpm_log_dir()?,
+    })
+}
+
+/// Uses the directories of wpmd for the rest of the process, which must happen before any of
+/// them is first used
+pub fn set_directories(directories: DaemonDirectories) -> std::io::Result<()> {
+    set_units_dir(directories.units);
+    set_data_dir(directories.data)?;
+    set_store_dir(directories.store)?;
+    set_log_dir(directories.logs)
 }
@@ -0,0 +1,139 @@
+use crate::clock;
+use crate::process_manager_status::format_bytes;
+use crate::wpm_log_dir;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::path::PathBuf;
+use tabled::builder::Builder;
+
+/// Prefix of the daily rolling log files wpmd writes its own colored output to
+pub const DAEMON_LOG: &str = "wpmd.log";
+
+/// Prefix of the daily rolling log files wpmd writes its own output without colors to
+pub const DAEMON_PLAINTEXT_LOG: &str = "wpmd_plaintext.log";
+
+/// The directory wpmd writes its own logs to
+pub fn daemon_log_dir() -> PathBuf {
+    std::env::temp_dir()
+}
+
+/// The log file with the given prefix which wpmd is currently writing to, as the daily rolling
+/// files are suffixed with the current date in UTC
+pub fn current_daemon_log(prefix: &str) -> PathBuf {
+    daemon_log_dir().join(format!("{prefix}.{}", Utc::now().format("%Y-%m-%d")))
+}
+
+/// Moves the contents of a log file into a copy suffixed with the current time, and a counter if
+/// a copy from the same second exists, and truncates it, returning the path of the copy, or `None` if the log file was missing or empty
+///
+/// The log is copied rather than renamed because the process writing to it keeps it open, which
+/// is fine to truncate as every log is opened for appending
+pub fn rotate(path: &Path) -> std::io::Result<Option<PathBuf>> {
+    if !path.is_file() || std::fs::metadata(path)?.len() == 0 {
+        return Ok(None);
+    }
+
+    let time = clock::now().format("%Y%m%dT%H%M%S").to_string();
+
+    // a counter is appended when the log was already rotated within the same second, so that
+    // earlier copies are never overwritten
+    let mut counter = 0;
+    let (rotated, mut copy) = loop {
+        let mut rotated = path.as_os_str().to_owned();
+        match counter {
+            0 => rotated.push(format!(".{time}")),
+            _ => rotated.push(format!(".{time}-{counter}")),
+        }
+
+        let rotated = PathBuf::from(rotated);
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&rotated)
+        {
+            Ok(copy) => break (rotated, copy),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => counter += 1,
+            Err(error) => return Err(error),
+        }
+    };
+
+    std::io::copy(&mut File::open(path)?, &mut copy)?;
+    OpenOptions::new().write(true).open(path)?.set_len(0)?;
+
+    Ok(Some(rotated))
+}
+
+/// Disk space taken up by the logs of wpmd or of a unit, including rotated logs
+#[derive(Debug, Clone, Default)]
+pub struct LogUsage {
+    pub name: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// The disk space taken up by the logs of wpmd, followed by the logs in the unit log directory
+/// grouped by unit
+pub fn log_usage() -> std::io::Result<Vec<LogUsage>> {
+    let mut daemon = LogUsage {
+        name: String::from("wpmd"),
+        ..Default::default()
+    };
+
+    for entry in std::fs::read_dir(daemon_log_dir())?.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if [DAEMON_LOG, DAEMON_PLAINTEXT_LOG]
+            .iter()
+            .any(|prefix| file_name.starts_with(&format!("{prefix}.")))
+        {
+            daemon.files += 1;
+            daemon.bytes += entry.metadata().map_or(0, |metadata| metadata.len());
+        }
+    }
+
+    let mut units = BTreeMap::<String, LogUsage>::new();
+    for entry in std::fs::read_dir(wpm_log_dir()?)?.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        // rotated logs keep the name of the log with the time of the rotation appended
+        let Some(name) = file_name
+            .strip_suffix(".log")
+            .or_else(|| file_name.rsplit_once(".log.").map(|(name, _)| name))
+        else {
+            continue;
+        };
+
+        let usage = units.entry(name.to_string()).or_insert_with(|| LogUsage {
+            name: name.to_string(),
+            ..Default::default()
+        });
+
+        usage.files += 1;
+        usage.bytes += entry.metadata().map_or(0, |metadata| metadata.len());
+    }
+
+    Ok(std::iter::once(daemon).chain(units.into_values()).collect())
+}
+
+/// A table of the disk space taken up by logs, with a total in the last row
+pub fn usage_table(usage: &[LogUsage]) -> String {
+    let mut builder = Builder::default();
+    builder.push_record(["log", "files", "size"]);
+    for log in usage {
+        builder.push_record([
+            log.name.clone(),
+            log.files.to_string(),
+            format_bytes(log.bytes),
+        ]);
+    }
+
+    builder.push_record([
+        String::from("total"),
+        usage.iter().map(|log| log.files).sum::<usize>().to_string(),
+        format_bytes(usage.iter().map(|log| log.bytes).sum()),
+    ]);
+
+    builder.build().to_string()
+}
@@ -0,0 +1,128 @@
+use crate::platform::CurrentPlatform;
+use crate::platform::Platform;
+use crate::unit::OutputEncoding;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
+
+const CHUNK_SIZE: usize = 4096;
+
+fn decode_utf16le(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+
+    String::from_utf16_lossy(&units).replace('\u{feff}', "")
+}
+
+fn decode(bytes: &[u8], encoding: OutputEncoding) -> String {
+    match encoding {
+        OutputEncoding::Utf16Le => decode_utf16le(bytes),
+        OutputEncoding::Oem => CurrentPlatform::decode_console_output(bytes),
+        OutputEncoding::Utf8 | OutputEncoding::Auto => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Guesses the encoding of the first output of a process: UTF-16LE if it starts with a byte
+/// order mark or looks like ASCII text interleaved with NUL bytes, UTF-8 if it is valid UTF-8,
+/// and the OEM code page otherwise
+fn detect(bytes: &[u8]) -> OutputEncoding {
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return OutputEncoding::Utf16Le;
+    }
+
+    let high_bytes = bytes.iter().skip(1).step_by(2);
+    let nul_bytes = high_bytes.clone().filter(|byte| **byte == 0).count();
+
+    if nul_bytes > 0 && nul_bytes * 2 > high_bytes.count() {
+        return OutputEncoding::Utf16Le;
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(_) => OutputEncoding::Utf8,
+        // the chunk may end in the middle of a character
+        Err(error) if error.error_len().is_none() => OutputEncoding::Utf8,
+        Err(_) => OutputEncoding::Oem,
+    }
+}
+
+/// The length of the pending output up to and including its last complete line, so that
+/// multi-byte characters are never split between two decodes
+fn complete_lines(pending: &[u8], encoding: OutputEncoding) -> usize {
+    match encoding {
+        OutputEncoding::Utf16Le => (0..pending.len().saturating_sub(1))
+            .step_by(2)
+            .rev()
+            .find(|index| pending[*index] == b'\n' && pending[index + 1] == 0)
+            .map_or(0, |index| index + 2),
+        _ => pending
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |index| index + 1),
+    }
+}
+
+/// Relays the output of a process to its log file on a separate thread, transcoding it to
+/// UTF-8 line by line and optionally removing ANSI escape sequences
+pub fn forward(
+    mut reader: impl Read + Send + 'static,
+    log_path: PathBuf,
+    encoding: OutputEncoding,
+    strip_ansi: bool,
+) {
+    let filter = move |text: String| {
+        if strip_ansi {
+            crate::strip_ansi(&text)
+        } else {
+            text
+        }
+    };
+
+    std::thread::spawn(move || {
+        let mut file = match OpenOptions::new().create(true).append(true).open(&log_path) {
+            Ok(file) => file,
+            Err(error) => {
+                tracing::warn!("could not open {}: {error}", log_path.display());
+                return;
+            }
+        };
+
+        let mut encoding = encoding;
+        let mut pending = vec![];
+        let mut chunk = [0; CHUNK_SIZE];
+
+        loop {
+            let read = match reader.read(&mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+
+            if matches!(encoding, OutputEncoding::Auto) {
+                encoding = detect(&chunk[..read]);
+                tracing::debug!("detected {encoding:?} output");
+            }
+
+            pending.extend_from_slice(&chunk[..read]);
+
+            let end = complete_lines(&pending, encoding);
+            if end == 0 {
+                continue;
+            }
+
+            let text = filter(decode(&pending[..end], encoding));
+            pending.drain(..end);
+
+            if let Err(error) = file.write_all(text.as_bytes()) {
+                tracing::warn!("could not write output to {}: {error}", log_path.display());
+                return;
+            }
+        }
+
+        // output which doesn't end with a newline is written once the process exits
+        if !pending.is_empty() {
+            let _ = file.write_all(filter(decode(&pending, encoding)).as_bytes());
+        }
+    });
+}
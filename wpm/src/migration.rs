@@ -0,0 +1,71 @@
+use serde_json::Map;
+use serde_json::Value;
+
+/// Version of the unit file format understood by this version of wpm, which is the version of
+/// unit files written by wpm
+pub const CURRENT_FORMAT_VERSION: u32 = 2;
+
+/// Format version of unit files which don't declare one
+pub const DEFAULT_FORMAT_VERSION: u32 = 1;
+
+/// A deprecated field which was migrated when a unit file was loaded
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    pub field: String,
+    pub message: String,
+}
+
+/// Upgrades a unit file from the previous version of the format, recording any deprecated
+/// fields it used
+type Migration = fn(&mut Map<String, Value>, &mut Vec<Deprecation>);
+
+/// Migrations to each format version from the previous one, starting with the migration from
+/// version 1 to version 2
+const MIGRATIONS: [Migration; (CURRENT_FORMAT_VERSION - DEFAULT_FORMAT_VERSION) as usize] =
+    [rename_service_type];
+
+/// Version 2 replaced the Type of a service with its Kind
+fn rename_service_type(definition: &mut Map<String, Value>, deprecations: &mut Vec<Deprecation>) {
+    let Some(Value::Object(service)) = definition.get_mut("Service") else {
+        return;
+    };
+
+    if let Some(kind) = service.remove("Type") {
+        if !service.contains_key("Kind") {
+            service.insert(String::from("Kind"), kind);
+        }
+
+        deprecations.push(Deprecation {
+            field: String::from("Service.Type"),
+            message: String::from("renamed to Service.Kind in format version 2"),
+        });
+    }
+}
+
+/// The format version declared by a unit file
+pub fn format_version(definition: &Value) -> u32 {
+    definition
+        .get("FormatVersion")
+        .and_then(Value::as_u64)
+        .map_or(DEFAULT_FORMAT_VERSION, |version| version as u32)
+}
+
+/// Upgrades a unit file from its format version to the current format version, returning the
+/// deprecated fields which were migrated
+pub fn migrate(definition: &mut Value) -> Vec<Deprecation> {
+    let version = format_version(definition).max(DEFAULT_FORMAT_VERSION);
+    let mut deprecations = vec![];
+
+    let Value::Object(fields) = definition else {
+        return deprecations;
+    };
+
+    for migration in MIGRATIONS
+        .iter()
+        .skip((version - DEFAULT_FORMAT_VERSION) as usize)
+    {
+        migration(fields, &mut deprecations);
+    }
+
+    deprecations
+}
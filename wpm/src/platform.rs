@@ -0,0 +1,43 @@
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(not(windows))]
+mod unsupported;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(not(windows))]
+pub use unsupported::Unsupported as CurrentPlatform;
+#[cfg(windows)]
+pub use windows::Windows as CurrentPlatform;
+
+/// The operating system specific parts of spawning and supervising unit processes, which are
+/// kept behind this trait so that the unit model, dependency resolution and status types can be
+/// built and used by tooling on every platform
+pub trait Platform {
+    /// Prepares a command before a unit process is spawned from it
+    fn configure_command(command: &mut Command);
+
+    /// Adds arguments to a command exactly as they are given, for shells which don't follow the
+    /// quoting rules used by [`Command::args`]
+    fn add_raw_arguments(command: &mut Command, arguments: &[String]);
+
+    /// Launches a packaged application by its Application User Model ID, returning its PID
+    fn activate_application(aumid: &str, arguments: &str) -> std::io::Result<u32>;
+
+    /// Opens a file through the shell, returning the PID of the launched process
+    fn open_with_shell(
+        path: &Path,
+        arguments: &str,
+        working_directory: Option<&Path>,
+    ) -> std::io::Result<u32>;
+
+    /// Makes sure that a process is killed when the daemon exits
+    fn kill_on_daemon_exit(pid: u32) -> std::io::Result<()>;
+
+    /// Starts a service of the operating system if it isn't running yet, and waits until it is
+    fn ensure_service_running(name: &str) -> std::io::Result<()>;
+
+    /// Decodes text in the legacy code page which console programs use for redirected output
+    fn decode_console_output(bytes: &[u8]) -> String;
+}
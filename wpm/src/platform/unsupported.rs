@@ -0,0 +1,46 @@
+use crate::platform::Platform;
+use std::path::Path;
+use std::process::Command;
+
+/// Platforms which wpm can't supervise Windows-specific units on yet, where the unit model and
+/// tooling still work but activating applications, job objects and Windows services don't
+pub struct Unsupported;
+
+fn unsupported(feature: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("{feature} is only supported on Windows"),
+    )
+}
+
+impl Platform for Unsupported {
+    fn configure_command(_command: &mut Command) {}
+
+    fn add_raw_arguments(command: &mut Command, arguments: &[String]) {
+        command.args(arguments);
+    }
+
+    fn activate_application(_aumid: &str, _arguments: &str) -> std::io::Result<u32> {
+        Err(unsupported("activating applications"))
+    }
+
+    fn open_with_shell(
+        _path: &Path,
+        _arguments: &str,
+        _working_directory: Option<&Path>,
+    ) -> std::io::Result<u32> {
+        Err(unsupported("opening shell links"))
+    }
+
+    fn kill_on_daemon_exit(_pid: u32) -> std::io::Result<()> {
+        Err(unsupported("killing processes when wpmd exits"))
+    }
+
+    fn ensure_service_running(_name: &str) -> std::io::Result<()> {
+        Err(unsupported("starting Windows services"))
+    }
+
+    fn decode_console_output(bytes: &[u8]) -> String {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
@@ -0,0 +1,80 @@
+use crate::activation;
+use crate::job;
+use crate::platform::Platform;
+use crate::windows_service;
+use std::os::windows::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use windows_sys::Win32::Globalization::MultiByteToWideChar;
+use windows_sys::Win32::Globalization::CP_OEMCP;
+
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+pub struct Windows;
+
+impl Platform for Windows {
+    fn configure_command(command: &mut Command) {
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    fn add_raw_arguments(command: &mut Command, arguments: &[String]) {
+        for argument in arguments {
+            command.raw_arg(argument);
+        }
+    }
+
+    fn activate_application(aumid: &str, arguments: &str) -> std::io::Result<u32> {
+        activation::activate_application(aumid, arguments)
+    }
+
+    fn open_with_shell(
+        path: &Path,
+        arguments: &str,
+        working_directory: Option<&Path>,
+    ) -> std::io::Result<u32> {
+        activation::open_with_shell(path, arguments, working_directory)
+    }
+
+    fn kill_on_daemon_exit(pid: u32) -> std::io::Result<()> {
+        job::kill_on_daemon_exit(pid)
+    }
+
+    fn ensure_service_running(name: &str) -> std::io::Result<()> {
+        windows_service::ensure_running(name)
+    }
+
+    /// Decodes text in the OEM code page of the system, which console programs often use when
+    /// their output is redirected
+    fn decode_console_output(bytes: &[u8]) -> String {
+        if bytes.is_empty() {
+            return String::new();
+        }
+
+        unsafe {
+            let length = MultiByteToWideChar(
+                CP_OEMCP,
+                0,
+                bytes.as_ptr(),
+                bytes.len() as i32,
+                std::ptr::null_mut(),
+                0,
+            );
+
+            if length <= 0 {
+                return String::from_utf8_lossy(bytes).into_owned();
+            }
+
+            let mut wide = vec![0u16; length as usize];
+            MultiByteToWideChar(
+                CP_OEMCP,
+                0,
+                bytes.as_ptr(),
+                bytes.len() as i32,
+                wide.as_mut_ptr(),
+                length,
+            );
+
+            String::from_utf16_lossy(&wide)
+        }
+    }
+}
@@ -1,31 +1,81 @@
+use crate::clock;
+use crate::clock::Clock;
+use crate::communication::daemon_socket;
 use crate::communication::send_message;
+use crate::diagnostic::ParseDiagnostic;
+use crate::fetch::fetch_bytes;
+use crate::fetch::prefetch;
+use crate::fetch::set_fetcher;
+use crate::fetch::Fetcher;
+use crate::journal;
+use crate::journal::UnitEvent;
+use crate::migration::format_version;
+use crate::migration::migrate;
+use crate::migration::CURRENT_FORMAT_VERSION;
+use crate::platform::CurrentPlatform;
+use crate::platform::Platform;
 use crate::process_manager_status::ProcessManagerStatus;
+use crate::restart_budget;
+use crate::set_log_dir;
+use crate::set_store_dir;
+use crate::startup_slots;
+use crate::state_hooks;
+use crate::sync::SYNC_DIRECTORY;
+use crate::time_format::Timestamp;
+use crate::unit::merge_environment;
+use crate::unit::schedule_restart;
+use crate::unit::unit_span;
+use crate::unit::CpuThreshold;
 use crate::unit::Definition;
 use crate::unit::Executable;
 use crate::unit::Healthcheck;
+use crate::unit::MemoryThreshold;
+use crate::unit::OnExistingProcess;
+use crate::unit::PathTrigger;
 use crate::unit::RestartStrategy;
 use crate::unit::ServiceKind;
+use crate::unit::SocketActivation;
 use crate::unit_status::DisplayedOption;
+use crate::unit_status::ExitReason;
+use crate::unit_status::HealthcheckFailure;
+use crate::unit_status::RestartBranch;
+use crate::unit_status::UnitExit;
+use crate::unit_status::UnitResult;
 use crate::unit_status::UnitState;
 use crate::unit_status::UnitStatus;
+use crate::unit_status::Uptime;
+use crate::validation::validate_definitions;
+use crate::validation::validate_unit_name;
+use crate::validation::UnitWarning;
+use crate::wpm_snapshots_dir;
+use crate::wpm_units_dir;
+use crate::RemoveOptions;
 use crate::SocketMessage;
+use crate::UnitAction;
 use chrono::DateTime;
-use chrono::Local;
 use chrono::Utc;
 use parking_lot::Mutex;
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
 use shared_child::SharedChild;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::ExitStatus;
 use std::sync::Arc;
+use std::sync::OnceLock;
 use std::time::Duration;
+use std::time::Instant;
 use sysinfo::Pid;
 use sysinfo::ProcessRefreshKind;
 use sysinfo::ProcessesToUpdate;
 use sysinfo::System;
+use sysinfo::UpdateKind;
 use thiserror::Error;
+use url::Url;
 
 #[derive(Error, Debug)]
 pub enum ProcessManagerError {
@@ -35,10 +85,23 @@ pub enum ProcessManagerError {
     RunningUnit(String),
     #[error("{0} is marked as completed; reset unit before trying again")]
     CompletedUnit(String),
-    #[error("{0} failed its healthcheck; reset unit before trying again")]
-    FailedHealthcheck(String),
+    #[error(
+        "{0} failed its healthcheck{details}; reset unit before trying again",
+        details = .1.as_ref().map(|failure| format!(" ({})", failure.summary())).unwrap_or_default()
+    )]
+    FailedHealthcheck(String, Option<HealthcheckFailure>),
     #[error("{0} is not running")]
     NotRunning(String),
+    #[error("could not find an untracked running process for {0}")]
+    NoExistingProcess(String),
+    #[error("{name} is already running outside of wpm as process {pid}")]
+    ExistingProcess { name: String, pid: u32 },
+    #[error("{name} could not replace process {pid}, which was still running after being killed")]
+    SurvivingProcess { name: String, pid: u32 },
+    #[error(
+        "{0} relays its output through wpmd, so its processes can't outlive wpmd to be adopted"
+    )]
+    RelayedOutput(String),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
@@ -47,14 +110,413 @@ pub enum ProcessManagerError {
     Json(#[from] serde_json::Error),
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
     #[error("{0} did not spawn a process with a handle")]
     NoHandle(String),
-    #[error("a forking service must have a process healthcheck target defined")]
+    #[error("a forking service must have a process healthcheck target or pid file defined")]
     InvalidForkingService,
-    #[error("a simple service cannot have a separate process healthcheck target")]
+    #[error("a simple service cannot have a separate process healthcheck target or pid file")]
     InvalidSimpleService,
     #[error("hash mismatch (expected {expected}, actual {actual})")]
     HashMismatch { expected: String, actual: String },
+    #[error("{name} was not started because its dependency {dependency} failed to start")]
+    DependencyFailed { name: String, dependency: String },
+    #[error("could not start windows service {name}: {error}")]
+    WindowsService { name: String, error: std::io::Error },
+    #[error("a service cannot have both a Script and an ExecStart")]
+    ScriptWithExecStart,
+    #[error("a {0} must have either an Executable or a Shell command line")]
+    MissingCommand(&'static str),
+    #[error("could not find {kind} executable {executable} in $PATH")]
+    MissingExecutable {
+        kind: &'static str,
+        executable: String,
+    },
+    #[error("{0}")]
+    Parse(ParseDiagnostic),
+    #[error("unknown fields: {0}")]
+    UnknownFields(String),
+    #[error("{name} is not an example unit (available: {available})")]
+    UnknownExample { name: String, available: String },
+    #[error("{name} is not a valid unit name: {reason}")]
+    InvalidUnitName { name: String, reason: String },
+    #[error("{name} is already defined in {}", existing.display())]
+    DuplicateUnit { name: String, existing: PathBuf },
+    #[error("{name} is not a valid snapshot name: {reason}")]
+    InvalidSnapshotName { name: String, reason: String },
+    #[error("there is no snapshot named {0}")]
+    UnknownSnapshot(String),
+    #[error("git {command} failed: {stderr}")]
+    Git { command: String, stderr: String },
+    #[error("no repository has been configured to sync units from")]
+    NoSyncRepository,
+    #[error("format version {version} is newer than the latest format version supported by this version of wpm ({current})")]
+    UnsupportedFormatVersion { version: u32, current: u32 },
+    #[error("{0} is not the url of a .json or .toml unit file")]
+    UnsupportedUnitUrl(String),
+    #[error("could not open log file {}: {error}", path.display())]
+    LogFile {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("there is no masked unit file for {0}")]
+    NoMaskedUnit(String),
+    #[error("{} is synced from a git repository, so it has to be changed there", .0.display())]
+    SyncedUnit(PathBuf),
+    #[error("{0} is a scoop package without a manifest, which is not supported yet")]
+    UnsupportedScoopPackage(String),
+    #[error("could not spawn {executable}: {error}")]
+    Spawn {
+        executable: String,
+        error: std::io::Error,
+    },
+    #[error("could not restart {name}: {error}")]
+    Restart { name: String, error: String },
+}
+
+/// The unit files in a unit directory followed by the unit files synced into it, each sorted by
+/// path; when multiple files define the same unit name the first file wins, so a local unit file
+/// can override a synced unit
+fn unit_files(unit_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut units = unit_files_in(unit_dir)?;
+
+    let sync_dir = unit_dir.join(SYNC_DIRECTORY);
+    if sync_dir.is_dir() {
+        units.append(&mut unit_files_in(&sync_dir)?);
+    }
+
+    Ok(units)
+}
+
+/// The result for each of the given units, where units without a failure succeeded
+fn unit_results(names: &[String], failures: Vec<(String, ProcessManagerError)>) -> Vec<UnitResult> {
+    names
+        .iter()
+        .map(|name| UnitResult {
+            name: name.clone(),
+            error: failures
+                .iter()
+                .find(|(failed, _)| failed == name)
+                .map(|(_, error)| error.to_string()),
+            restart: None,
+        })
+        .collect()
+}
+
+/// Extension appended to unit files to mask them, which keeps them from being loaded without
+/// deleting them
+pub const MASKED_EXTENSION: &str = "disabled";
+
+/// Only .json and .toml files are unit files, so masked unit files ending in .disabled are
+/// skipped
+fn unit_files_in(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut units = vec![];
+
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if path.is_file() {
+            #[allow(clippy::if_same_then_else)]
+            if path.extension() == Some(OsStr::new("json")) {
+                units.push(path);
+            } else if path.extension() == Some(OsStr::new("toml"))
+                && path.file_name() != Some(OsStr::new("taplo.toml"))
+                && path.file_name() != Some(OsStr::new(".taplo.toml"))
+            {
+                units.push(path);
+            }
+        }
+    }
+
+    units.sort();
+
+    Ok(units)
+}
+
+/// Name of the snapshot saved when wpmd shuts down and restored when it starts, if enabled
+pub const SESSION_SNAPSHOT: &str = "session";
+
+/// The units which were running at a point in time
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub timestamp: DateTime<Utc>,
+    pub units: Vec<String>,
+}
+
+/// Snapshot names follow the same rules as unit names so that they can be used as file names
+fn snapshot_path(name: &str) -> Result<PathBuf, ProcessManagerError> {
+    validate_unit_name(name).map_err(|reason| ProcessManagerError::InvalidSnapshotName {
+        name: name.to_string(),
+        reason,
+    })?;
+
+    Ok(wpm_snapshots_dir()?.join(format!("{name}.json")))
+}
+
+/// Minimum time between full refreshes of the shared process table
+const PROCESS_TABLE_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+static PROCESS_TABLE: OnceLock<Mutex<ProcessTable>> = OnceLock::new();
+
+/// A process table shared by everything in wpm which needs to look up processes, so that
+/// each lookup doesn't enumerate every process on the machine with `System::new_all`
+struct ProcessTable {
+    system: System,
+    refreshed_at: Option<Instant>,
+}
+
+fn process_table() -> &'static Mutex<ProcessTable> {
+    PROCESS_TABLE.get_or_init(|| {
+        Mutex::new(ProcessTable {
+            system: System::new(),
+            refreshed_at: None,
+        })
+    })
+}
+
+/// Refreshes a single process in the shared process table and returns whether it is running
+pub fn process_exists(pid: u32) -> bool {
+    let mut table = process_table().lock();
+    let pid = Pid::from_u32(pid);
+    table.system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+
+    table.system.process(pid).is_some()
+}
+
+/// How long a start waits for the existing processes killed by `OnExistingProcess = "Kill"`
+const KILLED_PROCESS_EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Waits for a process to exit, returning false if it is still running once the timeout has
+/// elapsed
+fn wait_for_exit(pid: u32, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+
+    while process_exists(pid) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    true
+}
+
+static KILLED_PROCESSES: OnceLock<Mutex<HashSet<u32>>> = OnceLock::new();
+
+/// Tracked processes which wpm has sent a kill signal to, as a process killed with
+/// TerminateProcess exits with a regular exit code on Windows
+fn killed_processes() -> &'static Mutex<HashSet<u32>> {
+    KILLED_PROCESSES.get_or_init(Default::default)
+}
+
+/// The reason a tracked process which has exited should be reported with instead of its exit
+/// status, which is [`ExitReason::Killed`] if wpm killed it; the kill is forgotten afterwards so
+/// that a later process with the same PID isn't reported as killed
+pub fn exit_reason_override(pid: u32) -> Option<ExitReason> {
+    killed_processes()
+        .lock()
+        .remove(&pid)
+        .then_some(ExitReason::Killed)
+}
+
+/// Memory and CPU usage of a process
+pub struct ProcessUsage {
+    /// Memory usage in bytes, which is the working set on Windows
+    pub memory: u64,
+    /// CPU usage since the previous sample in percent of a single logical processor
+    pub cpu_percent: f32,
+}
+
+/// Refreshes a single process in the shared process table and returns its resource usage
+pub fn process_usage(pid: u32) -> Option<ProcessUsage> {
+    let mut table = process_table().lock();
+    let pid = Pid::from_u32(pid);
+    table.system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing().with_memory().with_cpu(),
+    );
+
+    table.system.process(pid).map(|process| ProcessUsage {
+        memory: process.memory(),
+        cpu_percent: process.cpu_usage(),
+    })
+}
+
+/// A running unit whose process is sampled for its resource usage history and thresholds
+pub struct MonitoredUnit {
+    pub name: String,
+    pub pid: u32,
+    pub memory: Option<MemoryThreshold>,
+    pub cpu: Option<CpuThreshold>,
+}
+
+/// Sends a kill signal to a process, returning false if the process could not be found
+pub fn kill_process(pid: u32) -> bool {
+    let mut table = process_table().lock();
+    let pid = Pid::from_u32(pid);
+    table.system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+
+    table
+        .system
+        .process(pid)
+        .map(|process| process.kill())
+        .unwrap_or_default()
+}
+
+/// A process found by name in the shared process table
+pub struct FoundProcess {
+    pub pid: u32,
+    pub executable: Option<PathBuf>,
+    pub parent: Option<u32>,
+    pub command_line: String,
+    /// The arguments of the command line, without the executable
+    pub arguments: Vec<String>,
+    /// When the process was started, in seconds since the Unix epoch
+    pub start_time: u64,
+}
+
+impl FoundProcess {
+    /// Whether the process's command line matches a pattern and its parent is the given PID,
+    /// ignoring whichever of the two is not given
+    pub fn matches(&self, command_line: Option<&Regex>, parent: Option<u32>) -> bool {
+        command_line.is_none_or(|pattern| pattern.is_match(&self.command_line))
+            && parent.is_none_or(|parent| self.parent == Some(parent))
+    }
+
+    /// Whether the process runs the executable at the given path, rather than only an
+    /// executable with the same file name
+    pub fn runs(&self, path: &Path) -> bool {
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+        self.executable
+            .as_deref()
+            .is_some_and(|executable| canonical(executable) == canonical(path))
+    }
+}
+
+/// Returns all processes with the given name, refreshing the shared process table if it
+/// hasn't been refreshed within the last second
+pub fn find_processes(name: &OsStr) -> Vec<FoundProcess> {
+    let mut table = process_table().lock();
+
+    let is_stale = table
+        .refreshed_at
+        .is_none_or(|refreshed_at| refreshed_at.elapsed() >= PROCESS_TABLE_REFRESH_INTERVAL);
+
+    if is_stale {
+        table.system.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::nothing()
+                .with_exe(UpdateKind::OnlyIfNotSet)
+                .with_cmd(UpdateKind::OnlyIfNotSet),
+        );
+
+        table.refreshed_at = Some(Instant::now());
+    }
+
+    table
+        .system
+        .processes_by_name(name)
+        .map(|process| FoundProcess {
+            pid: process.pid().as_u32(),
+            executable: process.exe().map(Path::to_path_buf),
+            parent: process.parent().map(Pid::as_u32),
+            command_line: process
+                .cmd()
+                .iter()
+                .map(|argument| argument.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            arguments: process
+                .cmd()
+                .iter()
+                .skip(1)
+                .map(|argument| argument.to_string_lossy().to_string())
+                .collect(),
+            start_time: process.start_time(),
+        })
+        .collect()
+}
+
+/// When a running process was started, in seconds since the Unix epoch
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let mut table = process_table().lock();
+    let pid = Pid::from_u32(pid);
+    table.system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+
+    table
+        .system
+        .process(pid)
+        .map(|process| process.start_time())
+}
+
+/// Details of a running process which are only known once it has been spawned
+pub struct ProcessDetails {
+    pub executable: Option<PathBuf>,
+    pub command_line: Vec<String>,
+    pub descendants: Vec<u32>,
+}
+
+/// Looks up the executable path, command line and descendant processes of a running process,
+/// which can differ from the unit definition when a process re-executes itself
+pub fn process_details(pid: u32) -> Option<ProcessDetails> {
+    let mut table = process_table().lock();
+    table.system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing()
+            .with_exe(UpdateKind::OnlyIfNotSet)
+            .with_cmd(UpdateKind::OnlyIfNotSet),
+    );
+
+    table.refreshed_at = Some(Instant::now());
+
+    let root = Pid::from_u32(pid);
+    let process = table.system.process(root)?;
+
+    let mut descendants = vec![];
+    let mut parents = vec![root];
+
+    while let Some(parent) = parents.pop() {
+        for (child_pid, child) in table.system.processes() {
+            if child.parent() == Some(parent)
+                && *child_pid != root
+                && !descendants.contains(&child_pid.as_u32())
+            {
+                descendants.push(child_pid.as_u32());
+                parents.push(*child_pid);
+            }
+        }
+    }
+
+    descendants.sort();
+
+    Some(ProcessDetails {
+        executable: process.exe().map(Path::to_path_buf),
+        command_line: process
+            .cmd()
+            .iter()
+            .map(|argument| argument.to_string_lossy().to_string())
+            .collect(),
+        descendants,
+    })
 }
 
 #[derive(Clone)]
@@ -72,25 +534,31 @@ impl Child {
     }
 
     pub fn kill(&self) -> std::io::Result<()> {
-        match self {
+        // recorded before the kill so that the thread waiting on the process can't miss it
+        killed_processes().lock().insert(self.id());
+
+        let result = match self {
             Child::Shared(shared) => shared.kill(),
             Child::Pid(id) => {
-                let pid = *id;
-                let s = System::new_all();
-                if let Some(process) = s.process(Pid::from_u32(pid)) {
-                    process.kill();
-                }
-
+                kill_process(*id);
                 Ok(())
             }
+        };
+
+        if result.is_err() {
+            killed_processes().lock().remove(&self.id());
         }
+
+        result
     }
 
     pub fn wait(&self) -> std::io::Result<ExitStatus> {
         match self {
             Self::Shared(child) => child.wait(),
             Self::Pid(pid) => {
-                let mut system = System::new_all();
+                // waiting blocks until the process exits, so this uses its own System
+                // rather than holding the lock on the shared process table
+                let mut system = System::new();
                 let pid = Pid::from_u32(*pid);
                 system.refresh_processes_specifics(
                     ProcessesToUpdate::Some(&[pid]),
@@ -113,27 +581,177 @@ impl Child {
     }
 }
 
+/// Runs a unit's ExecStop commands unless forced, kills its process and runs its ExecStopPost
+/// commands. The running map is only locked briefly, so that several units can be stopped at once
+fn stop_process(
+    unit: &Definition,
+    running: &Mutex<HashMap<String, ProcessState>>,
+    force: bool,
+) -> Result<(), ProcessManagerError> {
+    let name = &unit.unit.name;
+
+    // removed first so that the thread waiting on the process spawned by Unit.execute() doesn't
+    // treat the process exiting as a termination, and put back if the process can't be stopped
+    let proc_state = running
+        .lock()
+        .remove(name)
+        .ok_or(ProcessManagerError::NotRunning(name.to_string()))?;
+
+    let id = proc_state.child.id();
+    let log_path = unit.log_path()?;
+
+    tracing::info!("stopping unit");
+
+    if force {
+        tracing::info!("skipping shutdown commands");
+    } else {
+        for command in unit.service.exec_stop.iter().flatten() {
+            let stringified = command.logged_command_line();
+
+            tracing::info!("executing shutdown command - {stringified}");
+            let environment =
+                unit.service
+                    .command_environment()
+                    .with_runtime(name, id, UnitState::Running, None);
+
+            if let Err(error) = command.run_hook("stop", &environment, Some(&log_path)) {
+                running.lock().insert(name.to_string(), proc_state);
+                return Err(error);
+            }
+        }
+    }
+
+    tracing::info!("sending kill signal to {id}");
+
+    if let Err(error) = proc_state.child.kill() {
+        // If there are any errors in killing the process, it's still considered to be running
+        // so we reinsert before returning the errors
+        running.lock().insert(name.to_string(), proc_state);
+        return Err(error.into());
+    }
+
+    let exit_code = match proc_state.child.wait() {
+        Ok(exit_status) => exit_status.code(),
+        Err(error) if matches!(error.kind(), std::io::ErrorKind::NotFound) => {
+            tracing::warn!("process {id} not found; assuming successful termination");
+            None
+        }
+        Err(error) => {
+            running.lock().insert(name.to_string(), proc_state);
+            return Err(error.into());
+        }
+    };
+
+    tracing::info!("process {id} successfully terminated");
+
+    for command in unit.service.exec_stop_post.iter().flatten() {
+        let stringified = command.logged_command_line();
+
+        tracing::info!("executing cleanup command - {stringified}");
+        let environment = unit.service.command_environment().with_runtime(
+            name,
+            id,
+            UnitState::Stopped,
+            exit_code,
+        );
+
+        command.run_hook("stop-post", &environment, Some(&log_path))?;
+    }
+
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct ProcessState {
     pub child: Child,
     pub timestamp: DateTime<Utc>,
 }
 
+/// A unit definition which could not be loaded, and why
+#[derive(Debug, Clone)]
+pub struct UnitLoadError {
+    pub name: String,
+    pub path: PathBuf,
+    pub error: String,
+    /// The file which already defines the unit, when this file failed to load because it
+    /// defines the same unit name again
+    pub duplicate_of: Option<PathBuf>,
+}
+
+impl UnitLoadError {
+    pub fn new(name: String, path: PathBuf, error: ProcessManagerError) -> Self {
+        let duplicate_of = match &error {
+            ProcessManagerError::DuplicateUnit { existing, .. } => Some(existing.clone()),
+            _ => None,
+        };
+
+        Self {
+            name,
+            path,
+            error: error.to_string(),
+            duplicate_of,
+        }
+    }
+
+    /// Whether the unit with the given name failed to load, which isn't the case when only
+    /// another file defining the same unit name failed to load
+    pub fn is_for(&self, name: &str) -> bool {
+        self.name == name && self.duplicate_of.is_none()
+    }
+}
+
+/// Explicit configuration for [`ProcessManager::init_with_options`]
+pub struct ProcessManagerOptions {
+    /// Directory the unit files are loaded from
+    pub units_dir: PathBuf,
+    /// Directory remote executables and resources are downloaded to
+    pub store_dir: Option<PathBuf>,
+    /// Directory the output of units is written to
+    pub log_dir: Option<PathBuf>,
+    pub strict: bool,
+    /// Whether units with Autostart enabled are started once they are loaded
+    pub autostart: bool,
+    /// Source of the timestamps recorded for units and their events
+    pub clock: Option<Arc<dyn Clock>>,
+    /// Downloader for remote executables, resources and unit files
+    pub fetcher: Option<Arc<dyn Fetcher>>,
+}
+
+impl ProcessManagerOptions {
+    pub fn new(units_dir: PathBuf) -> Self {
+        Self {
+            units_dir,
+            store_dir: None,
+            log_dir: None,
+            strict: false,
+            autostart: false,
+            clock: None,
+            fetcher: None,
+        }
+    }
+}
+
 pub struct ProcessManager {
     definitions: HashMap<String, Definition>,
+    load_errors: Vec<UnitLoadError>,
+    load_warnings: Vec<UnitWarning>,
+    /// Reject unit files containing fields which wpm doesn't recognize
+    strict: bool,
     running: Arc<Mutex<HashMap<String, ProcessState>>>,
     completed: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
-    failed: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
-    terminated: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+    failed: Arc<Mutex<HashMap<String, UnitExit>>>,
+    terminated: Arc<Mutex<HashMap<String, UnitExit>>>,
+    /// The next start attempt of units which are waiting out their StartRetrySec
+    start_retries: Arc<Mutex<HashMap<String, u8>>>,
 }
 
 impl ProcessManager {
     pub fn unit_directory() -> PathBuf {
-        let home = dirs::home_dir().expect("could not find home dir");
-        let dir = home.join(".config").join("wpm");
+        let dir = wpm_units_dir();
 
         if !dir.is_dir() {
-            std::fs::create_dir_all(&dir).expect("could not create ~/.config/wpm");
+            std::fs::create_dir_all(&dir)
+                .unwrap_or_else(|_| panic!("could not create {}", dir.display()));
         }
 
         dir
@@ -156,14 +774,22 @@ impl ProcessManager {
         None
     }
 
-    pub fn init(path: Option<PathBuf>) -> Result<Self, ProcessManagerError> {
-        let mut pm = ProcessManager {
+    fn new(strict: bool) -> Self {
+        ProcessManager {
             definitions: Default::default(),
+            strict,
+            load_errors: Default::default(),
+            load_warnings: Default::default(),
             running: Arc::new(Default::default()),
             completed: Arc::new(Default::default()),
             failed: Arc::new(Default::default()),
             terminated: Arc::new(Default::default()),
-        };
+            start_retries: Arc::new(Default::default()),
+        }
+    }
+
+    pub fn init(path: Option<PathBuf>, strict: bool) -> Result<Self, ProcessManagerError> {
+        let mut pm = Self::new(strict);
 
         pm.load_units(path)?;
         pm.autostart();
@@ -171,20 +797,84 @@ impl ProcessManager {
         Ok(pm)
     }
 
+    /// Initializes a process manager with explicit directories, clock and fetcher instead of the
+    /// ones resolved from the environment, for embedding wpm in other programs and for tests
+    ///
+    /// The store and log directories, the clock and the fetcher are shared by the whole process,
+    /// so they should be the same for every process manager initialized this way
+    pub fn init_with_options(options: ProcessManagerOptions) -> Result<Self, ProcessManagerError> {
+        if let Some(dir) = options.store_dir {
+            set_store_dir(dir)?;
+        }
+
+        if let Some(dir) = options.log_dir {
+            set_log_dir(dir)?;
+        }
+
+        if let Some(clock) = options.clock {
+            clock::set_clock(clock);
+        }
+
+        if let Some(fetcher) = options.fetcher {
+            set_fetcher(fetcher);
+        }
+
+        let mut pm = Self::new(options.strict);
+        pm.load_units(Some(options.units_dir))?;
+
+        if options.autostart {
+            pm.autostart();
+        }
+
+        Ok(pm)
+    }
+
+    /// Starts the units with Autostart enabled on this machine, one priority at a time from the
+    /// highest AutostartPriority to the lowest; units with the same priority are started in
+    /// dependency order and then by name
     pub fn autostart(&mut self) {
-        let mut autostart = vec![];
+        let host_name = System::host_name();
 
-        for (name, def) in &self.definitions {
-            if def.service.autostart {
-                autostart.push(name.clone());
-            }
+        let mut autostart = self
+            .definitions
+            .iter()
+            .filter(|(name, def)| {
+                let autostarts = def.service.autostarts_on(host_name.as_deref());
+                if def.service.autostart && !autostarts {
+                    let _span = unit_span(name).entered();
+                    tracing::info!(
+                        "not autostarting on {}",
+                        host_name.as_deref().unwrap_or("this machine")
+                    );
+                }
+
+                autostarts
+            })
+            .map(|(name, def)| {
+                (
+                    def.service.autostart_priority.unwrap_or_default(),
+                    name.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        autostart.sort_by(|(a_priority, a_name), (b_priority, b_name)| {
+            b_priority.cmp(a_priority).then_with(|| a_name.cmp(b_name))
+        });
+
+        for (_, name) in &autostart {
+            let _span = unit_span(name).entered();
+            tracing::info!("autostarting");
         }
 
-        for name in &autostart {
-            tracing::info!("{name}: autostarting");
-            if let Err(error) = self.start(name) {
-                tracing::error!("{error}");
-            }
+        for group in autostart.chunk_by(|(a, _), (b, _)| a == b) {
+            let names = group
+                .iter()
+                .map(|(_, name)| name.clone())
+                .collect::<Vec<_>>();
+
+            // errors are logged for each unit by start_all
+            let _ = self.start_all(&names);
         }
     }
 
@@ -195,24 +885,7 @@ impl ProcessManager {
             Self::unit_directory()
         };
 
-        let read_dir = std::fs::read_dir(unit_dir)?;
-
-        let mut paths = vec![];
-
-        for entry in read_dir.flatten() {
-            let path = entry.path();
-            if path.is_file() {
-                #[allow(clippy::if_same_then_else)]
-                if path.extension() == Some(OsStr::new("json")) {
-                    paths.push(path);
-                } else if path.extension() == Some(OsStr::new("toml"))
-                    && path.file_name() != Some(OsStr::new("taplo.toml"))
-                    && path.file_name() != Some(OsStr::new(".taplo.toml"))
-                {
-                    paths.push(path);
-                }
-            }
-        }
+        let paths = unit_files(&unit_dir)?;
 
         let mut units = vec![];
 
@@ -232,395 +905,1262 @@ impl ProcessManager {
         Ok(units)
     }
 
-    pub fn load_units(&mut self, path: Option<PathBuf>) -> Result<(), ProcessManagerError> {
+    /// Parses every unit file in a directory, returning the definitions which were parsed
+    /// successfully and the files which could not be loaded
+    ///
+    /// In strict mode, unit files containing unknown fields fail to load instead of having
+    /// those fields ignored
+    #[allow(clippy::type_complexity)]
+    pub fn parse_units(
+        path: Option<PathBuf>,
+        strict: bool,
+    ) -> Result<(Vec<(PathBuf, Definition)>, Vec<UnitLoadError>), ProcessManagerError> {
         let unit_dir = if let Some(path) = path {
             path
         } else {
             Self::unit_directory()
         };
 
-        let read_dir = std::fs::read_dir(unit_dir)?;
-
-        let mut units = vec![];
+        let units = unit_files(&unit_dir)?;
+        let sync_dir = unit_dir.join(SYNC_DIRECTORY);
 
-        for entry in read_dir.flatten() {
-            let path = entry.path();
-            if path.is_file() {
-                #[allow(clippy::if_same_then_else)]
-                if path.extension() == Some(OsStr::new("json")) {
-                    units.push(path);
-                } else if path.extension() == Some(OsStr::new("toml"))
-                    && path.file_name() != Some(OsStr::new("taplo.toml"))
-                    && path.file_name() != Some(OsStr::new(".taplo.toml"))
-                {
-                    units.push(path);
-                }
-            }
-        }
+        let mut definitions: Vec<(PathBuf, Definition)> = vec![];
+        let mut load_errors = vec![];
 
         for path in units {
-            let mut definition: Definition = match path.extension() {
-                Some(extension) => match extension.to_string_lossy().to_string().as_str() {
-                    "json" => serde_json::from_str(&std::fs::read_to_string(path)?)?,
-                    "toml" => toml::from_str(&std::fs::read_to_string(path)?)?,
-                    _ => continue,
-                },
-                None => continue,
-            };
-
-            definition.resolve_resources()?;
+            let definition = match Self::parse_unit(&path, strict) {
+                Ok(definition) => definition,
+                Err(error) => {
+                    let name = path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().to_string())
+                        .unwrap_or_default();
 
-            if matches!(definition.service.kind, ServiceKind::Forking) {
-                let mut is_valid_forking_service = false;
-                if let Some(Healthcheck::Process(proc)) = &definition.service.healthcheck {
-                    if proc.target.is_some() {
-                        is_valid_forking_service = true;
-                    }
+                    load_errors.push(UnitLoadError::new(name, path, error));
+                    continue;
                 }
+            };
 
-                if !is_valid_forking_service {
-                    return Err(ProcessManagerError::InvalidForkingService);
-                }
+            let name = definition.unit.name.clone();
+
+            if let Err(reason) = validate_unit_name(&name) {
+                let error = ProcessManagerError::InvalidUnitName { name, reason };
+                load_errors.push(UnitLoadError::new(definition.unit.name, path, error));
+                continue;
             }
 
-            if matches!(definition.service.kind, ServiceKind::Simple) {
-                let mut is_invalid_simple_service = false;
-                if let Some(Healthcheck::Process(proc)) = &definition.service.healthcheck {
-                    if proc.target.is_some() {
-                        is_invalid_simple_service = true;
-                    }
+            match definitions
+                .iter()
+                .find(|(_, existing)| existing.unit.name == name)
+            {
+                None => definitions.push((path, definition)),
+                // local unit files come first, so that they can deliberately override synced ones
+                Some((existing, _))
+                    if path.starts_with(&sync_dir) && !existing.starts_with(&sync_dir) =>
+                {
+                    let _span = unit_span(&name).entered();
+                    tracing::info!(
+                        "{} overrides the synced unit file {}",
+                        existing.display(),
+                        path.display()
+                    );
                 }
+                Some((existing, _)) => {
+                    let error = ProcessManagerError::DuplicateUnit {
+                        name: name.clone(),
+                        existing: existing.clone(),
+                    };
 
-                if is_invalid_simple_service {
-                    return Err(ProcessManagerError::InvalidSimpleService);
+                    load_errors.push(UnitLoadError::new(name, path, error));
                 }
             }
+        }
 
-            let home_dir = dirs::home_dir()
-                .expect("could not find home dir")
-                .to_str()
-                .unwrap()
-                .to_string();
+        Ok((definitions, load_errors))
+    }
 
-            if let Some(working_directory) = definition.service.working_directory.as_mut() {
-                let stringified = working_directory.to_string_lossy();
-                let stringified = stringified.replace("$USERPROFILE", &home_dir);
-                let directory = PathBuf::from(stringified);
+    pub fn load_units(&mut self, path: Option<PathBuf>) -> Result<(), ProcessManagerError> {
+        let (units, load_errors) = Self::parse_units(path, self.strict)?;
+        let (paths, definitions): (Vec<_>, Vec<_>) = units.into_iter().unzip();
 
-                *working_directory = directory;
-            }
+        self.load_errors = load_errors;
 
-            if let Some(environment_file) = &definition.service.environment_file {
-                let stringified = environment_file.to_string_lossy();
-                let stringified = stringified.replace("$USERPROFILE", &home_dir);
-                let environment_file = PathBuf::from(stringified);
+        prefetch(&definitions);
 
-                if let Ok(environment) =
-                    serde_envfile::from_file::<serde_envfile::Value>(&environment_file)
-                {
-                    for (k, v) in environment.iter() {
-                        match &mut definition.service.environment {
-                            None => {
-                                definition.service.environment = Some(vec![(k.clone(), v.clone())])
-                            }
-                            Some(e) => {
-                                e.push((k.clone(), v.clone()));
-                            }
-                        }
-                    }
-                }
+        for (path, definition) in paths.into_iter().zip(definitions) {
+            let name = definition.unit.name.clone();
+            let _span = unit_span(&name).entered();
+            match Self::prepare_definition(definition) {
+                Ok(definition) => self.register(definition),
+                Err(error) => self.load_errors.push(UnitLoadError::new(name, path, error)),
             }
+        }
 
-            for (_, value) in definition.service.environment.iter_mut().flatten() {
-                *value = value.replace("$USERPROFILE", &home_dir);
-            }
+        for error in &self.load_errors {
+            let _span = unit_span(&error.name).entered();
+            tracing::error!(
+                "failed to load unit from {}: {}",
+                error.path.display(),
+                error.error
+            );
+        }
 
-            for cmd in definition.service.exec_start_pre.iter_mut().flatten() {
-                cmd.resolve_user_profile();
-            }
+        self.load_warnings =
+            validate_definitions(&self.definitions.values().cloned().collect::<Vec<_>>());
 
-            definition.service.exec_start.resolve_user_profile();
+        for warning in &self.load_warnings {
+            tracing::warn!("{warning}");
+        }
 
-            for cmd in definition.service.exec_start_post.iter_mut().flatten() {
-                cmd.resolve_user_profile();
-            }
+        Ok(())
+    }
 
-            for cmd in definition.service.exec_stop.iter_mut().flatten() {
-                cmd.resolve_user_profile();
-            }
+    fn parse_unit(path: &Path, strict: bool) -> Result<Definition, ProcessManagerError> {
+        Self::parse_unit_contents(path, &std::fs::read_to_string(path)?, strict)
+    }
 
-            for cmd in definition.service.exec_stop_post.iter_mut().flatten() {
-                cmd.resolve_user_profile();
+    /// Parses the contents of a unit file, where the extension of the path decides whether the
+    /// contents are parsed as JSON or TOML
+    fn parse_unit_contents(
+        path: &Path,
+        contents: &str,
+        strict: bool,
+    ) -> Result<Definition, ProcessManagerError> {
+        let mut unknown_fields = vec![];
+
+        // $schema is only used by editors and is never deserialized
+        let mut track_unknown_field = |field: serde_ignored::Path| {
+            let field = field.to_string();
+            if field != "$schema" {
+                unknown_fields.push(field);
             }
+        };
 
-            if definition
-                .service
-                .exec_start
-                .executable
-                .pathbuf()?
-                .canonicalize()
-                .is_err()
-            {
-                match Self::find_exe(&definition.service.exec_start.executable.pathbuf()?) {
-                    Some(path) => {
-                        definition.service.exec_start.executable = Executable::Local(path)
-                    }
-                    None => {
-                        tracing::warn!(
-                            "{}: could not find executable in $PATH, skipping unit",
-                            definition.unit.name
-                        );
-                        continue;
-                    }
-                }
-            }
+        let is_json = path.extension() == Some(OsStr::new("json"));
 
-            for command in definition.service.exec_start_pre.iter_mut().flatten() {
-                if command.executable.pathbuf()?.canonicalize().is_err() {
-                    match Self::find_exe(&command.executable.pathbuf()?) {
-                        Some(path) => command.executable = Executable::Local(path),
-                        None => {
-                            tracing::warn!(
-                            "{}: could not find pre-start command executable in $PATH, skipping unit",
-                            definition.unit.name
-                        );
-                            continue;
-                        }
-                    }
-                }
-            }
+        // unit files are read as untyped values first to check their format version, and are
+        // only deserialized from those values when they had to be migrated, as parse errors can
+        // then no longer point at a position in the file
+        let value = if is_json {
+            serde_json::from_str::<serde_json::Value>(contents).ok()
+        } else {
+            toml::from_str::<toml::Value>(contents)
+                .ok()
+                .and_then(|value| serde_json::to_value(value).ok())
+        };
 
-            for command in definition.service.exec_start_post.iter_mut().flatten() {
-                if command.executable.pathbuf()?.canonicalize().is_err() {
-                    match Self::find_exe(&command.executable.pathbuf()?) {
-                        Some(path) => command.executable = Executable::Local(path),
-                        None => {
-                            tracing::warn!(
-                            "{}: could not find post-start command executable in $PATH, skipping unit",
-                            definition.unit.name
-                        );
-                            continue;
-                        }
-                    }
-                }
-            }
+        let mut deprecations = vec![];
+        let mut migrated = None;
 
-            for command in definition.service.exec_stop.iter_mut().flatten() {
-                if command.executable.pathbuf()?.canonicalize().is_err() {
-                    match Self::find_exe(&command.executable.pathbuf()?) {
-                        Some(path) => command.executable = Executable::Local(path),
-                        None => {
-                            tracing::warn!(
-                            "{}: could not find shutdown command executable in $PATH, skipping unit",
-                            definition.unit.name
-                        );
-                            continue;
-                        }
-                    }
-                }
+        if let Some(mut value) = value {
+            let version = format_version(&value);
+            if version > CURRENT_FORMAT_VERSION {
+                return Err(ProcessManagerError::UnsupportedFormatVersion {
+                    version,
+                    current: CURRENT_FORMAT_VERSION,
+                });
             }
 
-            for command in definition.service.exec_stop_post.iter_mut().flatten() {
-                if command.executable.pathbuf()?.canonicalize().is_err() {
-                    match Self::find_exe(&command.executable.pathbuf()?) {
-                        Some(path) => command.executable = Executable::Local(path),
-                        None => {
-                            tracing::warn!(
-                            "{}: could not find cleanup command executable in $PATH, skipping unit",
-                            definition.unit.name
-                        );
-                            continue;
-                        }
-                    }
-                }
+            deprecations = migrate(&mut value);
+            if !deprecations.is_empty() {
+                migrated = Some(value);
             }
+        }
 
-            if matches!(definition.service.kind, ServiceKind::Simple)
-                && definition.service.healthcheck.is_none()
-            {
-                definition.service.healthcheck = Some(Healthcheck::default());
-            }
+        let mut definition: Definition = if let Some(value) = migrated {
+            serde_ignored::deserialize(value, &mut track_unknown_field)?
+        } else if is_json {
+            let to_diagnostic = |error| {
+                ProcessManagerError::Parse(ParseDiagnostic::from_json(path, contents, &error))
+            };
 
-            if matches!(definition.service.kind, ServiceKind::Oneshot)
-                && definition.service.healthcheck.is_some()
-            {
-                definition.service.healthcheck = None;
-            }
+            let mut deserializer = serde_json::Deserializer::from_str(contents);
+            let definition: Definition =
+                serde_ignored::deserialize(&mut deserializer, &mut track_unknown_field)
+                    .map_err(to_diagnostic)?;
+            deserializer.end().map_err(to_diagnostic)?;
+            definition
+        } else {
+            serde_ignored::deserialize(toml::Deserializer::new(contents), &mut track_unknown_field)
+                .map_err(|error| {
+                    ProcessManagerError::Parse(ParseDiagnostic::from_toml(path, contents, &error))
+                })?
+        };
 
-            if let Some(Healthcheck::Command(command)) = &mut definition.service.healthcheck {
-                command.resolve_user_profile();
+        if strict && !unknown_fields.is_empty() {
+            return Err(ProcessManagerError::UnknownFields(
+                unknown_fields.join(", "),
+            ));
+        }
 
-                if command.executable.canonicalize().is_err() {
-                    match Self::find_exe(&command.executable) {
-                        Some(path) => command.executable = path,
-                        None => {
-                            tracing::warn!(
-                            "{}: could not find healthcheck command executable in $PATH, skipping unit",
-                            definition.unit.name
-                        );
-                            continue;
-                        }
-                    }
-                }
+        definition.deprecations = deprecations;
+
+        Ok(definition)
+    }
+
+    /// Downloads a unit file and writes it to the unit directory under its unit name, after
+    /// checking that it parses and that its executables and resources can be resolved
+    ///
+    /// A unit defined by another file in the unit directory is never replaced, and an existing
+    /// file with the same name is only replaced when `force` is set
+    pub fn add_unit(url: &str, strict: bool, force: bool) -> Result<PathBuf, ProcessManagerError> {
+        let unsupported = || ProcessManagerError::UnsupportedUnitUrl(url.to_string());
+        let parsed = Url::parse(url).map_err(|_| unsupported())?;
+
+        let extension = match Path::new(parsed.path()).extension() {
+            Some(extension) if extension == "json" || extension == "toml" => {
+                extension.to_string_lossy().to_string()
             }
+            _ => return Err(unsupported()),
+        };
 
-            self.register(definition);
-        }
+        let contents = String::from_utf8(fetch_bytes(&parsed)?)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
 
-        Ok(())
-    }
+        // the format is inferred from the extension, which a query string would otherwise end up
+        // in, and parse errors shouldn't show tokens passed in the query string either
+        let mut source = parsed.clone();
+        source.set_query(None);
+        source.set_fragment(None);
 
-    pub fn register(&mut self, definition: Definition) {
+        let definition = Self::parse_unit_contents(Path::new(source.as_str()), &contents, strict)?;
         let name = definition.unit.name.clone();
-        self.definitions
-            .insert(definition.unit.name.clone(), definition);
-        tracing::info!("{name}: registered unit");
+
+        validate_unit_name(&name).map_err(|reason| ProcessManagerError::InvalidUnitName {
+            name: name.clone(),
+            reason,
+        })?;
+
+        let unit_dir = Self::unit_directory();
+        let target = unit_dir.join(format!("{name}.{extension}"));
+
+        // synced units are not considered, as local unit files take precedence over them
+        let (units, _) = Self::parse_units(Some(unit_dir.clone()), false)?;
+        let existing = units
+            .into_iter()
+            .find(|(path, existing)| existing.unit.name == name && path.parent() == Some(&unit_dir))
+            .map(|(path, _)| path)
+            .or_else(|| target.is_file().then(|| target.clone()));
+
+        if let Some(existing) = existing {
+            if existing != target || !force {
+                return Err(ProcessManagerError::DuplicateUnit { name, existing });
+            }
+        }
+
+        Self::prepare_definition(definition)?;
+
+        std::fs::write(&target, contents)?;
+
+        Ok(target)
     }
 
-    pub fn start(&mut self, name: &str) -> Result<Arc<SharedChild>, ProcessManagerError> {
-        let definition = self
-            .definitions
-            .get(name)
-            .cloned()
+    /// Masks a unit by appending .disabled to its unit file, so that it is no longer loaded until
+    /// it is unmasked again
+    ///
+    /// Returns the path of the masked unit file
+    pub fn mask(name: &str) -> Result<PathBuf, ProcessManagerError> {
+        let unit_dir = Self::unit_directory();
+        let (units, load_errors) = Self::parse_units(Some(unit_dir.clone()), false)?;
+
+        // units which fail to load can be masked too, by the name of their unit file
+        let path = units
+            .into_iter()
+            .find(|(_, definition)| definition.unit.name == name)
+            .map(|(path, _)| path)
+            .or_else(|| {
+                load_errors
+                    .into_iter()
+                    .find(|error| error.is_for(name))
+                    .map(|error| error.path)
+            })
             .ok_or(ProcessManagerError::UnregisteredUnit(name.to_string()))?;
 
-        if self.running.lock().contains_key(name) {
-            return Err(ProcessManagerError::RunningUnit(name.to_string()));
+        if path.starts_with(unit_dir.join(SYNC_DIRECTORY)) {
+            return Err(ProcessManagerError::SyncedUnit(path));
         }
 
-        if self.completed.lock().contains_key(name) {
-            return Err(ProcessManagerError::CompletedUnit(name.to_string()));
-        }
+        let mut masked = path.clone().into_os_string();
+        masked.push(format!(".{MASKED_EXTENSION}"));
+        let masked = PathBuf::from(masked);
 
-        self.failed.lock().remove(name);
-        self.terminated.lock().remove(name);
+        std::fs::rename(&path, &masked)?;
 
-        for dep in definition.unit.requires.iter().flatten() {
-            tracing::info!("{name}: requires {dep}");
-            let dependency = self
-                .definitions
-                .get(dep)
+        Ok(masked)
+    }
+
+    /// Unmasks a unit masked with [`ProcessManager::mask`] by removing .disabled from its unit
+    /// file again
+    ///
+    /// Returns the path of the unmasked unit file
+    pub fn unmask(name: &str) -> Result<PathBuf, ProcessManagerError> {
+        let unit_dir = Self::unit_directory();
+
+        let mut masked = std::fs::read_dir(&unit_dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension() == Some(OsStr::new(MASKED_EXTENSION)))
+            .collect::<Vec<_>>();
+
+        masked.sort();
+
+        for path in masked {
+            let unmasked = path.with_extension("");
+
+            let matches = unmasked
+                .file_stem()
+                .is_some_and(|stem| stem == OsStr::new(name))
+                || std::fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|contents| {
+                        Self::parse_unit_contents(&unmasked, &contents, false).ok()
+                    })
+                    .is_some_and(|definition| definition.unit.name == name);
+
+            if !matches {
+                continue;
+            }
+
+            if unmasked.exists() {
+                return Err(ProcessManagerError::DuplicateUnit {
+                    name: name.to_string(),
+                    existing: unmasked,
+                });
+            }
+
+            std::fs::rename(&path, &unmasked)?;
+
+            return Ok(unmasked);
+        }
+
+        Err(ProcessManagerError::NoMaskedUnit(name.to_string()))
+    }
+
+    /// Validates a parsed unit definition and resolves its paths, executables and resources
+    fn prepare_definition(mut definition: Definition) -> Result<Definition, ProcessManagerError> {
+        definition.resolve_resources()?;
+        definition.materialize_script()?;
+
+        if matches!(definition.service.kind, ServiceKind::Forking) {
+            let mut is_valid_forking_service = false;
+            if let Some(Healthcheck::Process(proc)) = &definition.service.healthcheck {
+                if proc.target.is_some() || proc.pid_file.is_some() {
+                    is_valid_forking_service = true;
+                }
+            }
+
+            if !is_valid_forking_service {
+                return Err(ProcessManagerError::InvalidForkingService);
+            }
+        }
+
+        if matches!(
+            definition.service.on_existing_process,
+            OnExistingProcess::Adopt
+        ) && definition.service.relays_output()
+        {
+            return Err(ProcessManagerError::RelayedOutput(
+                definition.unit.name.clone(),
+            ));
+        }
+
+        if let Some(Healthcheck::Process(proc)) = &definition.service.healthcheck {
+            if let Some(pattern) = &proc.command_line {
+                Regex::new(pattern)?;
+            }
+        }
+
+        if matches!(definition.service.kind, ServiceKind::Simple) {
+            let mut is_invalid_simple_service = false;
+            if let Some(Healthcheck::Process(proc)) = &definition.service.healthcheck {
+                if proc.target.is_some() || proc.pid_file.is_some() {
+                    is_invalid_simple_service = true;
+                }
+            }
+
+            if is_invalid_simple_service {
+                return Err(ProcessManagerError::InvalidSimpleService);
+            }
+        }
+
+        let home_dir = dirs::home_dir()
+            .expect("could not find home dir")
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        if let Some(working_directory) = definition.service.working_directory.as_mut() {
+            let stringified = working_directory.to_string_lossy();
+            let stringified = stringified.replace("$USERPROFILE", &home_dir);
+            let directory = PathBuf::from(stringified);
+
+            *working_directory = directory;
+        }
+
+        if let Some(environment_file) = &definition.service.environment_file {
+            let stringified = environment_file.to_string_lossy();
+            let stringified = stringified.replace("$USERPROFILE", &home_dir);
+            let environment_file = PathBuf::from(stringified);
+
+            if let Ok(environment) =
+                serde_envfile::from_file::<serde_envfile::Value>(&environment_file)
+            {
+                let from_file = environment
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect::<Vec<_>>();
+
+                definition.service.environment = Some(merge_environment([
+                    Some(from_file.as_slice()),
+                    definition.service.environment.as_deref(),
+                ]));
+            }
+        }
+
+        for (_, value) in definition.service.environment.iter_mut().flatten() {
+            *value = value.replace("$USERPROFILE", &home_dir);
+        }
+
+        for path in definition
+            .path
+            .iter_mut()
+            .flat_map(|trigger| &mut trigger.paths)
+        {
+            *path = PathBuf::from(path.to_string_lossy().replace("$USERPROFILE", &home_dir));
+        }
+
+        for cmd in definition.service.exec_start_pre.iter_mut().flatten() {
+            cmd.resolve_shell("pre-start command")?;
+            cmd.resolve_user_profile();
+        }
+
+        definition
+            .service
+            .exec_start
+            .resolve_shell("start command")?;
+        definition.service.exec_start.resolve_user_profile();
+
+        for cmd in definition.service.exec_start_post.iter_mut().flatten() {
+            cmd.resolve_shell("post-start command")?;
+            cmd.resolve_user_profile();
+        }
+
+        for cmd in definition.service.exec_stop.iter_mut().flatten() {
+            cmd.resolve_shell("shutdown command")?;
+            cmd.resolve_user_profile();
+        }
+
+        for cmd in definition.service.exec_stop_post.iter_mut().flatten() {
+            cmd.resolve_shell("cleanup command")?;
+            cmd.resolve_user_profile();
+        }
+
+        if !matches!(
+            definition.service.exec_start.executable,
+            Executable::Aumid(_)
+        ) && definition
+            .service
+            .exec_start
+            .executable
+            .pathbuf()?
+            .canonicalize()
+            .is_err()
+        {
+            match Self::find_exe(&definition.service.exec_start.executable.pathbuf()?) {
+                Some(path) => definition.service.exec_start.executable = Executable::Local(path),
+                None => {
+                    return Err(ProcessManagerError::MissingExecutable {
+                        kind: "start command",
+                        executable: definition.service.exec_start.executable.to_string(),
+                    });
+                }
+            }
+        }
+
+        if definition.service.resolve_shims {
+            definition.service.exec_start.resolve_shim();
+        }
+
+        for command in definition.service.exec_start_pre.iter_mut().flatten() {
+            if command.executable.pathbuf()?.canonicalize().is_err() {
+                match Self::find_exe(&command.executable.pathbuf()?) {
+                    Some(path) => command.executable = Executable::Local(path),
+                    None => {
+                        return Err(ProcessManagerError::MissingExecutable {
+                            kind: "pre-start command",
+                            executable: command.executable.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        for command in definition.service.exec_start_post.iter_mut().flatten() {
+            if command.executable.pathbuf()?.canonicalize().is_err() {
+                match Self::find_exe(&command.executable.pathbuf()?) {
+                    Some(path) => command.executable = Executable::Local(path),
+                    None => {
+                        return Err(ProcessManagerError::MissingExecutable {
+                            kind: "post-start command",
+                            executable: command.executable.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        for command in definition.service.exec_stop.iter_mut().flatten() {
+            if command.executable.pathbuf()?.canonicalize().is_err() {
+                match Self::find_exe(&command.executable.pathbuf()?) {
+                    Some(path) => command.executable = Executable::Local(path),
+                    None => {
+                        return Err(ProcessManagerError::MissingExecutable {
+                            kind: "shutdown command",
+                            executable: command.executable.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        for command in definition.service.exec_stop_post.iter_mut().flatten() {
+            if command.executable.pathbuf()?.canonicalize().is_err() {
+                match Self::find_exe(&command.executable.pathbuf()?) {
+                    Some(path) => command.executable = Executable::Local(path),
+                    None => {
+                        return Err(ProcessManagerError::MissingExecutable {
+                            kind: "cleanup command",
+                            executable: command.executable.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        if matches!(definition.service.kind, ServiceKind::Simple)
+            && definition.service.healthcheck.is_none()
+        {
+            definition.service.healthcheck = Some(Healthcheck::default());
+        }
+
+        if matches!(definition.service.kind, ServiceKind::Oneshot)
+            && definition.service.healthcheck.is_some()
+        {
+            definition.service.healthcheck = None;
+        }
+
+        if let Some(Healthcheck::Command(command)) = &mut definition.service.healthcheck {
+            command.resolve_user_profile();
+
+            if command.executable.canonicalize().is_err() {
+                match Self::find_exe(&command.executable) {
+                    Some(path) => command.executable = path,
+                    None => {
+                        return Err(ProcessManagerError::MissingExecutable {
+                            kind: "healthcheck command",
+                            executable: command.executable.display().to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(definition)
+    }
+
+    pub fn load_errors(&self) -> &[UnitLoadError] {
+        &self.load_errors
+    }
+
+    pub fn load_warnings(&self) -> &[UnitWarning] {
+        &self.load_warnings
+    }
+
+    pub fn register(&mut self, definition: Definition) {
+        let name = definition.unit.name.clone();
+        let _span = unit_span(&name).entered();
+        self.definitions
+            .insert(definition.unit.name.clone(), definition);
+        tracing::info!("registered unit");
+    }
+
+    /// Starts units concurrently in waves, where each wave holds the units whose dependencies
+    /// have already been started, returning the first error after attempting every unit
+    pub fn start_all(&self, names: &[String]) -> Result<(), ProcessManagerError> {
+        match self.start_waves(names)?.into_iter().next() {
+            None => Ok(()),
+            Some((_, error)) => Err(error),
+        }
+    }
+
+    /// Starts units like [`ProcessManager::start_all`], returning the result for each of them
+    pub fn start_each(&self, names: &[String]) -> Vec<UnitResult> {
+        let mut failures = vec![];
+
+        // units which are (or require) unregistered units can't be started, but shouldn't keep
+        // the other units from being started
+        let registered = names
+            .iter()
+            .filter(
+                |name| match self.with_dependencies(std::slice::from_ref(*name)) {
+                    Ok(_) => true,
+                    Err(error) => {
+                        failures.push(((*name).clone(), error));
+                        false
+                    }
+                },
+            )
+            .cloned()
+            .collect::<Vec<_>>();
+
+        match self.start_waves(&registered) {
+            Ok(errors) => failures.extend(errors),
+            Err(error) => tracing::error!("{error}"),
+        }
+
+        unit_results(names, failures)
+    }
+
+    /// Starts units in waves, returning the units which failed to start in the order they failed
+    fn start_waves(
+        &self,
+        names: &[String],
+    ) -> Result<Vec<(String, ProcessManagerError)>, ProcessManagerError> {
+        let mut remaining = self
+            .with_dependencies(names)?
+            .into_iter()
+            .filter(|name| {
+                names.contains(name)
+                    || !(self.running.lock().contains_key(name)
+                        || self.completed.lock().contains_key(name))
+            })
+            .collect::<HashSet<_>>();
+
+        let mut failed = HashSet::new();
+        let mut errors = vec![];
+
+        while !remaining.is_empty() {
+            // units requiring a unit which failed to start are not attempted, so that
+            // units sharing a failed dependency don't try to start it at the same time
+            while let Some((name, dependency)) = remaining.iter().find_map(|name| {
+                self.definitions[name]
+                    .unit
+                    .requires
+                    .iter()
+                    .flatten()
+                    .find(|dependency| failed.contains(*dependency))
+                    .map(|dependency| (name.clone(), dependency.clone()))
+            }) {
+                let error = ProcessManagerError::DependencyFailed {
+                    name: name.clone(),
+                    dependency: dependency.clone(),
+                };
+
+                journal::record(
+                    &name,
+                    UnitEvent::DependencyFailed {
+                        dependency,
+                        error: error.to_string(),
+                    },
+                );
+
+                tracing::error!("{error}");
+                errors.push((name.clone(), error));
+                remaining.remove(&name);
+                failed.insert(name);
+            }
+
+            let mut wave = remaining
+                .iter()
+                .filter(|name| {
+                    !self.definitions[*name]
+                        .unit
+                        .requires
+                        .iter()
+                        .flatten()
+                        .any(|dependency| remaining.contains(dependency))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+
+            // dependency cycles are started one unit at a time
+            if wave.is_empty() {
+                wave.extend(remaining.iter().next().cloned());
+            }
+
+            for name in &wave {
+                remaining.remove(name);
+            }
+
+            let results = std::thread::scope(|scope| {
+                wave.iter()
+                    .map(|name| scope.spawn(move || (name, self.start(name))))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .filter_map(|handle| handle.join().ok())
+                    .collect::<Vec<_>>()
+            });
+
+            for (name, result) in results {
+                if let Err(error) = result {
+                    tracing::error!("{error}");
+                    failed.insert(name.clone());
+                    errors.push((name.clone(), error));
+                }
+            }
+        }
+
+        Ok(errors)
+    }
+
+    pub fn start(&self, name: &str) -> Result<(), ProcessManagerError> {
+        self.start_unit(name, true)
+    }
+
+    /// Starts units without starting the units they require, for when a dependency has been
+    /// started outside of wpm (for example in a debugger)
+    pub fn start_without_dependencies(&self, names: &[String]) -> Result<(), ProcessManagerError> {
+        for name in names {
+            self.start_unit(name, false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts units like [`ProcessManager::start_without_dependencies`], returning the result
+    /// for each of them
+    pub fn start_each_without_dependencies(&self, names: &[String]) -> Vec<UnitResult> {
+        let failures = names
+            .iter()
+            .filter_map(|name| {
+                self.start_unit(name, false)
+                    .err()
+                    .map(|error| (name.clone(), error))
+            })
+            .collect();
+
+        unit_results(names, failures)
+    }
+
+    /// Stops units, or force stops them, returning the result for each of them
+    pub fn stop_each(&mut self, names: &[String], force: bool) -> Vec<UnitResult> {
+        let mut failures = vec![];
+
+        for name in names {
+            let result = if force {
+                self.force_stop(name)
+            } else {
+                self.stop(name)
+            };
+
+            if let Err(error) = result {
+                tracing::error!("{error}");
+                failures.push((name.clone(), error));
+            }
+        }
+
+        unit_results(names, failures)
+    }
+
+    fn start_unit(&self, name: &str, start_dependencies: bool) -> Result<(), ProcessManagerError> {
+        let _span = unit_span(name).entered();
+        let definition = self
+            .definitions
+            .get(name)
+            .cloned()
+            .ok_or(ProcessManagerError::UnregisteredUnit(name.to_string()))?;
+
+        // a retry after StartRetrySec carries on counting the attempts of the same start
+        let retry = self.start_retries.lock().remove(name);
+
+        if self.running.lock().contains_key(name) {
+            return Err(ProcessManagerError::RunningUnit(name.to_string()));
+        }
+
+        if self.completed.lock().contains_key(name) {
+            return Err(ProcessManagerError::CompletedUnit(name.to_string()));
+        }
+
+        self.failed.lock().remove(name);
+        self.terminated.lock().remove(name);
+
+        if retry.is_none() {
+            journal::record(name, UnitEvent::StartRequested);
+        }
+
+        let mut pending_oneshots = vec![];
+
+        let requires = if start_dependencies {
+            definition.unit.requires.clone().unwrap_or_default()
+        } else {
+            if definition.unit.requires.is_some() {
+                tracing::info!("not starting required units");
+            }
+
+            vec![]
+        };
+
+        for dep in &requires {
+            tracing::info!("requires {dep}");
+            let dependency = self
+                .definitions
+                .get(dep)
                 .cloned()
                 .ok_or(ProcessManagerError::UnregisteredUnit(dep.to_string()))?;
 
+            let is_oneshot = matches!(dependency.service.kind, ServiceKind::Oneshot);
+
+            // a oneshot dependency only has to have completed once
+            if is_oneshot && self.completed.lock().contains_key(dep) {
+                continue;
+            }
+
             if !self.running.lock().contains_key(&dependency.unit.name) {
-                self.start(&dependency.unit.name)?;
+                if let Err(error) = self.start(&dependency.unit.name) {
+                    journal::record(
+                        name,
+                        UnitEvent::DependencyFailed {
+                            dependency: dep.clone(),
+                            error: error.to_string(),
+                        },
+                    );
+
+                    return Err(error);
+                }
+            }
+
+            if is_oneshot && self.running.lock().contains_key(dep) {
+                pending_oneshots.push(dep.clone());
             }
         }
 
-        let mut retry_limit = definition.service.exec_start.retry_limit.unwrap_or(5);
-        let mut process_id = None;
-        while retry_limit > 0 {
-            let id = definition.execute(
-                self.running.clone(),
-                self.completed.clone(),
-                self.terminated.clone(),
-            )?;
+        if !pending_oneshots.is_empty() {
+            self.start_after_oneshots(name, pending_oneshots);
+            return Ok(());
+        }
+
+        for service in definition.unit.requires_windows_service.iter().flatten() {
+            tracing::info!("requires windows service {service}");
+            if let Err(error) = CurrentPlatform::ensure_service_running(service) {
+                let error = ProcessManagerError::WindowsService {
+                    name: service.clone(),
+                    error,
+                };
+
+                journal::record(name, UnitEvent::StartFailed(error.to_string()));
+                return Err(error);
+            }
+        }
+
+        match definition.service.on_existing_process {
+            OnExistingProcess::Ignore => {}
+            OnExistingProcess::Fail => {
+                if let Some(pid) = self.untracked_processes(&definition).first() {
+                    let error = ProcessManagerError::ExistingProcess {
+                        name: name.to_string(),
+                        pid: *pid,
+                    };
+
+                    journal::record(name, UnitEvent::StartFailed(error.to_string()));
+                    return Err(error);
+                }
+            }
+            OnExistingProcess::Adopt => {
+                if let Some(pid) = self.untracked_processes(&definition).first() {
+                    self.track_existing(&definition, *pid);
+                    return Ok(());
+                }
+            }
+            OnExistingProcess::Kill => {
+                // unlike the other policies, processes are never matched by the file name of
+                // their executable alone, which could kill unrelated processes
+                let tracked = self.tracked_processes();
+                for pid in definition
+                    .orphaned_processes()
+                    .into_iter()
+                    .filter(|pid| !tracked.contains(pid))
+                {
+                    tracing::info!("killing existing process {pid}");
+                    if !kill_process(pid) {
+                        tracing::warn!("could not kill existing process {pid}");
+                    }
+
+                    // the new process could otherwise race the old one for its ports and files
+                    if !wait_for_exit(pid, KILLED_PROCESS_EXIT_TIMEOUT) {
+                        let error = ProcessManagerError::SurvivingProcess {
+                            name: name.to_string(),
+                            pid,
+                        };
+
+                        journal::record(name, UnitEvent::StartFailed(error.to_string()));
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        let _slot = startup_slots::acquire(name);
+
+        // a unit is always started at least once, even with a RetryLimit of 0
+        let attempts = definition
+            .service
+            .exec_start
+            .retry_limit
+            .unwrap_or(5)
+            .max(1);
+        let retry_sec = definition.service.start_retry_sec.unwrap_or(0);
+
+        for attempt in retry.unwrap_or(1)..=attempts {
+            let id = definition
+                .execute(
+                    self.running.clone(),
+                    self.completed.clone(),
+                    self.failed.clone(),
+                    self.terminated.clone(),
+                )
+                .inspect_err(|error| {
+                    journal::record(name, UnitEvent::StartFailed(error.to_string()));
+                })?;
 
             match definition.healthcheck(
-                id.clone(),
+                id,
                 self.running.clone(),
                 self.failed.clone(),
                 self.terminated.clone(),
             ) {
                 Ok(_) => {
-                    process_id = Some(id);
+                    if let Some(proc_state) = self.running.lock().get(name) {
+                        journal::record(name, UnitEvent::Started(proc_state.child.id()));
+                        state_hooks::unit_started(name);
+                    }
+
                     break;
                 }
                 Err(error) => {
-                    retry_limit -= 1;
-                    if retry_limit == 0 {
+                    if let ProcessManagerError::FailedHealthcheck(_, failure) = &error {
+                        journal::record(name, UnitEvent::HealthcheckFailed(failure.clone()));
+                    } else {
+                        journal::record(name, UnitEvent::StartFailed(error.to_string()));
+                    }
+
+                    if attempt == attempts {
+                        if matches!(error, ProcessManagerError::FailedHealthcheck(..))
+                            && definition
+                                .service
+                                .restart
+                                .should_restart(&ExitReason::FailedHealthcheck(None))
+                            && restart_budget::allow_restart(name)
+                        {
+                            schedule_restart(
+                                name.to_string(),
+                                definition.service.restart_sec.unwrap_or(1),
+                            );
+                        }
+
                         return Err(error);
                     }
+
+                    tracing::warn!(
+                        "start attempt {attempt} of {attempts} failed, retrying in {retry_sec}s"
+                    );
+
+                    journal::record(
+                        name,
+                        UnitEvent::StartRetry {
+                            attempt,
+                            attempts,
+                            delay_sec: retry_sec,
+                        },
+                    );
+
+                    // the next attempt is left to wpmd so that it isn't blocked while waiting
+                    // the start hasn't failed yet, so the unit shows as stopped until the next
+                    // attempt rather than as failed
+                    if retry_sec > 0 {
+                        self.schedule_start_retry(name, attempt + 1, retry_sec);
+                        self.failed.lock().remove(name);
+                        self.terminated.lock().remove(name);
+                        tracing::info!("retry scheduled");
+                        return Ok(());
+                    }
                 }
             }
         }
 
-        #[allow(clippy::unwrap_used)]
-        Ok(process_id.unwrap())
+        Ok(())
     }
 
-    pub fn stop(&mut self, name: &str) -> Result<(), ProcessManagerError> {
-        let unit = self
+    /// Starts a unit again after StartRetrySec from a separate thread, unless it is stopped in
+    /// the meantime
+    fn schedule_start_retry(&self, name: &str, attempt: u8, retry_sec: u64) {
+        let name = name.to_string();
+        let start_retries = self.start_retries.clone();
+        start_retries.lock().insert(name.clone(), attempt);
+
+        std::thread::spawn(move || {
+            let _span = unit_span(&name).entered();
+            std::thread::sleep(Duration::from_secs(retry_sec));
+
+            if start_retries.lock().get(&name) != Some(&attempt) {
+                return;
+            }
+
+            if let Err(error) = send_message(
+                daemon_socket(),
+                SocketMessage::Scheduled(UnitAction::Start, vec![name.clone()]),
+            ) {
+                tracing::error!("{error}");
+            }
+        });
+    }
+
+    /// Starts tracking an already running process for a unit instead of starting a new one
+    pub fn adopt(&mut self, name: &str) -> Result<u32, ProcessManagerError> {
+        let _span = unit_span(name).entered();
+        let definition = self
             .definitions
             .get(name)
             .cloned()
             .ok_or(ProcessManagerError::UnregisteredUnit(name.to_string()))?;
 
-        let mut running = self.running.lock();
+        if self.running.lock().contains_key(name) {
+            return Err(ProcessManagerError::RunningUnit(name.to_string()));
+        }
 
-        let proc_state = running
-            .get(name)
-            .cloned()
-            .ok_or(ProcessManagerError::NotRunning(name.to_string()))?;
+        if definition.service.relays_output() {
+            return Err(ProcessManagerError::RelayedOutput(name.to_string()));
+        }
 
-        let id = proc_state.child.id();
+        let pid = self
+            .untracked_processes(&definition)
+            .first()
+            .copied()
+            .ok_or(ProcessManagerError::NoExistingProcess(name.to_string()))?;
 
-        tracing::info!("{name}: stopping unit");
+        self.track_existing(&definition, pid);
 
-        if let Some(shutdown_commands) = unit.service.exec_stop {
-            for command in shutdown_commands {
-                let stringified = if let Some(args) = &command.arguments {
-                    format!("{} {}", command.executable, args.join(" "))
-                } else {
-                    command.executable.to_string()
-                };
+        Ok(pid)
+    }
+
+    /// PIDs of the processes which are tracked by any unit
+    fn tracked_processes(&self) -> Vec<u32> {
+        self.running
+            .lock()
+            .values()
+            .map(|state| state.child.id())
+            .collect()
+    }
+
+    /// Running processes for a unit which aren't already tracked by another unit
+    fn untracked_processes(&self, definition: &Definition) -> Vec<u32> {
+        let tracked = self.tracked_processes();
+
+        definition
+            .existing_processes()
+            .into_iter()
+            .filter(|pid| !tracked.contains(pid))
+            .collect()
+    }
+
+    fn track_existing(&self, definition: &Definition, pid: u32) {
+        let name = &definition.unit.name;
+        tracing::info!("adopting running process {pid}");
+        journal::record(name, UnitEvent::Adopted(pid));
+
+        self.completed.lock().remove(name);
+        self.failed.lock().remove(name);
+        self.terminated.lock().remove(name);
+
+        self.running.lock().insert(
+            name.clone(),
+            ProcessState {
+                child: Child::Pid(pid),
+                timestamp: clock::now(),
+            },
+        );
+
+        definition.monitor_child(
+            Child::Pid(pid),
+            self.running.clone(),
+            self.terminated.clone(),
+        );
+    }
+
+    /// Oneshots are waited on outside of the thread handling socket messages, so units which
+    /// depend on oneshots that are still running are started once those oneshots complete
+    fn start_after_oneshots(&self, name: &str, oneshots: Vec<String>) {
+        let name = name.to_string();
+        let running = self.running.clone();
+        let completed = self.completed.clone();
+
+        tracing::info!(
+            "waiting for oneshot dependencies to complete - {}",
+            oneshots.join(", ")
+        );
 
-                tracing::info!("{name}: executing shutdown command - {stringified}");
-                let mut command = command.to_silent_command(unit.service.environment.clone());
-                command.output()?;
+        std::thread::spawn(move || {
+            let _span = unit_span(&name).entered();
+
+            while oneshots.iter().any(|dep| running.lock().contains_key(dep)) {
+                std::thread::sleep(Duration::from_millis(250));
             }
-        }
 
-        tracing::info!("{name}: sending kill signal to {id}");
+            if let Some(dep) = oneshots
+                .iter()
+                .find(|dep| !completed.lock().contains_key(*dep))
+            {
+                tracing::error!(
+                    "oneshot dependency {dep} did not complete successfully, not starting unit"
+                );
+                return;
+            }
 
-        // remove first to avoid race condition with the other child.wait()
-        // call spawned in a thread by Unit.execute()
-        let tmp_proc_state = running.remove(name).unwrap();
+            if let Err(error) = send_message(
+                daemon_socket(),
+                SocketMessage::Scheduled(UnitAction::Start, vec![name.clone()]),
+            ) {
+                tracing::error!("{error}");
+            }
+        });
+    }
 
-        if let Err(error) = proc_state.child.kill() {
-            // If there are any errors in killing the process, it's still considered to be running
-            // so we reinsert before returning the errors
-            running.insert(name.to_string(), tmp_proc_state);
-            return Err(error.into());
+    pub fn stop(&mut self, name: &str) -> Result<(), ProcessManagerError> {
+        self.stop_unit(name, false, true)
+    }
+
+    /// Stops a unit without running its ExecStop commands, killing its process immediately
+    pub fn force_stop(&mut self, name: &str) -> Result<(), ProcessManagerError> {
+        self.stop_unit(name, true, true)
+    }
+
+    /// Stops a unit if it is running and unregisters it, deleting its unit file, log and store
+    /// files as requested, and returns a line for everything which was done
+    pub fn remove(
+        &mut self,
+        name: &str,
+        options: &RemoveOptions,
+    ) -> Result<Vec<String>, ProcessManagerError> {
+        let _span = unit_span(name).entered();
+        let definition = self
+            .definitions
+            .get(name)
+            .cloned()
+            .ok_or(ProcessManagerError::UnregisteredUnit(name.to_string()))?;
+
+        let mut output = vec![];
+
+        if self.running.lock().contains_key(name) {
+            self.stop(name)?;
+            output.push(format!("Stopped {name}"));
         }
 
-        if let Err(error) = proc_state.child.wait() {
-            if matches!(error.kind(), std::io::ErrorKind::NotFound) {
-                tracing::warn!("{name}: process {id} not found; assuming successful termination");
-            } else {
-                running.insert(name.to_string(), tmp_proc_state);
-                return Err(error.into());
+        self.definitions.remove(name);
+        self.completed.lock().remove(name);
+        self.failed.lock().remove(name);
+        self.terminated.lock().remove(name);
+        tracing::info!("unregistered unit");
+        output.push(format!("Unregistered {name}"));
+
+        if !options.keep_file {
+            let (units, _) = Self::parse_units(None, false)?;
+            let sync_dir = Self::unit_directory().join(SYNC_DIRECTORY);
+
+            match units.iter().find(|(_, unit)| unit.unit.name == name) {
+                None => output.push(format!("Could not find the unit file of {name}")),
+                Some((path, _)) if path.starts_with(&sync_dir) => output.push(format!(
+                    "Kept {}, which is synced from a git repository and will be loaded again on the next reload",
+                    path.display()
+                )),
+                Some((path, _)) => {
+                    std::fs::remove_file(path)?;
+                    output.push(format!("Deleted {}", path.display()));
+                }
             }
         }
 
-        tracing::info!("{name}: process {id} successfully terminated");
+        if options.purge_logs {
+            let log_path = definition.log_path()?;
+            if log_path.is_file() {
+                std::fs::remove_file(&log_path)?;
+                output.push(format!("Deleted {}", log_path.display()));
+            }
+        }
 
-        if let Some(cleanup_commands) = unit.service.exec_stop_post {
-            for command in cleanup_commands {
-                let stringified = if let Some(args) = &command.arguments {
-                    format!("{} {}", command.executable, args.join(" "))
-                } else {
-                    command.executable.to_string()
-                };
+        if options.purge_store {
+            // remote executables and resources are stored by url, so they can be shared
+            let shared = self
+                .definitions
+                .values()
+                .flat_map(Definition::store_paths)
+                .collect::<HashSet<_>>();
+
+            for path in definition.store_paths() {
+                if !shared.contains(&path) && path.is_file() {
+                    std::fs::remove_file(&path)?;
+                    output.push(format!("Deleted {}", path.display()));
+                }
+            }
+        }
 
-                tracing::info!("{name}: executing cleanup command - {stringified}");
-                let mut command = command.to_silent_command(unit.service.environment.clone());
-                command.output()?;
+        for dependent in self.definitions.values() {
+            if dependent
+                .unit
+                .requires
+                .iter()
+                .flatten()
+                .any(|required| required == name)
+            {
+                output.push(format!("Warning: {} requires {name}", dependent.unit.name));
             }
         }
 
+        Ok(output)
+    }
+
+    /// Stops a unit, scheduling its automatic restart if it should be restarted `Always`, unless
+    /// `restart` is false because the caller is about to start it again itself
+    fn stop_unit(
+        &mut self,
+        name: &str,
+        force: bool,
+        restart: bool,
+    ) -> Result<(), ProcessManagerError> {
+        let _span = unit_span(name).entered();
+        let unit = self
+            .definitions
+            .get(name)
+            .cloned()
+            .ok_or(ProcessManagerError::UnregisteredUnit(name.to_string()))?;
+
+        self.start_retries.lock().remove(name);
+        stop_process(&unit, &self.running, force)?;
+        journal::record(
+            name,
+            if force {
+                UnitEvent::ForceStopped
+            } else {
+                UnitEvent::Stopped
+            },
+        );
+
         let thread_name = name.to_string();
-        if matches!(unit.service.restart, RestartStrategy::Always) {
+        if restart && matches!(unit.service.restart, RestartStrategy::Always) {
+            journal::record(
+                name,
+                UnitEvent::RestartScheduled(unit.service.restart_sec.unwrap_or(1)),
+            );
+
             std::thread::spawn(move || {
+                let _span = unit_span(&thread_name).entered();
                 let restart_sec = unit.service.restart_sec.unwrap_or(1);
-                tracing::info!("{thread_name}: restarting terminated process in {restart_sec}s");
+                tracing::info!("restarting terminated process in {restart_sec}s");
                 std::thread::sleep(Duration::from_secs(restart_sec));
 
-                if let Err(error) =
-                    send_message("wpmd.sock", SocketMessage::Start(vec![thread_name.clone()]))
-                {
-                    tracing::error!("{thread_name}: {error}");
+                if let Err(error) = send_message(
+                    daemon_socket(),
+                    SocketMessage::Scheduled(UnitAction::Start, vec![thread_name.clone()]),
+                ) {
+                    tracing::error!("{error}");
                 }
             });
         }
@@ -628,23 +2168,198 @@ impl ProcessManager {
         Ok(())
     }
 
+    /// Path triggers of all registered units, with the unit each of them starts
+    pub fn path_triggers(&self) -> Vec<(String, PathTrigger)> {
+        self.definitions
+            .values()
+            .filter_map(|definition| {
+                let trigger = definition.path.clone()?;
+                let target = trigger
+                    .unit
+                    .clone()
+                    .unwrap_or_else(|| definition.unit.name.clone());
+
+                Some((target, trigger))
+            })
+            .collect()
+    }
+
+    /// Running units, with their memory and CPU thresholds
+    pub fn monitored_units(&self) -> Vec<MonitoredUnit> {
+        let running = self.running.lock();
+
+        self.definitions
+            .values()
+            .filter_map(|definition| {
+                let state = running.get(&definition.unit.name)?;
+
+                Some(MonitoredUnit {
+                    name: definition.unit.name.clone(),
+                    pid: state.child.id(),
+                    memory: definition.service.restart_on_memory_above.clone(),
+                    cpu: definition.service.alert_on_cpu_above.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Socket activations of all registered units, with the unit each of them starts
+    pub fn socket_activations(&self) -> Vec<(String, SocketActivation)> {
+        self.definitions
+            .values()
+            .filter_map(|definition| {
+                Some((definition.unit.name.clone(), definition.socket.clone()?))
+            })
+            .collect()
+    }
+
     pub fn reset(&mut self, name: &str) {
-        tracing::info!("{name}: resetting unit");
+        let _span = unit_span(name).entered();
+        tracing::info!("resetting unit");
         self.completed.lock().remove(name);
         self.failed.lock().remove(name);
         self.terminated.lock().remove(name);
+        self.start_retries.lock().remove(name);
+    }
+
+    /// Kills the running processes of a unit which aren't tracked by any unit, returning their
+    /// PIDs; only processes running the unit's exact executable and arguments are killed, see
+    /// [`Definition::orphaned_processes`]
+    pub fn kill_orphans(&self, name: &str) -> Result<Vec<u32>, ProcessManagerError> {
+        let _span = unit_span(name).entered();
+        let definition = self
+            .definitions
+            .get(name)
+            .ok_or(ProcessManagerError::UnregisteredUnit(name.to_string()))?;
+
+        let tracked = self.tracked_processes();
+
+        // a running unit's own process is tracked, so only its strays are found
+        let killed = definition
+            .orphaned_processes()
+            .into_iter()
+            .filter(|pid| !tracked.contains(pid))
+            .filter(|pid| {
+                let killed = kill_process(*pid);
+                if killed {
+                    tracing::info!("killed orphaned process {pid}");
+                } else {
+                    tracing::warn!("could not kill orphaned process {pid}");
+                }
+
+                killed
+            })
+            .collect();
+
+        Ok(killed)
     }
 
-    pub fn shutdown(&mut self) -> Result<(), ProcessManagerError> {
+    /// Orders units so that each unit is stopped before any of the given units which it requires
+    fn stop_order(&self, mut units: Vec<String>) -> Vec<String> {
+        let mut ordered = vec![];
+
+        while !units.is_empty() {
+            let index = units
+                .iter()
+                .position(|name| {
+                    !self
+                        .dependents(name)
+                        .iter()
+                        .any(|dependent| units.contains(dependent))
+                })
+                // dependency cycles are broken arbitrarily
+                .unwrap_or(0);
+
+            ordered.push(units.remove(index));
+        }
+
+        ordered
+    }
+
+    /// Stops all running units, dependents before their dependencies and independent units
+    /// concurrently, killing every unit which hasn't stopped once the timeout has elapsed
+    pub fn shutdown(&mut self, timeout: Duration) -> Result<(), ProcessManagerError> {
         tracing::info!("wpmd: shutting down process manager");
 
-        let mut units = vec![];
-        for unit in self.running.lock().keys() {
-            units.push(unit.clone());
+        let deadline = Instant::now() + timeout;
+
+        // kept so that units which are stuck in their shutdown commands can still be killed
+        let processes = self
+            .running
+            .lock()
+            .iter()
+            .map(|(name, state)| (name.clone(), state.child.clone()))
+            .collect::<HashMap<_, _>>();
+
+        let mut remaining = processes.keys().cloned().collect::<Vec<_>>();
+        let mut stopped = HashSet::new();
+
+        while !remaining.is_empty() && Instant::now() < deadline {
+            // units which aren't required by any other remaining unit can be stopped together
+            let (mut batch, mut rest): (Vec<_>, Vec<_>) =
+                remaining.iter().cloned().partition(|name| {
+                    !self
+                        .dependents(name)
+                        .iter()
+                        .any(|dependent| remaining.contains(dependent))
+                });
+
+            // dependency cycles are broken by stopping all of the remaining units together
+            if batch.is_empty() {
+                std::mem::swap(&mut batch, &mut rest);
+            }
+
+            let handles = batch
+                .into_iter()
+                .map(|name| {
+                    let unit = self.definitions.get(&name).cloned();
+                    let running = self.running.clone();
+                    let child = processes[&name].clone();
+                    let thread_name = name.clone();
+
+                    let handle = std::thread::spawn(move || {
+                        let _span = unit_span(&thread_name).entered();
+                        let result = match &unit {
+                            Some(unit) => stop_process(unit, &running, false),
+                            // units removed by a reload have no shutdown commands to run
+                            None => child.kill().map_err(ProcessManagerError::from),
+                        };
+
+                        if let Err(error) = result {
+                            tracing::warn!("{error}");
+                        }
+                    });
+
+                    (name, handle)
+                })
+                .collect::<Vec<_>>();
+
+            while handles.iter().any(|(_, handle)| !handle.is_finished())
+                && Instant::now() < deadline
+            {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+
+            for (name, handle) in handles {
+                if handle.is_finished() {
+                    stopped.insert(name);
+                }
+            }
+
+            remaining = rest;
         }
 
-        for unit in units {
-            self.stop(&unit)?;
+        // units which failed to stop are put back in the running map
+        for (name, child) in processes {
+            if !stopped.contains(&name) || self.running.lock().contains_key(&name) {
+                let _span = unit_span(&name).entered();
+                tracing::warn!("killing process {} which did not stop", child.id());
+                if let Err(error) = child.kill() {
+                    tracing::warn!("{error}");
+                }
+
+                self.running.lock().remove(&name);
+            }
         }
 
         Ok(())
@@ -667,6 +2382,206 @@ impl ProcessManager {
         dependents
     }
 
+    /// The given units and all of the units they require, directly or transitively
+    pub fn with_dependencies(
+        &self,
+        names: &[String],
+    ) -> Result<HashSet<String>, ProcessManagerError> {
+        let mut units = HashSet::new();
+        let mut queue = names.to_vec();
+
+        while let Some(name) = queue.pop() {
+            let definition = self
+                .definitions
+                .get(&name)
+                .ok_or(ProcessManagerError::UnregisteredUnit(name.clone()))?;
+
+            if units.insert(name) {
+                queue.extend(definition.unit.requires.iter().flatten().cloned());
+            }
+        }
+
+        Ok(units)
+    }
+
+    /// Starts the given units and their dependencies, and stops every other running unit
+    pub fn isolate(&mut self, names: &[String]) -> Result<(), ProcessManagerError> {
+        let keep = self.with_dependencies(names)?;
+
+        let to_stop = self
+            .running
+            .lock()
+            .keys()
+            .filter(|name| !keep.contains(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        tracing::info!("isolating {}", names.join(", "));
+
+        for name in self.stop_order(to_stop) {
+            if let Err(error) = self.stop(&name) {
+                tracing::warn!("{error}");
+            }
+        }
+
+        let to_start = names
+            .iter()
+            .filter(|name| {
+                !(self.running.lock().contains_key(*name)
+                    || self.completed.lock().contains_key(*name))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.start_all(&to_start)
+    }
+
+    /// Running units which require any of the given units, directly or transitively
+    fn running_dependents(&self, names: &[String]) -> Vec<String> {
+        let mut dependents = vec![];
+        let mut queue = names.to_vec();
+
+        while let Some(name) = queue.pop() {
+            for dependent in self.dependents(&name) {
+                if !names.contains(&dependent)
+                    && !dependents.contains(&dependent)
+                    && self.running.lock().contains_key(&dependent)
+                {
+                    dependents.push(dependent.clone());
+                    queue.push(dependent);
+                }
+            }
+        }
+
+        dependents
+    }
+
+    /// Restarts units like [`ProcessManager::restart_each`], failing with the first unit which
+    /// could not be restarted
+    pub fn restart(
+        &mut self,
+        names: &[String],
+        with_dependents: bool,
+    ) -> Result<(), ProcessManagerError> {
+        let failure = self
+            .restart_each(names, with_dependents)
+            .into_iter()
+            .find_map(|result| Some((result.name, result.error?)));
+
+        match failure {
+            Some((name, error)) => Err(ProcessManagerError::Restart { name, error }),
+            None => Ok(()),
+        }
+    }
+
+    /// Restarts units, and if they propagate restarts, their running dependents, returning the
+    /// result for each of them along with whether it had to be stopped first; units which aren't
+    /// running are only started, and units which fail to stop aren't started again
+    pub fn restart_each(&mut self, names: &[String], with_dependents: bool) -> Vec<UnitResult> {
+        let propagating = names
+            .iter()
+            .filter(|name| {
+                with_dependents
+                    || self
+                        .definitions
+                        .get(*name)
+                        .is_some_and(|definition| definition.unit.restart_dependents)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let dependents = self.running_dependents(&propagating);
+        for dependent in &dependents {
+            let _span = unit_span(dependent).entered();
+            tracing::info!("restarting as a dependent of {}", names.join(", "));
+        }
+
+        let units = names.iter().cloned().chain(dependents).collect::<Vec<_>>();
+
+        let mut stopped = HashSet::new();
+        let mut failures = vec![];
+        for name in self.stop_order(units.clone()) {
+            if !self.running.lock().contains_key(&name) {
+                let _span = unit_span(&name).entered();
+                tracing::info!("not running, starting it without stopping it first");
+                continue;
+            }
+
+            match self.stop_unit(&name, false, false) {
+                Ok(()) => {
+                    stopped.insert(name);
+                }
+                Err(error) => failures.push((name, error)),
+            }
+        }
+
+        let startable = units
+            .iter()
+            .filter(|name| !failures.iter().any(|(failed, _)| failed == *name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut results = self.start_each(&startable);
+        for result in &mut results {
+            result.restart = Some(if stopped.contains(&result.name) {
+                RestartBranch::Restarted
+            } else {
+                RestartBranch::Started
+            });
+        }
+
+        let failed = failures
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        results.extend(unit_results(&failed, failures));
+        results.sort_by_key(|result| units.iter().position(|name| *name == result.name));
+
+        results
+    }
+
+    /// Saves the names of the running units as a snapshot which can be restored later
+    pub fn save_snapshot(&self, name: &str) -> Result<Snapshot, ProcessManagerError> {
+        let path = snapshot_path(name)?;
+
+        let mut units = self.running.lock().keys().cloned().collect::<Vec<_>>();
+        units.sort();
+
+        let snapshot = Snapshot {
+            timestamp: clock::now(),
+            units,
+        };
+
+        std::fs::write(&path, serde_json::to_string_pretty(&snapshot)?)?;
+        tracing::info!("saved snapshot {name} to {}", path.display());
+
+        Ok(snapshot)
+    }
+
+    /// Isolates the units in a snapshot, skipping units which are no longer registered
+    pub fn restore_snapshot(&mut self, name: &str) -> Result<Snapshot, ProcessManagerError> {
+        let path = snapshot_path(name)?;
+        if !path.is_file() {
+            return Err(ProcessManagerError::UnknownSnapshot(name.to_string()));
+        }
+
+        let mut snapshot = serde_json::from_str::<Snapshot>(&std::fs::read_to_string(&path)?)?;
+
+        snapshot.units.retain(|unit| {
+            let registered = self.definitions.contains_key(unit);
+            if !registered {
+                tracing::warn!("skipping {unit} from snapshot {name}, it is not a registered unit");
+            }
+
+            registered
+        });
+
+        tracing::info!("restoring snapshot {name}");
+        self.isolate(&snapshot.units)?;
+
+        Ok(snapshot)
+    }
+
     pub fn state(&self) -> ProcessManagerStatus {
         let mut units = vec![];
         let running = self.running.lock();
@@ -676,55 +2591,59 @@ impl ProcessManager {
 
         for (name, def) in &self.definitions {
             if let Some(proc_state) = running.get(name) {
-                let local: DateTime<Local> = DateTime::from(proc_state.timestamp);
-
                 units.push((
                     def.clone(),
                     UnitStatus {
                         name: name.clone(),
-                        kind: def.service.kind,
+                        kind: DisplayedOption(Some(def.service.kind)),
                         state: UnitState::Running,
                         pid: DisplayedOption(Some(proc_state.child.id())),
-                        timestamp: DisplayedOption(Some(local.to_string())),
+                        timestamp: DisplayedOption(Some(Timestamp(proc_state.timestamp))),
+                        reason: DisplayedOption(None),
+                        uptime: DisplayedOption(Some(Uptime(
+                            (clock::now() - proc_state.timestamp)
+                                .to_std()
+                                .unwrap_or_default(),
+                        ))),
                     },
                 ))
             } else if let Some(timestamp) = completed.get(name) {
-                let local: DateTime<Local> = DateTime::from(*timestamp);
-
                 units.push((
                     def.clone(),
                     UnitStatus {
                         name: name.clone(),
-                        kind: def.service.kind,
+                        kind: DisplayedOption(Some(def.service.kind)),
                         state: UnitState::Completed,
                         pid: DisplayedOption(None),
-                        timestamp: DisplayedOption(Some(local.to_string())),
+                        timestamp: DisplayedOption(Some(Timestamp(*timestamp))),
+                        reason: DisplayedOption(None),
+                        uptime: DisplayedOption(None),
                     },
                 ))
-            } else if let Some(timestamp) = failed.get(name) {
-                let local: DateTime<Local> = DateTime::from(*timestamp);
-
+            } else if let Some(exit) = failed.get(name) {
                 units.push((
                     def.clone(),
                     UnitStatus {
                         name: name.clone(),
-                        kind: def.service.kind,
+                        kind: DisplayedOption(Some(def.service.kind)),
                         state: UnitState::Failed,
                         pid: DisplayedOption(None),
-                        timestamp: DisplayedOption(Some(local.to_string())),
+                        timestamp: DisplayedOption(Some(Timestamp(exit.timestamp))),
+                        reason: DisplayedOption(Some(exit.reason.clone())),
+                        uptime: DisplayedOption(None),
                     },
                 ))
-            } else if let Some(timestamp) = terminated.get(name) {
-                let local: DateTime<Local> = DateTime::from(*timestamp);
-
+            } else if let Some(exit) = terminated.get(name) {
                 units.push((
                     def.clone(),
                     UnitStatus {
                         name: name.clone(),
-                        kind: def.service.kind,
+                        kind: DisplayedOption(Some(def.service.kind)),
                         state: UnitState::Terminated,
                         pid: DisplayedOption(None),
-                        timestamp: DisplayedOption(Some(local.to_string())),
+                        timestamp: DisplayedOption(Some(Timestamp(exit.timestamp))),
+                        reason: DisplayedOption(Some(exit.reason.clone())),
+                        uptime: DisplayedOption(None),
                     },
                 ))
             } else {
@@ -732,15 +2651,21 @@ impl ProcessManager {
                     def.clone(),
                     UnitStatus {
                         name: name.clone(),
-                        kind: def.service.kind,
-                        state: UnitState::Stopped,
+                        kind: DisplayedOption(Some(def.service.kind)),
+                        state: if self.load_errors.iter().any(|error| error.is_for(name)) {
+                            UnitState::LoadError
+                        } else {
+                            UnitState::Stopped
+                        },
                         pid: DisplayedOption(None),
                         timestamp: DisplayedOption(None),
+                        reason: DisplayedOption(None),
+                        uptime: DisplayedOption(None),
                     },
                 ))
             }
         }
 
-        ProcessManagerStatus(units)
+        ProcessManagerStatus(units, self.load_errors.clone(), self.load_warnings.clone())
     }
 }
@@ -1,22 +1,459 @@
+use crate::journal;
+use crate::journal::UnitEvent;
+use crate::process_manager::process_details;
 use crate::process_manager::ProcessManagerError;
+use crate::process_manager::UnitLoadError;
+use crate::restart_budget;
+use crate::unit::quote_arguments;
 use crate::unit::Definition;
+use crate::unit::Executable;
 use crate::unit::Healthcheck;
+use crate::unit::Service;
+use crate::unit::REDACTED;
+use crate::unit_status::DisplayedOption;
+use crate::unit_status::ExitReason;
+use crate::unit_status::StateColumn;
+use crate::unit_status::StateOptions;
 use crate::unit_status::UnitState;
+use crate::unit_status::UnitStateEntry;
 use crate::unit_status::UnitStatus;
-use tabled::Table;
+use crate::unit_status::Uptime;
+use crate::unit_status::MAX_STATE_LOG_LINES;
+use crate::usage_history;
+use crate::validation::UnitWarning;
+use crate::wpm_log_dir;
+use crate::wpm_store_dir;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::Duration;
+use tabled::builder::Builder;
 
-pub struct ProcessManagerStatus(pub Vec<(Definition, UnitStatus)>);
+/// Size of the chunks read backwards from the end of a log file when looking for recent lines
+const TAIL_CHUNK_SIZE: u64 = 8 * 1024;
+
+/// Returns the last `count` non-empty lines of a file, reading backwards from the end of the
+/// file so that the whole file doesn't have to be read into memory
+pub(crate) fn tail_lines(path: &Path, count: usize) -> std::io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let mut position = file.seek(SeekFrom::End(0))?;
+    let mut buffer = vec![];
+
+    let is_non_empty = |line: &&[u8]| !line.trim_ascii().is_empty();
+
+    while position > 0 {
+        let chunk_size = TAIL_CHUNK_SIZE.min(position);
+        position -= chunk_size;
+
+        let mut chunk = vec![0; chunk_size as usize];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut chunk)?;
+        chunk.append(&mut buffer);
+        buffer = chunk;
+
+        // the first line in the buffer is incomplete until the start of the file is reached
+        let complete_lines = buffer
+            .split(|byte| *byte == b'\n')
+            .skip(usize::from(position > 0))
+            .filter(is_non_empty)
+            .count();
+
+        if complete_lines >= count {
+            break;
+        }
+    }
+
+    let lines = buffer
+        .split(|byte| *byte == b'\n')
+        .skip(usize::from(position > 0))
+        .filter(is_non_empty)
+        .map(|line| {
+            String::from_utf8_lossy(line)
+                .trim_end_matches('\r')
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+
+    Ok(lines[lines.len().saturating_sub(count)..].to_vec())
+}
+
+/// Number of units listed for each resource in the daemon statistics
+const TOP_UNITS: usize = 5;
+
+/// Total size of the files in a directory and its subdirectories
+fn directory_size(path: &Path) -> u64 {
+    std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            if metadata.is_dir() {
+                Some(directory_size(&entry.path()))
+            } else {
+                Some(metadata.len())
+            }
+        })
+        .sum()
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes}B")
+    } else {
+        format!("{size:.1}{}", UNITS[unit])
+    }
+}
+
+/// A line of the environment shown in the unit status, with the value masked if it is sensitive
+fn environment_line(service: &Service, name: &str, value: &str) -> String {
+    let value = if service.is_sensitive(name) {
+        REDACTED
+    } else {
+        value
+    };
+
+    format!("    {}", format!("{name}={value}").replace("/", "\\"))
+}
+
+/// The lines of the effective environment of a unit, each with the layer that set the variable
+fn effective_environment_lines(definition: &Definition) -> Vec<String> {
+    let service = &definition.service;
+    let sets = |environment: &Option<Vec<(String, String)>>, name: &str| {
+        environment
+            .iter()
+            .flatten()
+            .any(|(variable, _)| variable.eq_ignore_ascii_case(name))
+    };
+
+    definition
+        .effective_environment()
+        .into_iter()
+        .map(|(name, value)| {
+            let layer = if sets(&service.exec_start.environment, &name) {
+                "ExecStart"
+            } else if sets(&service.environment, &name) {
+                "Service"
+            } else {
+                "wpmd"
+            };
+
+            format!("{} ({layer})", environment_line(service, &name, &value))
+        })
+        .collect()
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("    {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub struct ProcessManagerStatus(
+    pub Vec<(Definition, UnitStatus)>,
+    pub Vec<UnitLoadError>,
+    pub Vec<UnitWarning>,
+);
 
 impl ProcessManagerStatus {
-    pub fn as_table(&self) -> String {
-        Table::new(self.0.iter().map(|(_, status)| status).collect::<Vec<_>>()).to_string()
+    fn load_error(&self, name: &str) -> Option<&UnitLoadError> {
+        self.1.iter().find(|error| error.is_for(name))
     }
 
-    pub fn unit_status(&self, name: &str) -> Result<String, ProcessManagerError> {
+    /// Units which failed to load without a previously registered definition, as rows of the
+    /// state table; files which only failed to load because they define the name of another
+    /// unit file again are listed in the load summary instead
+    fn unregistered(&self) -> Vec<UnitStatus> {
+        self.1
+            .iter()
+            .filter(|error| error.duplicate_of.is_none())
+            .filter(|error| !self.0.iter().any(|(def, _)| def.unit.name == error.name))
+            .map(|error| UnitStatus {
+                name: error.name.clone(),
+                kind: DisplayedOption(None),
+                state: UnitState::LoadError,
+                pid: DisplayedOption(None),
+                timestamp: DisplayedOption(None),
+                reason: DisplayedOption(None),
+                uptime: DisplayedOption(None),
+            })
+            .collect()
+    }
+
+    /// The rows of the state table which match the state filter of the options, in order
+    fn rows<'a>(
+        &'a self,
+        options: &StateOptions,
+        unregistered: &'a [UnitStatus],
+    ) -> Vec<&'a UnitStatus> {
+        let mut rows = self
+            .0
+            .iter()
+            .map(|(_, status)| status)
+            .chain(unregistered.iter())
+            .filter(|status| options.states.is_empty() || options.states.contains(&status.state))
+            .collect::<Vec<_>>();
+
+        if let Some(column) = options.sort {
+            rows.sort_by(|a, b| a.compare(b, column).then_with(|| a.name.cmp(&b.name)));
+        }
+
+        rows
+    }
+
+    /// The rows of the state table as JSON, along with the recent log lines of each unit when
+    /// the options ask for them
+    pub fn as_json(&self, options: &StateOptions) -> Result<String, ProcessManagerError> {
+        let unregistered = self.unregistered();
+        let log_lines = options.log_lines.min(MAX_STATE_LOG_LINES);
+
+        let entries = self
+            .rows(options, &unregistered)
+            .into_iter()
+            .map(|status| {
+                // units which have never been started don't have a log yet
+                let log = (log_lines > 0).then(|| {
+                    self.0
+                        .iter()
+                        .find(|(definition, _)| definition.unit.name == status.name)
+                        .and_then(|(definition, _)| definition.log_path().ok())
+                        .and_then(|path| tail_lines(&path, log_lines).ok())
+                        .unwrap_or_default()
+                });
+
+                UnitStateEntry {
+                    name: status.name.clone(),
+                    kind: status.kind.0,
+                    state: status.state,
+                    pid: status.pid.0,
+                    timestamp: status.timestamp.0.map(|timestamp| timestamp.0),
+                    reason: status.reason.0.as_ref().map(ToString::to_string),
+                    uptime_secs: status.uptime.0.map(|uptime| uptime.0.as_secs()),
+                    log,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(serde_json::to_string(&entries)?)
+    }
+
+    /// The state table, or only the names of the units in it when the options ask for quiet
+    /// output
+    pub fn as_table(&self, options: &StateOptions) -> String {
+        let unregistered = self.unregistered();
+        let rows = self.rows(options, &unregistered);
+
+        if options.quiet {
+            return rows
+                .iter()
+                .map(|status| status.name.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+
+        let columns = if options.columns.is_empty() {
+            &StateColumn::DEFAULT[..]
+        } else {
+            &options.columns[..]
+        };
+
+        let mut builder = Builder::default();
+        builder.push_record(columns.iter().map(ToString::to_string));
+        for status in rows {
+            builder.push_record(columns.iter().map(|column| status.cell(*column)));
+        }
+
+        builder.build().to_string()
+    }
+
+    /// Aggregate statistics of the daemon and of all registered units
+    pub fn stats(&self, uptime: Duration) -> String {
+        let mut output = vec![format!("Daemon uptime: {}", Uptime(uptime))];
+
+        let mut states = BTreeMap::new();
+        for (_, status) in &self.0 {
+            *states.entry(status.state.to_string()).or_insert(0) += 1;
+        }
+
+        let states = states
+            .iter()
+            .map(|(state, count)| format!("{state}: {count}"))
+            .collect::<Vec<_>>();
+
+        output.push(format!("Units: {} ({})", self.0.len(), states.join(", ")));
+        output.push(format!(
+            "Automatic restarts today: {}",
+            restart_budget::restarts_today()
+        ));
+
+        // the latest sample of each unit which is still running
+        let latest = usage_history::samples(&[])
+            .into_iter()
+            .filter(|(name, _)| {
+                self.0.iter().any(|(_, status)| {
+                    &status.name == name && matches!(status.state, UnitState::Running)
+                })
+            })
+            .filter_map(|(name, samples)| Some((name, samples.last()?.clone())))
+            .collect::<Vec<_>>();
+
+        let mut by_memory = latest.clone();
+        by_memory.sort_by_key(|(_, sample)| Reverse(sample.memory));
+
+        if !by_memory.is_empty() {
+            output.push("Top units by memory:".to_string());
+            for (name, sample) in by_memory.iter().take(TOP_UNITS) {
+                output.push(format!("  {name}: {}", format_bytes(sample.memory)));
+            }
+        }
+
+        let mut by_cpu = latest;
+        by_cpu.sort_by(|(_, a), (_, b)| b.cpu_percent.total_cmp(&a.cpu_percent));
+
+        if !by_cpu.is_empty() {
+            output.push("Top units by CPU:".to_string());
+            for (name, sample) in by_cpu.iter().take(TOP_UNITS) {
+                output.push(format!("  {name}: {:.0}%", sample.cpu_percent));
+            }
+        }
+
+        output.push(format!(
+            "Store size: {}",
+            format_bytes(wpm_store_dir().map_or(0, |dir| directory_size(&dir)))
+        ));
+        output.push(format!(
+            "Log directory size: {}",
+            format_bytes(wpm_log_dir().map_or(0, |dir| directory_size(&dir)))
+        ));
+
+        output.join("\n")
+    }
+
+    pub fn load_summary(&self) -> String {
+        let mut output = vec![format!("Registered units: {}", self.0.len())];
+
+        if !self.1.is_empty() {
+            output.push(format!("Units which failed to load: {}", self.1.len()));
+            for error in &self.1 {
+                output.push(format!("  {} ({}):", error.name, error.path.display()));
+                output.push(indent(&error.error));
+            }
+        }
+
+        if !self.2.is_empty() {
+            output.push(format!("Warnings: {}", self.2.len()));
+            for warning in &self.2 {
+                output.push(format!("  {warning}"));
+            }
+        }
+
+        output.join("\n")
+    }
+
+    /// Explains the current state of a unit with the states of its dependencies and the events
+    /// recorded for it since wpmd started
+    pub fn explain(&self, name: &str) -> String {
+        let Some((definition, status)) = self.0.iter().find(|(def, _status)| def.unit.name == name)
+        else {
+            return match self.load_error(name) {
+                None => format!("Unregistered unit: {name}"),
+                Some(error) => [
+                    format!(
+                        "● {name} could not be loaded from {}:",
+                        error.path.display()
+                    ),
+                    indent(&error.error),
+                ]
+                .join("\n"),
+            };
+        };
+
+        let mut heading = format!("● {name} is {}", status.state);
+        if let Some(reason) = &status.reason.0 {
+            heading.push_str(&format!(" ({reason})"));
+        }
+
+        if let Some(timestamp) = &status.timestamp.0 {
+            heading.push_str(&format!(" since {timestamp}"));
+        }
+
+        let mut output = vec![heading];
+
+        if let Some(requires) = &definition.unit.requires {
+            output.push("  Requires:".to_string());
+            for dependency in requires {
+                let state = self
+                    .0
+                    .iter()
+                    .find(|(def, _status)| &def.unit.name == dependency)
+                    .map_or_else(
+                        || "Unregistered".to_string(),
+                        |(_, status)| status.state.to_string(),
+                    );
+
+                output.push(format!("    {dependency}: {state}"));
+            }
+        }
+
+        if restart_budget::is_exhausted() {
+            output.push("  Automatic restarts are paused after too many restarts within a minute; run `wpmctl reset --restart-budget` to resume them".to_string());
+        }
+
+        let entries = journal::entries(name);
+        if entries.is_empty() {
+            output.push("  No events recorded since wpmd started".to_string());
+        } else {
+            output.push("  Events:".to_string());
+            for entry in entries {
+                output.push(format!("    {entry}"));
+            }
+        }
+
+        output.join("\n")
+    }
+
+    /// The environment ExecStart of a unit is started with, merged from the environment of wpmd,
+    /// the service environment and the ExecStart environment
+    pub fn show(&self, name: &str) -> String {
+        match self.0.iter().find(|(def, _status)| def.unit.name == name) {
+            None => format!("Unregistered unit: {name}"),
+            Some((definition, _)) => {
+                let mut output = vec![format!("● Environment of {name}:")];
+                output.append(&mut effective_environment_lines(definition));
+                output.join("\n")
+            }
+        }
+    }
+
+    pub fn unit_status(&self, name: &str, log_lines: usize) -> Result<String, ProcessManagerError> {
         match self.0.iter().find(|(def, _status)| def.unit.name == name) {
-            None => Ok(format!("Unregistered unit: {name}")),
+            None => match self.load_error(name) {
+                None => Ok(format!("Unregistered unit: {name}")),
+                Some(error) => Ok([
+                    format!("● Status of {name}:"),
+                    "  State: LoadError".to_string(),
+                    format!("  File: {}", error.path.display()),
+                    "  Error:".to_string(),
+                    indent(&error.error),
+                ]
+                .join("\n")),
+            },
             Some((definition, status)) => {
-                let log_path = definition.log_path();
+                let log_path = definition.log_path()?;
                 let mut output = Vec::new();
 
                 match status.state {
@@ -28,6 +465,30 @@ impl ProcessManagerStatus {
                             format!("  PID: {}", status.pid),
                             format!("  Log: {}", log_path.to_string_lossy()),
                         ]);
+
+                        if let Some(details) = status.pid.0.and_then(process_details) {
+                            if let Some(executable) = details.executable {
+                                output.push(format!("  Executable: {}", executable.display()));
+                            }
+
+                            if !details.command_line.is_empty() {
+                                output.push(format!(
+                                    "  Command line: {}",
+                                    details.command_line.join(" ")
+                                ));
+                            }
+
+                            if !details.descendants.is_empty() {
+                                let descendants = details
+                                    .descendants
+                                    .iter()
+                                    .map(ToString::to_string)
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+
+                                output.push(format!("  Child PIDs: {descendants}"));
+                            }
+                        }
                     }
                     UnitState::Stopped => {
                         output.append(&mut vec![
@@ -50,47 +511,105 @@ impl ProcessManagerStatus {
                             format!("● Status of {name}:"),
                             format!("  Kind: {}", definition.service.kind),
                             format!("  State: Failed at {}", status.timestamp),
+                            format!("  Reason: {}", status.reason),
                             format!("  Log file: {}", log_path.to_string_lossy()),
                         ]);
+
+                        if let Some(ExitReason::FailedHealthcheck(Some(failure))) = &status.reason.0
+                        {
+                            for (stream, captured) in
+                                [("stdout", &failure.stdout), ("stderr", &failure.stderr)]
+                            {
+                                if !captured.is_empty() {
+                                    output.push(format!("  Healthcheck {stream}:"));
+                                    output.push(indent(captured));
+                                }
+                            }
+                        }
                     }
                     UnitState::Terminated => {
                         output.append(&mut vec![
                             format!("● Status of {name}:"),
                             format!("  Kind: {}", definition.service.kind),
                             format!("  State: Terminated at {}", status.timestamp),
+                            format!("  Reason: {}", status.reason),
+                            format!("  Log file: {}", log_path.to_string_lossy()),
+                        ]);
+                    }
+                    UnitState::LoadError => {
+                        output.append(&mut vec![
+                            format!("● Status of {name}:"),
+                            format!("  Kind: {}", definition.service.kind),
+                            "  State: LoadError".to_string(),
                             format!("  Log file: {}", log_path.to_string_lossy()),
                         ]);
                     }
                 }
 
-                if let Some(args) = &definition.service.exec_start.arguments {
-                    let arguments = args.join(" ");
-                    let arguments = arguments.replace("/", "\\");
+                // only shown when the latest start needed more than one attempt
+                let retries = journal::entries(name)
+                    .into_iter()
+                    .rev()
+                    .take_while(|entry| !matches!(entry.event, UnitEvent::StartRequested))
+                    .find_map(|entry| match entry.event {
+                        UnitEvent::StartRetry {
+                            attempt, attempts, ..
+                        } => Some((attempt, attempts)),
+                        _ => None,
+                    });
+
+                if let Some((attempt, attempts)) = retries {
+                    output.push(format!("  Start attempts: {} of {attempts}", attempt + 1));
+                }
+
+                // a previously registered definition is kept when reloading its file fails
+                if let Some(error) = self.load_error(name) {
+                    output.push(format!("  Load error ({}):", error.path.display()));
+                    output.push(indent(&error.error));
+                }
+
+                for duplicate in self
+                    .1
+                    .iter()
+                    .filter(|error| error.name == name && error.duplicate_of.is_some())
+                {
                     output.push(format!(
-                        "  ExecStart: {} {arguments}",
-                        definition
-                            .service
-                            .exec_start
-                            .executable
-                            .to_string()
-                            .replace("/", "\\")
+                        "  Ignored duplicate definition: {}",
+                        duplicate.path.display()
                     ));
+                }
+
+                let warnings = self
+                    .2
+                    .iter()
+                    .filter(|warning| warning.unit == name)
+                    .collect::<Vec<_>>();
+
+                if !warnings.is_empty() {
+                    output.push("  Warnings:".to_string());
+                    for warning in warnings {
+                        output.push(format!("    {}: {}", warning.field, warning.message));
+                    }
+                }
+
+                // urls of remote executables are shown as they were declared
+                let executable = match &definition.service.exec_start.executable {
+                    Executable::Local(path) => path.to_string_lossy().replace("/", "\\"),
+                    executable => executable.to_string(),
+                };
+
+                if let Some(args) = &definition.service.exec_start.arguments {
+                    let arguments = quote_arguments(args);
+                    let arguments = arguments.replace("/", "\\");
+                    output.push(format!("  ExecStart: {executable} {arguments}"));
                 } else {
-                    output.push(format!(
-                        "  ExecStart: {}",
-                        definition
-                            .service
-                            .exec_start
-                            .executable
-                            .to_string()
-                            .replace("/", "\\")
-                    ));
+                    output.push(format!("  ExecStart: {executable}"));
                 }
 
                 match &definition.service.healthcheck {
                     Some(Healthcheck::Command(command)) => {
                         if let Some(args) = &command.arguments {
-                            let arguments = args.join(" ");
+                            let arguments = quote_arguments(args);
                             let arguments = arguments.replace("/", "\\");
                             output.push(format!(
                                 "  Healthcheck: {} {arguments}",
@@ -126,7 +645,7 @@ impl ProcessManagerStatus {
                     output.push("  ExecStop:".to_string());
                     for command in shutdowns {
                         if let Some(args) = &command.arguments {
-                            let arguments = args.join(" ");
+                            let arguments = quote_arguments(args);
                             let arguments = arguments.replace("/", "\\");
                             output.push(format!("    {} {arguments}", command.executable));
                         } else {
@@ -139,7 +658,7 @@ impl ProcessManagerStatus {
                     output.push("  ExecStopPost:".to_string());
                     for command in shutdowns {
                         if let Some(args) = &command.arguments {
-                            let arguments = args.join(" ");
+                            let arguments = quote_arguments(args);
                             let arguments = arguments.replace("/", "\\");
                             output.push(format!("    {} {arguments}", command.executable,));
                         } else {
@@ -149,47 +668,73 @@ impl ProcessManagerStatus {
                 }
 
                 if let Some(environment) = &definition.service.environment {
-                    let vars = environment
-                        .iter()
-                        .map(|(a, b)| format!("{a}={b}"))
-                        .collect::<Vec<_>>();
-
                     output.push("  Environment (Service):".to_string());
-                    for var in vars {
-                        let var = var.replace("/", "\\");
-                        output.push(format!("    {var}"));
+                    for (name, value) in environment {
+                        output.push(environment_line(&definition.service, name, value));
                     }
                 }
 
                 if let Some(environment) = &definition.service.exec_start.environment {
-                    let vars = environment
-                        .iter()
-                        .map(|(a, b)| format!("{a}={b}"))
-                        .collect::<Vec<_>>();
-
                     output.push("  Environment (ExecStart):".to_string());
-                    for var in vars {
-                        let var = var.replace("/", "\\");
-                        output.push(format!("    {var}"));
+                    for (name, value) in environment {
+                        output.push(environment_line(&definition.service, name, value));
                     }
                 }
 
+                output.push("  Environment (Effective):".to_string());
+                output.append(&mut effective_environment_lines(definition));
+
+                if let Some(threshold) = &definition.service.restart_on_memory_above {
+                    output.push(format!(
+                        "  RestartOnMemoryAbove: {}MB for {} consecutive samples",
+                        threshold.megabytes,
+                        threshold.consecutive_samples.unwrap_or(3)
+                    ));
+                }
+
+                if let Some(threshold) = &definition.service.alert_on_cpu_above {
+                    output.push(format!(
+                        "  AlertOnCpuAbove: {}% for {} consecutive samples{}",
+                        threshold.percent,
+                        threshold.consecutive_samples.unwrap_or(3),
+                        if threshold.restart {
+                            ", restarting"
+                        } else {
+                            ""
+                        }
+                    ));
+                }
+
+                if let Some(documentation) = &definition.unit.documentation {
+                    let documentation = documentation.join(" ");
+                    output.push(format!("  Documentation: {documentation}"));
+                }
+
                 if let Some(requires) = &definition.unit.requires {
                     let requires = requires.join(" ");
                     output.push(format!("  Requires: {requires}",));
                 }
 
-                let log_contents = std::fs::read_to_string(log_path)?;
-                let lines = log_contents
-                    .lines()
-                    .filter(|line| !line.is_empty())
-                    .collect::<Vec<_>>();
-                let last_ten_lines = lines.iter().rev().take(10).rev().collect::<Vec<_>>();
+                if let Some(services) = &definition.unit.requires_windows_service {
+                    let services = services.join(" ");
+                    output.push(format!("  RequiresWindowsService: {services}"));
+                }
 
-                if !last_ten_lines.is_empty() {
-                    output.push("\nRecent logs:".to_string());
-                    for line in last_ten_lines {
-                        output.push(format!("  {line}"));
+                // the log is only created when the unit is first started, and a log which can't
+                // be read shouldn't hide the rest of the status
+                match tail_lines(&log_path, log_lines) {
+                    Ok(recent_lines) if recent_lines.is_empty() => {}
+                    Ok(recent_lines) => {
+                        output.push("\nRecent logs:".to_string());
+                        for line in recent_lines {
+                            output.push(format!("  {line}"));
+                        }
+                    }
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                        output.push("\nRecent logs: no logs yet".to_string());
+                    }
+                    Err(error) => {
+                        output.push(format!("\nRecent logs: could not be read ({error})"));
                     }
                 }
 
@@ -0,0 +1,101 @@
+use crate::journal;
+use crate::journal::UnitEvent;
+use crate::unit::unit_span;
+use chrono::Local;
+use chrono::NaiveDate;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Window over which automatic restarts across all units are counted
+const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+/// Default number of automatic restarts allowed across all units within the restart window
+pub const DEFAULT_MAX_RESTARTS_PER_MINUTE: usize = 30;
+
+static RESTART_BUDGET: Mutex<RestartBudget> = Mutex::new(RestartBudget {
+    limit: DEFAULT_MAX_RESTARTS_PER_MINUTE,
+    restarts: VecDeque::new(),
+    exhausted: false,
+    today: None,
+});
+
+/// Automatic restarts across all units, which are paused once too many happen within a minute
+/// so that units crash-looping on a shared dependency can't take over the machine
+struct RestartBudget {
+    limit: usize,
+    restarts: VecDeque<Instant>,
+    exhausted: bool,
+    /// The current day and the number of automatic restarts allowed on it
+    today: Option<(NaiveDate, usize)>,
+}
+
+/// Sets the number of automatic restarts allowed across all units within a minute
+pub fn set_limit(limit: usize) {
+    RESTART_BUDGET.lock().limit = limit;
+}
+
+/// Records an automatic restart of a unit, returning false without recording it if the budget
+/// is exhausted, in which case automatic restarts stay paused until [`reset`] is called
+pub fn allow_restart(name: &str) -> bool {
+    let _span = unit_span(name).entered();
+    let mut budget = RESTART_BUDGET.lock();
+
+    if budget.exhausted {
+        tracing::warn!("not restarting, automatic restarts are paused");
+        journal::record(name, UnitEvent::RestartSuppressed);
+        return false;
+    }
+
+    let now = Instant::now();
+    while budget
+        .restarts
+        .front()
+        .is_some_and(|restart| now.duration_since(*restart) >= RESTART_WINDOW)
+    {
+        budget.restarts.pop_front();
+    }
+
+    if budget.restarts.len() >= budget.limit {
+        budget.exhausted = true;
+        tracing::error!(
+            "more than {} automatic restarts within a minute, pausing automatic restarts for all units until `wpmctl reset --restart-budget` is run",
+            budget.limit
+        );
+
+        journal::record(name, UnitEvent::RestartSuppressed);
+        return false;
+    }
+
+    budget.restarts.push_back(now);
+
+    let date = Local::now().date_naive();
+    match &mut budget.today {
+        Some((today, count)) if *today == date => *count += 1,
+        today => *today = Some((date, 1)),
+    }
+
+    true
+}
+
+/// Number of automatic restarts across all units since midnight
+pub fn restarts_today() -> usize {
+    match RESTART_BUDGET.lock().today {
+        Some((today, count)) if today == Local::now().date_naive() => count,
+        _ => 0,
+    }
+}
+
+/// Whether automatic restarts are paused because the budget was exhausted
+pub fn is_exhausted() -> bool {
+    RESTART_BUDGET.lock().exhausted
+}
+
+/// Resumes automatic restarts with an empty restart window
+pub fn reset() {
+    let mut budget = RESTART_BUDGET.lock();
+    budget.restarts.clear();
+    budget.exhausted = false;
+    tracing::info!("restart budget reset, resuming automatic restarts");
+}
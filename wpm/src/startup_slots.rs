@@ -0,0 +1,52 @@
+use crate::unit::unit_span;
+use parking_lot::Condvar;
+use parking_lot::Mutex;
+
+static STARTUP_SLOTS: Mutex<StartupSlots> = Mutex::new(StartupSlots {
+    limit: None,
+    in_use: 0,
+});
+
+static SLOT_RELEASED: Condvar = Condvar::new();
+
+/// Units which are spawning or running their healthchecks, which are capped so that starting
+/// many units at once doesn't thrash machines with few cores
+struct StartupSlots {
+    limit: Option<usize>,
+    in_use: usize,
+}
+
+/// Held by a unit while it is spawning and running its healthcheck, freeing the slot for a
+/// queued unit when dropped
+pub struct StartupSlot(());
+
+impl Drop for StartupSlot {
+    fn drop(&mut self) {
+        STARTUP_SLOTS.lock().in_use -= 1;
+        SLOT_RELEASED.notify_one();
+    }
+}
+
+/// Sets the number of units which can be spawning or running their healthchecks at the same
+/// time, where `None` doesn't limit them
+pub fn set_limit(limit: Option<usize>) {
+    STARTUP_SLOTS.lock().limit = limit.map(|limit| limit.max(1));
+    SLOT_RELEASED.notify_all();
+}
+
+/// Waits until a startup slot is free and takes it
+pub fn acquire(name: &str) -> StartupSlot {
+    let mut slots = STARTUP_SLOTS.lock();
+
+    if slots.limit.is_some_and(|limit| slots.in_use >= limit) {
+        let _span = unit_span(name).entered();
+        tracing::info!("waiting for other units to finish starting");
+
+        while slots.limit.is_some_and(|limit| slots.in_use >= limit) {
+            SLOT_RELEASED.wait(&mut slots);
+        }
+    }
+
+    slots.in_use += 1;
+    StartupSlot(())
+}
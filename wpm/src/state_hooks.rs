@@ -0,0 +1,73 @@
+use crate::platform::CurrentPlatform;
+use crate::platform::Platform;
+use crate::unit::unit_span;
+use parking_lot::Mutex;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use wpm_protocol::UnitState;
+
+static STATE_HOOKS: Mutex<StateHooks> = Mutex::new(StateHooks {
+    on_unit_failed: None,
+    on_unit_started: None,
+});
+
+/// Commands run by wpmd when a unit changes state, with the name of the unit and its new state
+/// as arguments
+#[derive(Debug, Clone, Default)]
+pub struct StateHooks {
+    pub on_unit_failed: Option<PathBuf>,
+    pub on_unit_started: Option<PathBuf>,
+}
+
+/// Sets the commands which are run when a unit changes state
+pub fn set_state_hooks(hooks: StateHooks) {
+    *STATE_HOOKS.lock() = hooks;
+}
+
+/// Runs the hook for a unit which entered the Failed state
+pub(crate) fn unit_failed(name: &str) {
+    let hook = STATE_HOOKS.lock().on_unit_failed.clone();
+    run(hook, name, UnitState::Failed);
+}
+
+/// Runs the hook for a unit which has been started and passed its healthcheck
+pub(crate) fn unit_started(name: &str) {
+    let hook = STATE_HOOKS.lock().on_unit_started.clone();
+    run(hook, name, UnitState::Running);
+}
+
+/// Runs a hook on a separate thread, so that a slow hook can't hold up the unit
+fn run(hook: Option<PathBuf>, name: &str, state: UnitState) {
+    let Some(hook) = hook else {
+        return;
+    };
+
+    let name = name.to_string();
+
+    std::thread::spawn(move || {
+        let _span = unit_span(&name).entered();
+
+        let mut command = Command::new(&hook);
+        command
+            .arg(&name)
+            .arg(state.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+
+        CurrentPlatform::configure_command(&mut command);
+
+        match command.status() {
+            Ok(status) if status.success() => {
+                tracing::debug!("ran {state} hook {}", hook.display());
+            }
+            Ok(status) => {
+                tracing::warn!("{state} hook {} exited with {status}", hook.display());
+            }
+            Err(error) => {
+                tracing::warn!("could not run {state} hook {}: {error}", hook.display());
+            }
+        }
+    });
+}
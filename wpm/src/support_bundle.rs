@@ -0,0 +1,130 @@
+use crate::clock;
+use crate::log_files::current_daemon_log;
+use crate::log_files::DAEMON_PLAINTEXT_LOG;
+use crate::process_manager::ProcessManager;
+use crate::process_manager::ProcessManagerError;
+use crate::process_manager_status::tail_lines;
+use crate::wpm_store_dir;
+use crate::PROTOCOL_VERSION;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// What goes into a support bundle besides the files which wpm can find by itself
+pub struct SupportBundle {
+    /// Version information of the program writing the bundle
+    pub version: String,
+    /// The state of the units as JSON, or `None` if wpmd could not be asked for it
+    pub state: Option<String>,
+    /// Number of recent lines included from the log of each unit
+    pub log_lines: usize,
+}
+
+impl SupportBundle {
+    /// The name of a bundle written now, which is unique for each second
+    pub fn default_file_name() -> String {
+        format!("wpm-bundle-{}.zip", clock::now().format("%Y%m%dT%H%M%S"))
+    }
+
+    /// Writes the bundle to a zip file, returning the names of the files in it
+    ///
+    /// The bundle holds the version information, the state of the units, the current log of
+    /// wpmd, the recent logs of every unit, the redacted unit definitions, and a listing of the
+    /// files in the store
+    ///
+    /// The definitions are read from the units directory of this process, so callers outside of
+    /// wpmd should use the directories of wpmd with `set_directories` first
+    pub fn write(&self, path: &Path) -> Result<Vec<String>, ProcessManagerError> {
+        let mut zip = ZipWriter::new(File::create(path)?);
+        let mut entries = vec![];
+
+        let mut add = |name: String, contents: &[u8]| -> Result<(), ProcessManagerError> {
+            zip.start_file(name.as_str(), SimpleFileOptions::default())?;
+            zip.write_all(contents)?;
+            entries.push(name);
+            Ok(())
+        };
+
+        add(
+            String::from("version.txt"),
+            format!(
+                "{}\nprotocol version: {PROTOCOL_VERSION}\nplatform: {} {}\n",
+                self.version.trim_end(),
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            )
+            .as_bytes(),
+        )?;
+
+        match &self.state {
+            Some(state) => add(String::from("state.json"), state.as_bytes())?,
+            None => add(
+                String::from("state.txt"),
+                b"wpmd did not respond, so the state of the units could not be collected\n",
+            )?,
+        }
+
+        if let Ok(log) = std::fs::read(current_daemon_log(DAEMON_PLAINTEXT_LOG)) {
+            add(String::from("logs/wpmd.log"), &log)?;
+        }
+
+        let (units, load_errors) = ProcessManager::parse_units(None, false)?;
+        for (_, definition) in &units {
+            let name = &definition.unit.name;
+
+            add(
+                format!("units/{name}.json"),
+                serde_json::to_string_pretty(&definition.redacted())?.as_bytes(),
+            )?;
+
+            if let Ok(lines) = tail_lines(&definition.log_path()?, self.log_lines) {
+                let log = lines
+                    .into_iter()
+                    .map(|line| line + "\n")
+                    .collect::<String>();
+
+                add(format!("logs/{name}.log"), log.as_bytes())?;
+            }
+        }
+
+        if !load_errors.is_empty() {
+            let errors = load_errors
+                .iter()
+                .map(|error| format!("{}: {}\n", error.path.display(), error.error))
+                .collect::<String>();
+
+            add(String::from("units/load-errors.txt"), errors.as_bytes())?;
+        }
+
+        add(String::from("store.txt"), store_manifest()?.as_bytes())?;
+
+        zip.finish()?;
+
+        Ok(entries)
+    }
+}
+
+/// The path relative to the store and the size of every file in the store, one per line
+fn store_manifest() -> std::io::Result<String> {
+    fn visit(root: &Path, dir: &Path, lines: &mut Vec<String>) {
+        for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                visit(root, &path, lines);
+            } else {
+                let size = entry.metadata().map_or(0, |metadata| metadata.len());
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                lines.push(format!("{} ({size} bytes)", relative.display()));
+            }
+        }
+    }
+
+    let store = wpm_store_dir()?;
+    let mut lines = vec![];
+    visit(&store, &store, &mut lines);
+    lines.sort();
+
+    Ok(lines.into_iter().map(|line| line + "\n").collect())
+}
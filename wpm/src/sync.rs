@@ -0,0 +1,121 @@
+use crate::process_manager::ProcessManagerError;
+use crate::wpm_data_dir;
+use crate::wpm_units_dir;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Subdirectory of the unit directory which units are synced into, and which is entirely
+/// managed by wpm
+pub const SYNC_DIRECTORY: &str = "synced";
+
+const DEFAULT_BRANCH: &str = "main";
+
+/// The git repository which units are synced from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncConfig {
+    pub repository: String,
+    pub branch: String,
+}
+
+impl SyncConfig {
+    pub fn new(repository: String, branch: Option<String>) -> Self {
+        Self {
+            repository,
+            branch: branch.unwrap_or_else(|| DEFAULT_BRANCH.to_string()),
+        }
+    }
+}
+
+fn config_path() -> std::io::Result<PathBuf> {
+    Ok(wpm_data_dir()?.join("sync.json"))
+}
+
+/// The repository configured by the last sync, if there was one
+pub fn config() -> Result<Option<SyncConfig>, ProcessManagerError> {
+    let path = config_path()?;
+
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_str(&std::fs::read_to_string(path)?)?))
+}
+
+/// The directory units are synced into
+pub fn sync_dir() -> PathBuf {
+    wpm_units_dir().join(SYNC_DIRECTORY)
+}
+
+fn git(directory: Option<&Path>, arguments: &[&str]) -> Result<String, ProcessManagerError> {
+    let mut command = Command::new("git");
+
+    if let Some(directory) = directory {
+        command.arg("-C").arg(directory);
+    }
+
+    let output = command.args(arguments).output()?;
+
+    if !output.status.success() {
+        return Err(ProcessManagerError::Git {
+            command: arguments.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Clones the configured repository into the sync directory, or updates the existing clone to
+/// the latest commit of the configured branch, discarding any local changes; the repository is
+/// saved so that later syncs don't have to pass it again
+///
+/// Returns the commit the sync directory is at afterwards
+pub fn sync(config: Option<SyncConfig>) -> Result<String, ProcessManagerError> {
+    let config = match config {
+        Some(config) => config,
+        None => self::config()?.ok_or(ProcessManagerError::NoSyncRepository)?,
+    };
+
+    let dir = sync_dir();
+
+    let cloned_from = if dir.join(".git").is_dir() {
+        git(Some(&dir), &["remote", "get-url", "origin"]).ok()
+    } else {
+        None
+    };
+
+    if cloned_from.as_deref() == Some(config.repository.as_str()) {
+        tracing::info!("updating {} from {}", dir.display(), config.repository);
+        git(
+            Some(&dir),
+            &["fetch", "--depth", "1", "origin", &config.branch],
+        )?;
+        git(Some(&dir), &["reset", "--hard", "FETCH_HEAD"])?;
+        git(Some(&dir), &["clean", "-fdx"])?;
+    } else {
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+
+        tracing::info!("cloning {} into {}", config.repository, dir.display());
+        git(
+            None,
+            &[
+                "clone",
+                "--depth",
+                "1",
+                "--branch",
+                &config.branch,
+                &config.repository,
+                &dir.to_string_lossy(),
+            ],
+        )?;
+    }
+
+    std::fs::write(config_path()?, serde_json::to_string_pretty(&config)?)?;
+
+    git(Some(&dir), &["rev-parse", "--short", "HEAD"])
+}
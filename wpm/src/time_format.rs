@@ -0,0 +1,103 @@
+use chrono::DateTime;
+use chrono::Local;
+use chrono::Utc;
+use parking_lot::RwLock;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Format of the timestamps shown in human-readable output, unless another format is set
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Time zone which timestamps are shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisplayTimeZone {
+    /// The time zone of the machine
+    #[default]
+    Local,
+    Utc,
+}
+
+impl Display for DisplayTimeZone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisplayTimeZone::Local => write!(f, "local"),
+            DisplayTimeZone::Utc => write!(f, "utc"),
+        }
+    }
+}
+
+impl FromStr for DisplayTimeZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "local" => Ok(DisplayTimeZone::Local),
+            "utc" => Ok(DisplayTimeZone::Utc),
+            _ => Err(format!(
+                "unknown time zone '{s}' (valid time zones: local, utc)"
+            )),
+        }
+    }
+}
+
+/// How timestamps are shown in the state table, unit status and journal
+#[derive(Debug, Clone)]
+pub struct TimeFormat {
+    /// strftime-style format string, as understood by chrono
+    pub format: String,
+    pub time_zone: DisplayTimeZone,
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        Self {
+            format: DEFAULT_TIME_FORMAT.to_string(),
+            time_zone: DisplayTimeZone::default(),
+        }
+    }
+}
+
+static TIME_FORMAT: RwLock<Option<TimeFormat>> = RwLock::new(None);
+
+/// Replaces the format of displayed timestamps for the rest of the process
+pub fn set_time_format(format: TimeFormat) {
+    *TIME_FORMAT.write() = Some(format);
+}
+
+/// Checks that a format string only contains specifiers which chrono understands, as formatting
+/// a timestamp with an invalid format string panics
+pub fn parse_format(format: &str) -> Result<String, String> {
+    let mut formatted = String::new();
+    match write!(formatted, "{}", Utc::now().format(format)) {
+        Ok(()) => Ok(format.to_string()),
+        Err(_) => Err(format!("'{format}' is not a valid time format")),
+    }
+}
+
+/// Formats a timestamp for human-readable output with the configured format and time zone
+pub fn display(timestamp: DateTime<Utc>) -> String {
+    let time_format = TIME_FORMAT.read();
+    let (format, time_zone) = time_format.as_ref().map_or(
+        (DEFAULT_TIME_FORMAT, DisplayTimeZone::Local),
+        |time_format| (time_format.format.as_str(), time_format.time_zone),
+    );
+
+    match time_zone {
+        DisplayTimeZone::Local => DateTime::<Local>::from(timestamp)
+            .format(format)
+            .to_string(),
+        DisplayTimeZone::Utc => timestamp.format(format).to_string(),
+    }
+}
+
+/// A timestamp which is displayed with the configured format and time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub DateTime<Utc>);
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", display(self.0))
+    }
+}
@@ -1,16 +1,38 @@
+use crate::clock;
+use crate::communication::daemon_socket;
 use crate::communication::send_message;
+use crate::fetch::download_resource;
+use crate::fetch::fetch_bytes;
+use crate::journal;
+use crate::journal::UnitEvent;
+use crate::log_output;
+use crate::migration::Deprecation;
+use crate::platform::CurrentPlatform;
+use crate::platform::Platform;
+use crate::process_manager::exit_reason_override;
+use crate::process_manager::find_processes;
+use crate::process_manager::process_details;
+use crate::process_manager::process_exists;
+use crate::process_manager::process_start_time;
 use crate::process_manager::Child;
 use crate::process_manager::ProcessManagerError;
 use crate::process_manager::ProcessState;
-use crate::reqwest_client;
 use crate::resource_regex;
+use crate::restart_budget;
+use crate::state_hooks;
+use crate::unit_status::ExitReason;
+use crate::unit_status::HealthcheckFailure;
+use crate::unit_status::UnitExit;
+use crate::unit_status::UnitState;
 use crate::wpm_log_dir;
 use crate::wpm_store_dir;
 use crate::SocketMessage;
+use crate::UnitAction;
 use chrono::DateTime;
 use chrono::Utc;
 use dirs::home_dir;
 use parking_lot::Mutex;
+use regex::Regex;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
@@ -19,18 +41,24 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
 use std::ops::Not;
-use std::os::windows::process::CommandExt;
+use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
+use std::process::Output;
 use std::sync::Arc;
 use std::time::Duration;
-use sysinfo::Pid;
-use sysinfo::ProcessRefreshKind;
-use sysinfo::ProcessesToUpdate;
-use sysinfo::System;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 use url::Url;
 
+pub use wpm_protocol::ServiceKind;
+
 #[derive(Serialize, Deserialize, Clone, JsonSchema)]
 /// A wpm definition
 #[serde(rename_all = "PascalCase")]
@@ -38,12 +66,75 @@ pub struct Definition {
     /// JSON Schema definition for auto completions
     #[serde(rename(serialize = "$schema"))]
     pub schema: Option<String>,
+    /// Version of the unit file format, which unit files using an older version are migrated from when they are loaded (default: 1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format_version: Option<u32>,
+    /// Deprecated fields which were migrated when the unit file was loaded
+    #[serde(skip)]
+    pub deprecations: Vec<Deprecation>,
     /// Information about this definition and its dependencies
     pub unit: Unit,
     /// Remote resources used by this definition
     pub resources: Option<HashMap<String, Url>>,
     /// Information about what this definition executes
     pub service: Service,
+    /// Files or directories which start a unit when they change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathTrigger>,
+    /// A TCP address which starts this unit when the first connection arrives
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket: Option<SocketActivation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+/// A TCP address which wpmd listens on, starting the unit on the first connection and proxying
+/// connections to the address the unit listens on
+#[serde(rename_all = "PascalCase")]
+pub struct SocketActivation {
+    /// Address that wpmd listens on (e.g. 127.0.0.1:8080)
+    pub listen: SocketAddr,
+    /// Address that the unit listens on, which connections are proxied to (e.g. 127.0.0.1:18080)
+    pub target: SocketAddr,
+    /// Time in seconds to wait for the unit to accept connections after starting it (default: 30s)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_sec: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+/// Files or directories which start a unit when they change
+#[serde(rename_all = "PascalCase")]
+pub struct PathTrigger {
+    /// Files or directories to watch, where directories are watched for changes to the files directly inside them
+    pub paths: Vec<PathBuf>,
+    /// Unit to start when a watched path changes (default: this unit)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+/// A working set size above which a unit is restarted, for processes which leak memory
+#[serde(rename_all = "PascalCase")]
+pub struct MemoryThreshold {
+    /// Working set size in megabytes
+    pub megabytes: u64,
+    /// Number of consecutive samples, taken every 10 seconds, which have to be above the threshold (default: 3)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consecutive_samples: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+/// A CPU usage above which an event is recorded for a unit, for processes which get stuck in busy loops
+#[serde(rename_all = "PascalCase")]
+pub struct CpuThreshold {
+    /// CPU usage in percent of a single logical processor, which can be above 100 for multi-threaded processes
+    pub percent: f32,
+    /// Number of consecutive samples, taken every 10 seconds, which have to be above the threshold (default: 3)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consecutive_samples: Option<u32>,
+    /// Gracefully restart the unit when the threshold is exceeded
+    #[serde(default)]
+    #[serde(skip_serializing_if = "<&bool>::not")]
+    pub restart: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, JsonSchema)]
@@ -55,25 +146,124 @@ pub struct Unit {
     /// Description of this definition
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// URLs of documentation for this definition, the first of which is opened by wpmctl docs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<Vec<String>>,
     /// Dependencies of this definition, validated at runtime
     #[serde(skip_serializing_if = "Option::is_none")]
     pub requires: Option<Vec<String>>,
+    /// Windows services which must be running before this definition is started, started through the service control manager if needed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requires_windows_service: Option<Vec<String>>,
+    /// Restart the running units which require this definition whenever it is restarted with wpmctl
+    #[serde(default)]
+    #[serde(skip_serializing_if = "<&bool>::not")]
+    pub restart_dependents: bool,
 }
 
 #[derive(Default, Serialize, Deserialize, Copy, Clone, JsonSchema)]
 /// Information about a wpm definition's restart strategy
 pub enum RestartStrategy {
+    /// Never restart the unit
     #[default]
     Never,
+    /// Restart the unit whenever it exits, including after it is stopped with wpmctl
     Always,
+    /// Restart the unit whenever it exits, except after it is stopped with wpmctl
+    UnlessStopped,
+    /// Restart the unit if it exits with a non-zero exit code, is killed or fails its healthcheck
     OnFailure,
+    /// Restart the unit only if it is killed, aborted by an NTSTATUS error (like an access
+    /// violation) or fails its healthcheck
+    OnAbnormal,
+}
+
+impl RestartStrategy {
+    /// Whether a unit which stopped running on its own for the given reason should be restarted
+    pub fn should_restart(&self, reason: &ExitReason) -> bool {
+        match self {
+            RestartStrategy::Never => false,
+            RestartStrategy::Always | RestartStrategy::UnlessStopped => true,
+            RestartStrategy::OnFailure => !matches!(reason, ExitReason::ExitCode(0)),
+            RestartStrategy::OnAbnormal => {
+                matches!(
+                    reason,
+                    ExitReason::Killed | ExitReason::Aborted(_) | ExitReason::FailedHealthcheck(_)
+                )
+            }
+        }
+    }
+}
+
+/// Reads the PID written to a PID file by a forking service
+fn read_pid_file(path: &Path) -> std::io::Result<u32> {
+    std::fs::read_to_string(path)?
+        .trim()
+        .parse::<u32>()
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+}
+
+/// Resets and starts a unit again after a delay, from a separate thread so that the message is
+/// handled by wpmd once the current socket message has been handled
+pub fn schedule_restart(name: String, restart_sec: u64) {
+    journal::record(&name, UnitEvent::RestartScheduled(restart_sec));
+
+    std::thread::spawn(move || {
+        let _span = unit_span(&name).entered();
+        tracing::info!("restarting in {restart_sec}s");
+        std::thread::sleep(Duration::from_secs(restart_sec));
+
+        for message in [
+            SocketMessage::Reset(vec![name.clone()]),
+            SocketMessage::Scheduled(UnitAction::Start, vec![name.clone()]),
+        ] {
+            if let Err(error) = send_message(daemon_socket(), message) {
+                tracing::error!("{error}");
+            }
+        }
+    });
+}
+
+/// Records that a unit failed, clearing the failure again after `reset_sec` seconds unless the
+/// unit has been reset or has failed again in the meantime
+fn record_failure(
+    failed: &Arc<Mutex<HashMap<String, UnitExit>>>,
+    name: &str,
+    reason: ExitReason,
+    reset_sec: Option<u64>,
+) {
+    let exit = UnitExit::now(reason);
+    let timestamp = exit.timestamp;
+    failed.lock().insert(name.to_string(), exit);
+    state_hooks::unit_failed(name);
+
+    let Some(reset_sec) = reset_sec else {
+        return;
+    };
+
+    let failed = failed.clone();
+    let name = name.to_string();
+
+    std::thread::spawn(move || {
+        let _span = unit_span(&name).entered();
+        tracing::info!("resetting failed unit in {reset_sec}s");
+        std::thread::sleep(Duration::from_secs(reset_sec));
+
+        let mut failed = failed.lock();
+        if failed
+            .get(&name)
+            .is_some_and(|exit| exit.timestamp == timestamp)
+        {
+            failed.remove(&name);
+            journal::record(&name, UnitEvent::FailureReset);
+        }
+    });
 }
 
 #[derive(Serialize, Deserialize, Clone, JsonSchema)]
 /// Information about what a wpm definition executes
 #[serde(rename_all = "PascalCase")]
 pub struct Service {
-    #[serde(alias = "Type")]
     #[serde(default)]
     /// Type of service definition
     pub kind: ServiceKind,
@@ -81,11 +271,28 @@ pub struct Service {
     #[serde(default)]
     #[serde(skip_serializing_if = "<&bool>::not")]
     pub autostart: bool,
+    /// Units with a higher priority are autostarted before units with a lower priority, even
+    /// without a dependency between them (default: 0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autostart_priority: Option<i32>,
+    /// Hostnames of the machines this definition is autostarted on, compared case-insensitively (default: every machine)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autostart_on: Option<Vec<String>>,
     /// Commands executed before ExecStart in this service definition
     #[serde(skip_serializing_if = "Option::is_none")]
     pub exec_start_pre: Option<Vec<ServiceCommand>>,
-    /// Command executed by this service definition
+    /// Command executed by this service definition, required unless Script is set
+    #[serde(
+        default = "ServiceCommand::unspecified",
+        skip_serializing_if = "ServiceCommand::is_unspecified"
+    )]
     pub exec_start: ServiceCommand,
+    /// An inline script which is written to the store and executed instead of ExecStart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// The shell used to run Script (default: PowerShell)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script_kind: Option<ShellKind>,
     /// Commands executed after ExecStart in this service definition
     #[serde(skip_serializing_if = "Option::is_none")]
     pub exec_start_post: Option<Vec<ServiceCommand>>,
@@ -114,13 +321,186 @@ pub struct Service {
     /// Time to sleep in seconds before attempting to restart service (default: 1s)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub restart_sec: Option<u64>,
+    /// Time in seconds to wait between attempts to start this service definition when it fails its healthcheck, up to the RetryLimit of ExecStart (default: 0s)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_retry_sec: Option<u64>,
+    /// Time in seconds after which a failed service definition leaves the Failed state and can be started again without being reset (default: never)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_sec: Option<u64>,
+    /// Restart this service definition gracefully when the working set of its process stays above a threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart_on_memory_above: Option<MemoryThreshold>,
+    /// Record an event, and optionally restart this service definition, when the CPU usage of its process stays above a threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_on_cpu_above: Option<CpuThreshold>,
+    /// Kill the process started by this service definition if wpmd exits for any reason, including crashes
+    #[serde(default)]
+    #[serde(skip_serializing_if = "<&bool>::not")]
+    pub kill_on_daemon_exit: bool,
+    /// What to do if the executable of this service definition is already running outside of wpm
+    #[serde(default)]
+    pub on_existing_process: OnExistingProcess,
+    /// Variables from the environment of wpmd which are passed to all commands in this service definition, including when CleanEnvironment is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pass_environment: Option<Vec<String>>,
+    /// Start all commands in this service definition with an empty environment instead of the environment of wpmd (variables such as SystemRoot have to be passed explicitly)
+    #[serde(default)]
+    #[serde(skip_serializing_if = "<&bool>::not")]
+    pub clean_environment: bool,
+    /// Environment variables whose values are masked in wpmd output, in addition to variables whose names end with _TOKEN, _KEY, _SECRET or _PASSWORD
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensitive: Option<Vec<String>>,
+    /// Encoding of the output of ExecStart; output which isn't UTF-8 is relayed through wpmd and transcoded to UTF-8 in the log file, and the process is then killed when wpmd exits and can't be adopted (default: Utf8)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_encoding: Option<OutputEncoding>,
+    /// Strip ANSI escape sequences such as colors from the output of ExecStart before it is written to the log file, which relays the output through wpmd, so the process is killed when wpmd exits and can't be adopted
+    #[serde(default)]
+    #[serde(skip_serializing_if = "<&bool>::not")]
+    pub strip_ansi: bool,
+    /// Start the executable behind a Scoop shim directly when ExecStart is a shim, so that healthchecks and process tracking apply to the actual process instead of the shim
+    #[serde(default)]
+    #[serde(skip_serializing_if = "<&bool>::not")]
+    pub resolve_shims: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "PascalCase")]
+pub enum OutputEncoding {
+    /// UTF-8, written to the log file unchanged
+    #[default]
+    Utf8,
+    /// UTF-16 little endian, as written by some Windows programs
+    Utf16Le,
+    /// The OEM code page of the system, as used by many console programs
+    Oem,
+    /// Detect UTF-16LE, UTF-8 or the OEM code page from the first output of the process
+    Auto,
+}
+
+/// The environment shared by all commands of a service definition
+#[derive(Debug, Clone, Default)]
+pub struct ServiceEnvironment {
+    /// Whether commands start with an empty environment instead of inheriting from wpmd
+    pub clean: bool,
+    pub variables: Vec<(String, String)>,
+}
+
+impl ServiceEnvironment {
+    /// Sets the environment of a command, with the command's own variables taking precedence
+    pub fn apply(&self, command: &mut Command, own: Option<&[(String, String)]>) {
+        if self.clean {
+            command.env_clear();
+        }
+
+        let variables = merge_environment([Some(self.variables.as_slice()), own]);
+        if !variables.is_empty() {
+            command.envs(variables);
+        }
+    }
+
+    /// The environment extended with what happened to the process of a unit, for ExecStop and
+    /// ExecStopPost commands; the exit code is only set once the process has exited with one
+    pub fn with_runtime(
+        &self,
+        name: &str,
+        pid: u32,
+        state: UnitState,
+        exit_code: Option<i32>,
+    ) -> Self {
+        let mut variables = vec![
+            (String::from("WPM_UNIT_NAME"), name.to_string()),
+            (String::from("WPM_MAIN_PID"), pid.to_string()),
+            (String::from("WPM_STATE"), state.to_string()),
+        ];
+
+        if let Some(code) = exit_code {
+            variables.push((String::from("WPM_EXIT_CODE"), code.to_string()));
+        }
+
+        Self {
+            clean: self.clean,
+            variables: merge_environment([Some(self.variables.as_slice()), Some(&variables)]),
+        }
+    }
+}
+
+impl Service {
+    /// The environment shared by all commands of this service, with the variables passed from
+    /// wpmd underneath the service environment
+    pub fn command_environment(&self) -> ServiceEnvironment {
+        let passed = self
+            .pass_environment
+            .iter()
+            .flatten()
+            .filter_map(|name| std::env::var(name).ok().map(|value| (name.clone(), value)))
+            .collect::<Vec<_>>();
+
+        ServiceEnvironment {
+            clean: self.clean_environment,
+            variables: merge_environment([Some(passed.as_slice()), self.environment.as_deref()]),
+        }
+    }
+
+    /// Whether the value of an environment variable should be masked when it is shown, because
+    /// it is listed in Sensitive or its name looks like it holds a secret
+    pub fn is_sensitive(&self, name: &str) -> bool {
+        let upper = name.to_ascii_uppercase();
+
+        self.sensitive
+            .iter()
+            .flatten()
+            .any(|sensitive| sensitive.eq_ignore_ascii_case(name))
+            || has_sensitive_suffix(&upper)
+    }
+
+    /// Whether the output of ExecStart has to be relayed through wpmd instead of being written
+    /// to the log file directly by the process
+    ///
+    /// wpmd holds the pipes the output is relayed through, so the process is killed when wpmd
+    /// exits like with KillOnDaemonExit, and can't be adopted
+    pub fn relays_output(&self) -> bool {
+        self.strip_ansi || !matches!(self.output_encoding, None | Some(OutputEncoding::Utf8))
+    }
+
+    /// Whether this service is autostarted on the machine with the given hostname
+    pub fn autostarts_on(&self, host_name: Option<&str>) -> bool {
+        match (&self.autostart_on, host_name) {
+            (None, _) => self.autostart,
+            (Some(hosts), Some(host_name)) => {
+                self.autostart
+                    && hosts
+                        .iter()
+                        .any(|host| host.eq_ignore_ascii_case(host_name))
+            }
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, Copy, Clone, JsonSchema)]
+/// What to do when starting a unit whose process is already running outside of wpm
+pub enum OnExistingProcess {
+    /// Start another process anyway
+    #[default]
+    Ignore,
+    /// Fail to start the unit
+    Fail,
+    /// Track the existing process instead of starting a new one
+    Adopt,
+    /// Kill the existing process before starting a new one, which only matches processes running
+    /// the exact ExecStart executable and arguments, and fail the start if it doesn't exit
+    Kill,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 /// A wpm definition command
 #[serde(rename_all = "PascalCase")]
 pub struct ServiceCommand {
-    /// Executable (local file, remote file, or Scoop package)
+    /// Executable (local file, remote file, or Scoop package), required unless Shell is set
+    #[serde(
+        default = "Executable::unspecified",
+        skip_serializing_if = "Executable::is_unspecified"
+    )]
     pub executable: Executable,
     /// Arguments passed to the executable
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -131,9 +511,85 @@ pub struct ServiceCommand {
     /// Path to an environment file, containing environment variables for this command
     #[serde(skip_serializing_if = "Option::is_none")]
     pub environment_file: Option<PathBuf>,
-    /// The maximum number of retries for ExecStart (default: 5)
+    /// The maximum number of attempts to start ExecStart and pass the healthcheck, where 0 is treated as 1; only used for ExecStart (default: 5)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retry_limit: Option<u8>,
+    /// Time in seconds to wait for a hook command to complete before killing it (default: 30s)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_sec: Option<u64>,
+    /// A command line run through a shell, used instead of Executable and Arguments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// The shell used to run the Shell command line (default: Cmd)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell_kind: Option<ShellKind>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "PascalCase")]
+pub enum ShellKind {
+    /// cmd.exe
+    #[default]
+    Cmd,
+    /// Windows PowerShell (powershell.exe)
+    PowerShell,
+    /// PowerShell 7+ (pwsh.exe)
+    Pwsh,
+}
+
+impl ShellKind {
+    pub fn program(&self) -> &'static str {
+        match self {
+            ShellKind::Cmd => "cmd.exe",
+            ShellKind::PowerShell => "powershell.exe",
+            ShellKind::Pwsh => "pwsh.exe",
+        }
+    }
+
+    /// File extension of scripts run by this shell
+    pub fn script_extension(&self) -> &'static str {
+        match self {
+            ShellKind::Cmd => "cmd",
+            ShellKind::PowerShell | ShellKind::Pwsh => "ps1",
+        }
+    }
+
+    /// The arguments which make this shell run a script file and exit
+    pub fn script_arguments(&self, path: &Path) -> Vec<String> {
+        let path = path.to_string_lossy().to_string();
+        match self {
+            ShellKind::Cmd => vec!["/D".to_string(), "/C".to_string(), path],
+            ShellKind::PowerShell | ShellKind::Pwsh => vec![
+                "-NoLogo".to_string(),
+                "-NoProfile".to_string(),
+                "-NonInteractive".to_string(),
+                "-ExecutionPolicy".to_string(),
+                "Bypass".to_string(),
+                "-File".to_string(),
+                path,
+            ],
+        }
+    }
+
+    /// The arguments which make this shell run a command line and exit
+    pub fn arguments(&self, line: &str) -> Vec<String> {
+        match self {
+            // /S makes cmd.exe strip the outer quotes and keep everything in between as-is
+            ShellKind::Cmd => vec![
+                "/D".to_string(),
+                "/S".to_string(),
+                "/C".to_string(),
+                format!("\"{line}\""),
+            ],
+            ShellKind::PowerShell | ShellKind::Pwsh => vec![
+                "-NoLogo".to_string(),
+                "-NoProfile".to_string(),
+                "-NonInteractive".to_string(),
+                "-Command".to_string(),
+                line.to_string(),
+            ],
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
@@ -145,6 +601,8 @@ pub enum Executable {
     Local(PathBuf),
     /// An executable file with a Scoop package dependency
     Scoop(ScoopExecutable),
+    /// A packaged (UWP or Store) app launched through shell activation
+    Aumid(AumidExecutable),
 }
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "PascalCase")]
@@ -153,6 +611,16 @@ pub struct RemoteExecutable {
     pub url: Url,
     /// Sha256 hash of the remote executable at
     pub hash: String,
+    /// Verify the hash of the cached executable before every start, downloading it again on a mismatch (default: true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify_on_start: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct AumidExecutable {
+    /// Application user model ID of the app (e.g. Microsoft.WindowsTerminal_8wekyb3d8bbwe!App)
+    pub aumid: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
@@ -201,24 +669,148 @@ pub struct ScoopManifest {
     pub target: Option<String>,
 }
 
+impl Executable {
+    /// Placeholder for commands which don't set an executable because they use a shell
+    fn unspecified() -> Self {
+        Executable::Local(PathBuf::new())
+    }
+
+    fn is_unspecified(&self) -> bool {
+        matches!(self, Executable::Local(path) if path.as_os_str().is_empty())
+    }
+
+    /// Whether this is a shell link (.lnk), which has to be opened through the shell
+    pub fn is_shell_link(&self) -> bool {
+        matches!(self, Executable::Local(path) if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("lnk")))
+    }
+}
+
+/// The source an executable was declared with, which never requires downloading or installing
+/// it; use [`Executable::pathbuf`] for the path it is run from
 impl Display for Executable {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.pathbuf().unwrap().to_string_lossy())
+        match self {
+            Executable::Local(path) => write!(f, "{}", path.display()),
+            Executable::Remote(remote) => write!(f, "{}", remote.url),
+            Executable::Aumid(app) => write!(f, "shell:AppsFolder\\{}", app.aumid),
+            Executable::Scoop(ScoopExecutable::Package(package)) => {
+                write!(f, "scoop:{}@{}", package.package, package.version)
+            }
+            Executable::Scoop(ScoopExecutable::Manifest(manifest)) => {
+                write!(f, "scoop:{}@{}", manifest.package, manifest.version)
+            }
+        }
     }
 }
 
-impl TryInto<PathBuf> for Executable {
+impl TryFrom<&Executable> for PathBuf {
     type Error = ProcessManagerError;
 
-    fn try_into(self) -> Result<PathBuf, Self::Error> {
-        self.pathbuf()
+    fn try_from(executable: &Executable) -> Result<Self, Self::Error> {
+        executable.pathbuf()
+    }
+}
+
+/// The executable and arguments behind a Scoop shim, read from the .shim file next to it, or
+/// None if the executable isn't a shim
+fn read_shim(executable: &Path) -> Option<(PathBuf, Vec<String>)> {
+    let contents = std::fs::read_to_string(executable.with_extension("shim")).ok()?;
+
+    let mut target = None;
+    let mut arguments = vec![];
+
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        match key.trim() {
+            "path" => target = Some(PathBuf::from(value.trim().trim_matches('"'))),
+            "args" => arguments = split_command_line(value),
+            _ => {}
+        }
+    }
+
+    Some((target?, arguments))
+}
+
+/// Splits a command line into arguments following the quoting rules of Windows programs, where
+/// quotes group whitespace into an argument, `""` within quotes is a literal quote, and
+/// backslashes are only escapes when they are followed by a quote
+fn split_command_line(line: &str) -> Vec<String> {
+    let mut arguments = vec![];
+    let mut current = String::new();
+    let mut in_argument = false;
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' if !quoted => {
+                if in_argument {
+                    arguments.push(std::mem::take(&mut current));
+                    in_argument = false;
+                }
+            }
+            '\\' => {
+                let mut backslashes = 1;
+                while chars.next_if_eq(&'\\').is_some() {
+                    backslashes += 1;
+                }
+
+                // backslashes before a quote escape each other and then the quote
+                if chars.peek() == Some(&'"') {
+                    current.extend(std::iter::repeat_n('\\', backslashes / 2));
+                    if backslashes % 2 == 1 {
+                        current.push('"');
+                        chars.next();
+                    }
+                } else {
+                    current.extend(std::iter::repeat_n('\\', backslashes));
+                }
+
+                in_argument = true;
+            }
+            '"' => {
+                if quoted && chars.next_if_eq(&'"').is_some() {
+                    current.push('"');
+                } else {
+                    quoted = !quoted;
+                }
+
+                in_argument = true;
+            }
+            c => {
+                current.push(c);
+                in_argument = true;
+            }
+        }
+    }
+
+    if in_argument {
+        arguments.push(current);
     }
+
+    arguments
+}
+
+fn scoop_dir() -> Result<PathBuf, ProcessManagerError> {
+    home_dir().map(|home| home.join("scoop")).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "could not find the home directory to locate scoop in",
+        )
+        .into()
+    })
 }
 
 impl Executable {
     pub fn pathbuf(&self) -> Result<PathBuf, ProcessManagerError> {
         match self {
             Executable::Local(local) => Ok(local.clone()),
+            Executable::Aumid(_) => self.cached_executable_path(),
             Executable::Remote(remote) => {
                 let cached_executable_path = self.cached_executable_path()?;
                 if cached_executable_path.is_file() {
@@ -228,13 +820,18 @@ impl Executable {
                     );
                     Ok(cached_executable_path)
                 } else {
-                    tracing::info!("downloading and caching executable from {}", remote.url);
+                    tracing::info!(
+                        "downloading and caching executable from {}",
+                        redacted_url(&remote.url)
+                    );
                     self.download_remote_executable()?;
                     Ok(cached_executable_path)
                 }
             }
             Executable::Scoop(scoop) => match scoop {
-                ScoopExecutable::Package(_) => todo!(),
+                ScoopExecutable::Package(_) => Err(ProcessManagerError::UnsupportedScoopPackage(
+                    self.to_string(),
+                )),
                 ScoopExecutable::Manifest(manifest) => {
                     let cached_executable_path = self.cached_executable_path()?;
                     if cached_executable_path.is_file() {
@@ -260,6 +857,7 @@ impl Executable {
     pub fn cached_executable_path(&self) -> Result<PathBuf, ProcessManagerError> {
         match self {
             Executable::Local(executable) => Ok(executable.clone()),
+            Executable::Aumid(app) => Ok(PathBuf::from(format!("shell:AppsFolder\\{}", app.aumid))),
             Executable::Remote(remote) => {
                 let stringified = remote.url.to_string();
                 let filename = stringified
@@ -276,15 +874,15 @@ impl Executable {
                 stringified_parent = stringified_parent.replace("/", "_").to_string();
                 stringified_parent = stringified_parent.trim_end_matches("_").to_string();
 
-                let cache_parent_dir = wpm_store_dir().join(&stringified_parent);
+                let cache_parent_dir = wpm_store_dir()?.join(&stringified_parent);
                 std::fs::create_dir_all(&cache_parent_dir)?;
                 Ok(cache_parent_dir.join(filename).clone())
             }
             Executable::Scoop(scoop) => match scoop {
-                ScoopExecutable::Package(_) => todo!(),
-                ScoopExecutable::Manifest(manifest) => Ok(home_dir()
-                    .unwrap()
-                    .join("scoop")
+                ScoopExecutable::Package(_) => Err(ProcessManagerError::UnsupportedScoopPackage(
+                    self.to_string(),
+                )),
+                ScoopExecutable::Manifest(manifest) => Ok(scoop_dir()?
                     .join("apps")
                     .join(&manifest.package)
                     .join(&manifest.version)
@@ -298,15 +896,43 @@ impl Executable {
         }
     }
 
+    /// Re-hashes a cached remote executable and downloads it again if it doesn't match the
+    /// expected hash, protecting against partial downloads and local modifications
+    pub fn verify_cached_executable(&self) -> Result<(), ProcessManagerError> {
+        let Executable::Remote(remote) = self else {
+            return Ok(());
+        };
+
+        if !remote.verify_on_start.unwrap_or(true) {
+            return Ok(());
+        }
+
+        let path = self.cached_executable_path()?;
+        if !path.is_file() {
+            return Ok(());
+        }
+
+        let digest = sha256::try_digest(path.as_path())?;
+        if digest != remote.hash {
+            tracing::warn!(
+                "cached executable {} does not match the expected hash (expected {}, actual {digest}), downloading it again",
+                path.display(),
+                remote.hash
+            );
+
+            std::fs::remove_file(&path)?;
+            self.download_remote_executable()?;
+        }
+
+        Ok(())
+    }
+
     pub fn download_remote_executable(&self) -> Result<(), ProcessManagerError> {
         match self {
-            Executable::Local(_) => {}
+            Executable::Local(_) | Executable::Aumid(_) => {}
             Executable::Remote(remote) => {
                 if let Ok(path) = self.cached_executable_path() {
-                    let bytes = reqwest_client()
-                        .get(remote.url.to_string())
-                        .send()?
-                        .bytes()?;
+                    let bytes = fetch_bytes(&remote.url)?;
 
                     let digest = sha256::digest(&*bytes);
 
@@ -329,11 +955,7 @@ impl Executable {
             Executable::Scoop(scoop) => match scoop {
                 ScoopExecutable::Package(_) => {}
                 ScoopExecutable::Manifest(manifest) => {
-                    let scoop = home_dir()
-                        .unwrap()
-                        .join("scoop")
-                        .join("shims")
-                        .join("scoop.cmd");
+                    let scoop = scoop_dir()?.join("shims").join("scoop.cmd");
 
                     let output = Command::new(scoop)
                         .arg("install")
@@ -357,6 +979,96 @@ impl Executable {
 }
 
 impl ServiceCommand {
+    /// The command line written to the log of wpmd, which is redacted like in
+    /// [`Definition::redacted`] so that secrets don't end up in support bundles
+    pub fn logged_command_line(&self) -> String {
+        let mut executable = self.executable.clone();
+        redact_executable(&mut executable);
+
+        match &self.arguments {
+            Some(_) if self.shell.is_some() => format!("{executable} {REDACTED}"),
+            Some(arguments) => {
+                let mut arguments = arguments.clone();
+                redact_arguments(&mut arguments);
+                format!("{executable} {}", quote_arguments(&arguments))
+            }
+            None => executable.to_string(),
+        }
+    }
+
+    /// Placeholder for the ExecStart of services which use a Script
+    fn unspecified() -> Self {
+        Self {
+            executable: Executable::unspecified(),
+            arguments: None,
+            environment: None,
+            environment_file: None,
+            retry_limit: None,
+            timeout_sec: None,
+            shell: None,
+            shell_kind: None,
+        }
+    }
+
+    fn is_unspecified(&self) -> bool {
+        self.executable.is_unspecified() && self.shell.is_none()
+    }
+
+    /// Replaces the executable and arguments with the configured shell if a Shell command line
+    /// is set, or checks that an executable is set otherwise
+    pub fn resolve_shell(&mut self, kind: &'static str) -> Result<(), ProcessManagerError> {
+        match &self.shell {
+            Some(line) => {
+                let shell = self.shell_kind.unwrap_or_default();
+                self.executable = Executable::Local(PathBuf::from(shell.program()));
+                self.arguments = Some(shell.arguments(line));
+            }
+            None => {
+                if self.executable.is_unspecified() {
+                    return Err(ProcessManagerError::MissingCommand(kind));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the arguments to a command, passing them through unquoted for cmd.exe, which
+    /// doesn't follow the quoting rules used by [`Command::args`]
+    fn apply_arguments(&self, command: &mut Command) {
+        let Some(arguments) = &self.arguments else {
+            return;
+        };
+
+        if self.shell.is_some() && self.shell_kind.unwrap_or_default() == ShellKind::Cmd {
+            CurrentPlatform::add_raw_arguments(command, arguments);
+        } else {
+            command.args(arguments);
+        }
+    }
+
+    /// Points a local executable which is a Scoop shim at the executable behind it, passing the
+    /// arguments of the shim before the arguments of this command
+    pub fn resolve_shim(&mut self) {
+        let Executable::Local(executable) = &self.executable else {
+            return;
+        };
+
+        let Some((target, mut arguments)) = read_shim(executable) else {
+            return;
+        };
+
+        tracing::info!(
+            "resolved shim {} to {}",
+            executable.display(),
+            target.display()
+        );
+
+        arguments.extend(self.arguments.take().into_iter().flatten());
+        self.executable = Executable::Local(target);
+        self.arguments = (!arguments.is_empty()).then_some(arguments);
+    }
+
     pub fn resolve_user_profile(&mut self) {
         let home_dir = dirs::home_dir()
             .expect("could not find home dir")
@@ -383,14 +1095,15 @@ impl ServiceCommand {
             if let Ok(environment) =
                 serde_envfile::from_file::<serde_envfile::Value>(&environment_file)
             {
-                for (k, v) in environment.iter() {
-                    match &mut self.environment {
-                        None => self.environment = Some(vec![(k.clone(), v.clone())]),
-                        Some(e) => {
-                            e.push((k.clone(), v.clone()));
-                        }
-                    }
-                }
+                let from_file = environment
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect::<Vec<_>>();
+
+                self.environment = Some(merge_environment([
+                    Some(from_file.as_slice()),
+                    self.environment.as_deref(),
+                ]));
             }
         }
 
@@ -399,33 +1112,139 @@ impl ServiceCommand {
         }
     }
 
-    pub fn to_silent_command(&self, global_environment: Option<Vec<(String, String)>>) -> Command {
-        let mut command = Command::new(self.executable.pathbuf().unwrap());
-        if let Some(arguments) = &self.arguments {
-            command.args(arguments);
+    /// Runs this command as a lifecycle hook, capturing its output in the unit log if one is
+    /// given and killing it if it has not completed after `timeout_sec`
+    pub fn run_hook(
+        &self,
+        phase: &str,
+        environment: &ServiceEnvironment,
+        log_path: Option<&Path>,
+    ) -> Result<Output, ProcessManagerError> {
+        let timeout = Duration::from_secs(self.timeout_sec.unwrap_or(DEFAULT_HOOK_TIMEOUT_SEC));
+        let mut child = self.to_silent_command(environment)?.spawn()?;
+
+        // the pipes are drained on separate threads so that a chatty hook can't block on a
+        // full pipe buffer, and so that partial output is still available after a timeout
+        let stdout = Arc::new(Mutex::new(vec![]));
+        let stderr = Arc::new(Mutex::new(vec![]));
+        let mut readers = vec![];
+
+        if let Some(pipe) = child.stdout.take() {
+            readers.push(drain_pipe(pipe, stdout.clone()));
+        }
+
+        if let Some(pipe) = child.stderr.take() {
+            readers.push(drain_pipe(pipe, stderr.clone()));
         }
 
-        let mut environment_variables = vec![];
+        let deadline = Instant::now() + timeout;
+        let mut timed_out = false;
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
 
-        if let Some(environment) = global_environment {
-            environment_variables.extend(environment.clone());
+            if Instant::now() >= deadline {
+                tracing::warn!(
+                    "{phase} command did not complete within {}s, killing process {}",
+                    timeout.as_secs(),
+                    child.id()
+                );
+
+                timed_out = true;
+                let _ = child.kill();
+                break child.wait()?;
+            }
+
+            std::thread::sleep(Duration::from_millis(50));
+        };
+
+        // grandchildren can keep the pipes open after a timeout, so only wait for the
+        // readers to hit EOF when the hook exited on its own
+        if !timed_out {
+            for reader in readers {
+                let _ = reader.join();
+            }
         }
 
-        if let Some(environment) = &self.environment {
-            environment_variables.extend(environment.clone());
+        let mut output = Output {
+            status,
+            stdout: stdout.lock().clone(),
+            stderr: stderr.lock().clone(),
+        };
+
+        if timed_out {
+            output.stderr.extend(
+                format!(
+                    "command timed out after {}s and was killed\n",
+                    timeout.as_secs()
+                )
+                .as_bytes(),
+            );
         }
 
-        if !environment_variables.is_empty() {
-            command.envs(environment_variables);
+        if let Some(log_path) = log_path {
+            log_command_output(log_path, phase, &output);
         }
 
-        command.stdout(std::process::Stdio::null());
-        command.stderr(std::process::Stdio::null());
+        Ok(output)
+    }
 
-        command
+    pub fn to_silent_command(
+        &self,
+        environment: &ServiceEnvironment,
+    ) -> Result<Command, ProcessManagerError> {
+        let mut command = Command::new(self.executable.pathbuf()?);
+        self.apply_arguments(&mut command);
+
+        environment.apply(&mut command, self.environment.as_deref());
+
+        command.stdout(std::process::Stdio::piped());
+        command.stderr(std::process::Stdio::piped());
+
+        Ok(command)
     }
 }
 
+/// Merges layers of environment variables, where later layers take precedence over earlier
+/// ones; names are compared case-insensitively, as they are on Windows, and each variable
+/// keeps the position of its first occurrence with the value of its last
+pub fn merge_environment<'a>(
+    layers: impl IntoIterator<Item = Option<&'a [(String, String)]>>,
+) -> Vec<(String, String)> {
+    let mut merged: Vec<(String, String)> = vec![];
+
+    for (name, value) in layers.into_iter().flatten().flatten() {
+        match merged
+            .iter_mut()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+        {
+            Some(variable) => *variable = (name.clone(), value.clone()),
+            None => merged.push((name.clone(), value.clone())),
+        }
+    }
+
+    merged
+}
+
+/// The environment variable holding the store path of a resource, such as WPM_RESOURCE_CONFIG
+/// for a resource named CONFIG
+fn resource_variable(identifier: &str) -> String {
+    let name = identifier
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    format!("WPM_RESOURCE_{name}")
+}
+
 fn replace_interpolations(input: &str, resources: &HashMap<String, PathBuf>) -> String {
     let mut output = input.to_string();
 
@@ -445,6 +1264,62 @@ fn replace_interpolations(input: &str, resources: &HashMap<String, PathBuf>) ->
 }
 
 impl Definition {
+    /// The environment variables set for ExecStart, on top of those inherited from wpmd
+    pub fn exec_start_environment(&self) -> Vec<(String, String)> {
+        merge_environment([
+            Some(self.service.command_environment().variables.as_slice()),
+            self.service.exec_start.environment.as_deref(),
+        ])
+    }
+
+    /// The whole environment ExecStart is started with: the environment of wpmd (only the
+    /// variables in PassEnvironment with CleanEnvironment) under the service and ExecStart ones
+    pub fn effective_environment(&self) -> Vec<(String, String)> {
+        let inherited = if self.service.clean_environment {
+            vec![]
+        } else {
+            std::env::vars_os()
+                .filter_map(|(name, value)| {
+                    Some((name.into_string().ok()?, value.into_string().ok()?))
+                })
+                .collect()
+        };
+
+        merge_environment([
+            Some(inherited.as_slice()),
+            Some(self.exec_start_environment().as_slice()),
+        ])
+    }
+
+    /// Writes an inline Script to the store and points ExecStart at it; the file is only
+    /// rewritten when the script has changed
+    pub fn materialize_script(&mut self) -> Result<(), ProcessManagerError> {
+        let Some(script) = &self.service.script else {
+            return Ok(());
+        };
+
+        if !self.service.exec_start.is_unspecified() {
+            return Err(ProcessManagerError::ScriptWithExecStart);
+        }
+
+        let shell = self.service.script_kind.unwrap_or(ShellKind::PowerShell);
+        let path = wpm_store_dir()?.join(format!(
+            "{}.script.{}",
+            self.unit.name,
+            shell.script_extension()
+        ));
+
+        if std::fs::read_to_string(&path).ok().as_ref() != Some(script) {
+            tracing::info!("writing script {}", path.display());
+            std::fs::write(&path, script)?;
+        }
+
+        self.service.exec_start.executable = Executable::Local(PathBuf::from(shell.program()));
+        self.service.exec_start.arguments = Some(shell.script_arguments(&path));
+
+        Ok(())
+    }
+
     pub fn resolve_resources(&mut self) -> Result<(), ProcessManagerError> {
         if let Some(resources) = &self.resources {
             let mut resource_map = HashMap::new();
@@ -456,26 +1331,13 @@ impl Definition {
                     }
                     Ok(store_ref) => {
                         if !store_ref.is_file() {
-                            tracing::info!(
-                                "{}: adding resource {} to store",
-                                self.unit.name,
-                                store_ref.display()
-                            );
-                            match reqwest_client().get(url.to_string()).send() {
-                                Err(error) => {
-                                    tracing::error!("{error}");
-                                    continue 'resources;
-                                }
-                                Ok(response) => {
-                                    std::fs::write(&store_ref, response.bytes()?)?;
-                                }
+                            tracing::info!("adding resource {} to store", store_ref.display());
+                            if let Err(error) = download_resource(url, &store_ref) {
+                                tracing::error!("{error}");
+                                continue 'resources;
                             }
                         } else {
-                            tracing::debug!(
-                                "{}: found resource {} in store",
-                                self.unit.name,
-                                store_ref.display()
-                            )
+                            tracing::debug!("found resource {} in store", store_ref.display())
                         }
 
                         resource_map.insert(identifier.clone(), store_ref);
@@ -487,6 +1349,22 @@ impl Definition {
                 *v = replace_interpolations(v, &resource_map);
             }
 
+            // resources are also exposed to every command through the service environment, before
+            // the variables of the service so that those take precedence
+            let mut variables = resource_map
+                .iter()
+                .map(|(identifier, path)| {
+                    (
+                        resource_variable(identifier),
+                        path.to_string_lossy().into_owned(),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            variables.sort();
+            variables.extend(self.service.environment.take().into_iter().flatten());
+            self.service.environment = Some(variables);
+
             for arg in self.service.exec_start.arguments.iter_mut().flatten() {
                 *arg = replace_interpolations(arg, &resource_map);
             }
@@ -539,125 +1417,262 @@ impl Definition {
         &self,
         running: Arc<Mutex<HashMap<String, ProcessState>>>,
         completed: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
-        terminated: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
-    ) -> Result<Arc<SharedChild>, ProcessManagerError> {
+        failed: Arc<Mutex<HashMap<String, UnitExit>>>,
+        terminated: Arc<Mutex<HashMap<String, UnitExit>>>,
+    ) -> Result<Child, ProcessManagerError> {
         let name = self.unit.name.to_string();
-        tracing::info!("{name}: starting unit");
+        tracing::info!("starting unit");
+
+        // failures to spawn the process are recorded as the reason the unit failed
+        let spawn_failed = |error: ProcessManagerError| {
+            record_failure(
+                &failed,
+                &name,
+                ExitReason::SpawnError(error.to_string()),
+                self.service.reset_sec,
+            );
 
-        for command in self.service.exec_start_pre.iter().flatten() {
-            let stringified = if let Some(args) = &command.arguments {
-                format!("{} {}", command.executable, args.join(" "))
-            } else {
-                command.executable.to_string()
+            error
+        };
+
+        // truncate the log here rather than when spawning ExecStart so that the output of
+        // pre-start commands is kept alongside the output of the process
+        let log_path = self
+            .log_path()
+            .map_err(ProcessManagerError::from)
+            .map_err(spawn_failed)?;
+
+        File::create(&log_path)
+            .map_err(|error| ProcessManagerError::LogFile {
+                path: log_path.clone(),
+                error,
+            })
+            .map_err(spawn_failed)?;
+
+        if let Some(commands) = &self.service.exec_start_pre {
+            let _span = tracing::info_span!("pre_start").entered();
+
+            for command in commands {
+                let stringified = command.logged_command_line();
+
+                tracing::info!("executing pre-start command - {stringified}");
+                command.run_hook(
+                    "pre-start",
+                    &self.service.command_environment(),
+                    Some(&log_path),
+                )?;
+            }
+        }
+
+        let child = {
+            let _span = tracing::info_span!("spawn").entered();
+
+            self.service
+                .exec_start
+                .executable
+                .verify_cached_executable()
+                .map_err(spawn_failed)?;
+
+            let exec_start = &self.service.exec_start;
+            let arguments = quote_arguments(exec_start.arguments.as_deref().unwrap_or_default());
+
+            let spawn_error = |error| ProcessManagerError::Spawn {
+                executable: exec_start.executable.to_string(),
+                error,
+            };
+
+            // shell activated processes are tracked by their pid, and their output can't be
+            // redirected to the unit log
+            let spawned = match &exec_start.executable {
+                Executable::Aumid(app) => {
+                    tracing::debug!("activating {}", app.aumid);
+                    CurrentPlatform::activate_application(&app.aumid, &arguments)
+                        .map(Child::Pid)
+                        .map_err(spawn_error)
+                }
+                executable if executable.is_shell_link() => executable.pathbuf().and_then(|path| {
+                    tracing::debug!("opening {}", path.display());
+                    CurrentPlatform::open_with_shell(
+                        &path,
+                        &arguments,
+                        self.service.working_directory.as_deref(),
+                    )
+                    .map(Child::Pid)
+                    .map_err(spawn_error)
+                }),
+                _ => Command::try_from(self).and_then(|mut command| {
+                    tracing::debug!("spawning {command:?}");
+                    SharedChild::spawn(&mut command)
+                        .map(|child| {
+                            if self.service.relays_output() {
+                                let encoding = self.service.output_encoding.unwrap_or_default();
+
+                                if let Some(stdout) = child.take_stdout() {
+                                    log_output::forward(
+                                        stdout,
+                                        log_path.clone(),
+                                        encoding,
+                                        self.service.strip_ansi,
+                                    );
+                                }
+
+                                if let Some(stderr) = child.take_stderr() {
+                                    log_output::forward(
+                                        stderr,
+                                        log_path.clone(),
+                                        encoding,
+                                        self.service.strip_ansi,
+                                    );
+                                }
+                            }
+
+                            Child::Shared(Arc::new(child))
+                        })
+                        .map_err(spawn_error)
+                }),
             };
 
-            tracing::info!("{name}: executing pre-start command - {stringified}");
-            let mut command = command.to_silent_command(self.service.environment.clone());
-            command.output()?;
+            spawned.map_err(spawn_failed)?
+        };
+        // relayed output is read from pipes held by wpmd, so the process can't outlive wpmd
+        if self.service.kill_on_daemon_exit || self.service.relays_output() {
+            if let Err(error) = CurrentPlatform::kill_on_daemon_exit(child.id()) {
+                tracing::warn!(
+                    "process {} will not be killed if wpmd exits: {error}",
+                    child.id()
+                );
+            }
         }
 
-        let mut command = Command::from(self);
-        let child = SharedChild::spawn(&mut command)?;
-        let thread_child = Arc::new(child);
-        let state_child = thread_child.clone();
+        let thread_child = child.clone();
 
         let completed_thread = completed.clone();
+        let failed_thread = failed.clone();
+        let reset_sec = self.service.reset_sec;
         let running_thread = running.clone();
         let exec_start_post_thread = self.service.exec_start_post.clone();
         let exec_stop_thread = self.service.exec_stop.clone();
-        let environment_thread = self.service.environment.clone();
+        let environment_thread = self.service.command_environment();
 
         match self.service.kind {
             ServiceKind::Simple => {
-                self.monitor_child(
-                    Child::Shared(thread_child),
-                    running_thread,
-                    terminated.clone(),
-                );
+                self.monitor_child(thread_child, running_thread, terminated.clone());
             }
-            // oneshots block the main thread
+            // oneshots are tracked as running until they exit, and are waited on in a separate
+            // thread so that they don't block the handling of other socket messages
             ServiceKind::Oneshot => {
-                match thread_child.wait() {
-                    Ok(exit_status) => {
-                        if exit_status.success() {
-                            completed_thread.lock().insert(name.clone(), Utc::now());
-                            tracing::info!(
-                                "{name}: oneshot unit terminated with successful exit code {}",
-                                exit_status.code().unwrap()
-                            );
+                running.lock().insert(
+                    name.clone(),
+                    ProcessState {
+                        child: thread_child.clone(),
+                        timestamp: clock::now(),
+                    },
+                );
+
+                std::thread::spawn(move || {
+                    let _span = unit_span(&name).entered();
+                    let result = thread_child.wait();
+                    let killed = exit_reason_override(thread_child.id());
 
-                            for command in exec_start_post_thread.iter().flatten() {
-                                let stringified = if let Some(args) = &command.arguments {
-                                    format!("{} {}", command.executable, args.join(" "))
-                                } else {
-                                    command.executable.to_string()
-                                };
+                    // if the unit is no longer in the running map it was stopped manually
+                    if !running_thread.lock().contains_key(&name) {
+                        return;
+                    }
 
+                    match result {
+                        Ok(exit_status) => {
+                            if exit_status.success() {
+                                completed_thread.lock().insert(name.clone(), clock::now());
+                                journal::record(&name, UnitEvent::Completed);
                                 tracing::info!(
-                                    "{name}: executing post-start command - {stringified}"
+                                    "oneshot unit terminated with successful exit code {}",
+                                    exit_status.code().unwrap()
                                 );
-                                let mut command =
-                                    command.to_silent_command(environment_thread.clone());
-                                let _ = command.output();
-                            }
 
-                            for command in exec_stop_thread.iter().flatten() {
-                                let stringified = if let Some(args) = &command.arguments {
-                                    format!("{} {}", command.executable, args.join(" "))
-                                } else {
-                                    command.executable.to_string()
-                                };
-
-                                tracing::info!("{name}: executing cleanup command - {stringified}");
-                                let mut command =
-                                    command.to_silent_command(environment_thread.clone());
-                                let _ = command.output();
+                                for command in exec_start_post_thread.iter().flatten() {
+                                    let stringified = command.logged_command_line();
+
+                                    tracing::info!("executing post-start command - {stringified}");
+                                    let _ = command.run_hook(
+                                        "post-start",
+                                        &environment_thread,
+                                        Some(&log_path),
+                                    );
+                                }
+
+                                for command in exec_stop_thread.iter().flatten() {
+                                    let stringified = command.logged_command_line();
+
+                                    tracing::info!("executing cleanup command - {stringified}");
+                                    let environment = environment_thread.with_runtime(
+                                        &name,
+                                        thread_child.id(),
+                                        UnitState::Completed,
+                                        exit_status.code(),
+                                    );
+                                    let _ = command.run_hook("stop", &environment, Some(&log_path));
+                                }
+                            } else {
+                                let reason = killed.unwrap_or(ExitReason::from(exit_status));
+                                tracing::warn!("oneshot unit terminated: {reason}");
+                                journal::record(&name, UnitEvent::Exited(reason.clone()));
+                                record_failure(&failed_thread, &name, reason, reset_sec);
                             }
-                        } else {
-                            tracing::warn!(
-                                "{name}: oneshot unit terminated with failure exit code {}",
-                                exit_status.code().unwrap()
+                        }
+                        Err(error) => {
+                            record_failure(
+                                &failed_thread,
+                                &name,
+                                ExitReason::WaitError(error.to_string()),
+                                reset_sec,
                             );
+                            tracing::error!("{error}");
                         }
                     }
-                    Err(error) => {
-                        tracing::error!("{name}: {error}");
-                    }
-                }
 
-                running_thread.lock().remove(&name);
+                    // only removed once the outcome has been recorded so that units waiting on
+                    // this oneshot never observe it as neither running nor completed
+                    running_thread.lock().remove(&name);
+                });
             }
-            // forking also blocks the main thread
-            ServiceKind::Forking => match thread_child.wait() {
-                Ok(exit_status) => {
-                    if exit_status.success() {
-                        tracing::info!(
-                            "{name}: forking unit terminated with successful exit code {}",
-                            exit_status.code().unwrap()
-                        );
-                    } else {
-                        tracing::warn!(
-                            "{name}: forking unit terminated with failure exit code {}",
-                            exit_status.code().unwrap()
-                        );
+            // the forking process is waited on in a separate thread, the forked process is
+            // picked up by the process healthcheck
+            ServiceKind::Forking => {
+                std::thread::spawn(move || {
+                    let _span = unit_span(&name).entered();
+                    match thread_child.wait() {
+                        Ok(exit_status) => {
+                            if exit_status.success() {
+                                tracing::info!(
+                                    "forking unit terminated with successful exit code {}",
+                                    exit_status.code().unwrap()
+                                );
+                            } else {
+                                tracing::warn!(
+                                    "forking unit terminated with failure exit code {}",
+                                    exit_status.code().unwrap()
+                                );
+                            }
+                        }
+                        Err(error) => {
+                            tracing::error!("{error}");
+                        }
                     }
-                }
-                Err(error) => {
-                    tracing::error!("{name}: {error}");
-                }
-            },
+                });
+            }
         }
 
-        Ok(state_child)
+        Ok(child)
     }
 
     pub fn healthcheck(
         &self,
-        child: Arc<SharedChild>,
+        child: Child,
         running: Arc<Mutex<HashMap<String, ProcessState>>>,
-        failed: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
-        terminated: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+        failed: Arc<Mutex<HashMap<String, UnitExit>>>,
+        terminated: Arc<Mutex<HashMap<String, UnitExit>>>,
     ) -> Result<(), ProcessManagerError> {
+        let _span = tracing::info_span!("healthcheck").entered();
         let mut passed = false;
         let name = self.unit.name.clone();
 
@@ -671,11 +1686,12 @@ impl Definition {
 
         // we don't want to run redundant healthchecks
         if running.lock().contains_key(&name) {
-            tracing::info!("{name}: passed healthcheck");
+            tracing::info!("passed healthcheck");
             return Ok(());
         }
 
         let mut forked_pid = None;
+        let mut failure = None;
 
         match &self.service.healthcheck {
             Some(Healthcheck::Command(healthcheck)) => {
@@ -685,70 +1701,129 @@ impl Definition {
                     format!(
                         "{} {}",
                         healthcheck.executable.to_string_lossy(),
-                        args.join(" ")
+                        quote_arguments(args)
                     )
                 } else {
                     healthcheck.executable.to_string_lossy().to_string()
                 };
 
-                tracing::info!("{name}: running command healthcheck - {stringified} ({seconds}s)");
+                tracing::info!("running command healthcheck - {stringified} ({seconds}s)");
                 std::thread::sleep(Duration::from_secs(healthcheck.delay_sec));
 
-                let mut command = healthcheck.to_silent_command(self.service.environment.clone());
+                let mut command =
+                    healthcheck.to_silent_command(&self.service.command_environment());
+                let log_path = self.log_path()?;
 
-                let mut status = command.spawn()?.wait()?;
-                let mut max_attempts = healthcheck.retry_limit.unwrap_or(5);
+                let mut output = command.output()?;
+                log_command_output(&log_path, "healthcheck", &output);
+                let mut retries = healthcheck.retry_limit.unwrap_or(5);
 
-                while !status.success() && max_attempts > 0 {
-                    tracing::warn!("{name}: failed healthcheck command, retrying in {seconds}s");
+                while !output.status.success() && retries > 0 {
+                    tracing::warn!("failed healthcheck command, retrying in {seconds}s");
                     std::thread::sleep(Duration::from_secs(seconds));
-                    status = command.spawn()?.wait()?;
-                    max_attempts -= 1;
+                    output = command.output()?;
+                    log_command_output(&log_path, "healthcheck", &output);
+                    retries -= 1;
                 }
 
-                if max_attempts > 0 {
+                if output.status.success() {
                     passed = true;
+                } else {
+                    failure = Some(HealthcheckFailure::from(&output));
                 }
             }
             Some(Healthcheck::Process(healthcheck)) => {
                 let seconds = healthcheck.delay_sec;
 
-                match &healthcheck.target {
-                    None => {
-                        let child_pid = child.id();
+                match (&healthcheck.pid_file, &healthcheck.target) {
+                    (Some(pid_file), _) => {
                         tracing::info!(
-                            "{name}: running pid {child_pid} liveness healthcheck ({seconds}s)"
+                            "running pid file {} liveness healthcheck ({seconds}s)",
+                            pid_file.display()
                         );
                         std::thread::sleep(Duration::from_secs(healthcheck.delay_sec));
-                        let mut system = System::new_all();
-                        let pid = Pid::from_u32(child_pid);
-                        system.refresh_processes_specifics(
-                            ProcessesToUpdate::Some(&[pid]),
-                            true,
-                            ProcessRefreshKind::everything(),
-                        );
 
-                        if system.process(pid).is_some() {
+                        match read_pid_file(pid_file) {
+                            Ok(pid) if process_exists(pid) => {
+                                forked_pid = Some(pid);
+                                passed = true;
+                            }
+                            Ok(pid) => {
+                                tracing::warn!(
+                                    "process {pid} from {} is not running",
+                                    pid_file.display()
+                                );
+                            }
+                            Err(error) => {
+                                tracing::warn!(
+                                    "could not read a pid from {}: {error}",
+                                    pid_file.display()
+                                );
+                            }
+                        }
+
+                        if let Some(pid) = forked_pid {
+                            self.monitor_child(Child::Pid(pid), running.clone(), terminated);
+                        }
+                    }
+                    (None, None) => {
+                        let child_pid = child.id();
+                        tracing::info!("running pid {child_pid} liveness healthcheck ({seconds}s)");
+                        std::thread::sleep(Duration::from_secs(healthcheck.delay_sec));
+
+                        if process_exists(child_pid) {
                             passed = true;
                         }
                     }
-                    Some(target) => {
-                        tracing::info!("{name}: running process liveness healthcheck ({seconds}s)");
+                    (None, Some(target)) => {
+                        tracing::info!("running process liveness healthcheck ({seconds}s)");
+
+                        // processes which were already running before ExecStart was spawned
+                        // belong to something else, so the spawn time is taken before the
+                        // spawned process has a chance to exit
+                        let spawned_at = process_start_time(child.id()).unwrap_or_else(|| {
+                            SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .map_or(0, |elapsed| elapsed.as_secs().saturating_sub(1))
+                        });
+
                         std::thread::sleep(Duration::from_secs(healthcheck.delay_sec));
-                        let mut system = System::new_all();
-                        system.refresh_processes_specifics(
-                            ProcessesToUpdate::All,
-                            true,
-                            ProcessRefreshKind::everything(),
-                        );
 
                         let proc_name = target.file_name().unwrap_or_default();
+                        let command_line = healthcheck
+                            .command_line
+                            .as_deref()
+                            .map(Regex::new)
+                            .transpose()?;
+                        let parent = healthcheck.match_parent.then(|| child.id());
+
+                        let descendants = process_details(child.id())
+                            .map(|details| details.descendants)
+                            .unwrap_or_default();
+
+                        let mut candidates = find_processes(proc_name)
+                            .into_iter()
+                            .filter(|process| process.matches(command_line.as_ref(), parent))
+                            .filter(|process| {
+                                let started_after = process.start_time >= spawned_at;
+                                if !started_after {
+                                    tracing::debug!(
+                                        "ignoring process {} which was started before the unit",
+                                        process.pid
+                                    );
+                                }
 
-                        for p in system.processes_by_name(proc_name) {
-                            if forked_pid.is_none() {
-                                forked_pid = Some(p.pid().as_u32());
-                                passed = true;
-                            }
+                                started_after
+                            })
+                            .collect::<Vec<_>>();
+
+                        // descendants of the spawned process are preferred over other processes
+                        // which happened to start at the same time
+                        candidates.sort_by_key(|process| !descendants.contains(&process.pid));
+
+                        if let Some(process) = candidates.first() {
+                            forked_pid = Some(process.pid);
+                            passed = true;
                         }
 
                         if let Some(pid) = forked_pid {
@@ -761,37 +1836,44 @@ impl Definition {
         }
 
         if passed {
-            tracing::info!("{name}: passed healthcheck");
+            tracing::info!("passed healthcheck");
             running.lock().insert(
                 name.clone(),
                 match forked_pid {
                     None => ProcessState {
-                        child: Child::Shared(child),
-                        timestamp: Utc::now(),
+                        child,
+                        timestamp: clock::now(),
                     },
                     Some(pid) => ProcessState {
                         child: Child::Pid(pid),
-                        timestamp: Utc::now(),
+                        timestamp: clock::now(),
                     },
                 },
             );
 
             for command in self.service.exec_start_post.iter().flatten() {
-                let stringified = if let Some(args) = &command.arguments {
-                    format!("{} {}", command.executable, args.join(" "))
-                } else {
-                    command.executable.to_string()
-                };
+                let stringified = command.logged_command_line();
 
-                tracing::info!("{name}: executing post-start command - {stringified}");
+                tracing::info!("executing post-start command - {stringified}");
 
-                let mut command = command.to_silent_command(self.service.environment.clone());
-                command.output()?;
+                command.run_hook(
+                    "post-start",
+                    &self.service.command_environment(),
+                    Some(&self.log_path()?),
+                )?;
             }
         } else {
-            tracing::warn!("{name}: failed healthcheck");
-            failed.lock().insert(name.clone(), Utc::now());
-            return Err(ProcessManagerError::FailedHealthcheck(name.to_string()));
+            tracing::warn!("failed healthcheck");
+            record_failure(
+                &failed,
+                &name,
+                ExitReason::FailedHealthcheck(failure.clone()),
+                self.service.reset_sec,
+            );
+            return Err(ProcessManagerError::FailedHealthcheck(
+                name.to_string(),
+                failure,
+            ));
         }
 
         Ok(())
@@ -801,91 +1883,464 @@ impl Definition {
         &self,
         child: Child,
         running: Arc<Mutex<HashMap<String, ProcessState>>>,
-        terminated: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+        terminated: Arc<Mutex<HashMap<String, UnitExit>>>,
     ) {
         let running_thread = running.clone();
         let terminated_thread = terminated.clone();
 
         let name = self.unit.name.clone();
         let exec_stop_post = self.service.exec_stop_post.clone();
-        let environment = self.service.environment.clone();
+        let environment = self.service.command_environment();
         let restart_strategy = self.service.restart;
         let restart_sec = self.service.restart_sec.unwrap_or(1);
+        // output which can't be logged doesn't stop the cleanup commands from running
+        let log_path = self
+            .log_path()
+            .inspect_err(|error| {
+                tracing::error!("cleanup command output won't be logged: {error}");
+            })
+            .ok();
 
         std::thread::spawn(move || {
-            match child.wait() {
+            let _span = unit_span(&name).entered();
+
+            let pid = child.id();
+            // the unit may already have been started again with a new process by the time this
+            // one has exited, which must not be mistaken for this process still running
+            let tracked = || {
+                running_thread
+                    .lock()
+                    .get(&name)
+                    .is_some_and(|state| state.child.id() == pid)
+            };
+
+            let result = child.wait();
+            let killed = exit_reason_override(pid);
+
+            match result {
                 Ok(exit_status) => {
+                    // units which are still in the running map were not stopped by wpm
+                    let state = if tracked() {
+                        UnitState::Terminated
+                    } else {
+                        UnitState::Stopped
+                    };
+
+                    let environment =
+                        environment.with_runtime(&name, child.id(), state, exit_status.code());
+
                     // Execute cleanup commands
                     for command in exec_stop_post.iter().flatten() {
-                        let stringified = if let Some(args) = &command.arguments {
-                            format!("{} {}", command.executable, args.join(" "))
-                        } else {
-                            command.executable.to_string()
-                        };
+                        let stringified = command.logged_command_line();
 
-                        tracing::info!("{name}: executing cleanup command - {stringified}");
-                        let mut command = command.to_silent_command(environment.clone());
-                        let _ = command.output();
+                        tracing::info!("executing cleanup command - {stringified}");
+                        let _ = command.run_hook("stop-post", &environment, log_path.as_deref());
                     }
 
                     // Handle process termination
-                    if running_thread.lock().contains_key(&name) {
-                        let should_restart = if exit_status.success() {
-                            tracing::warn!(
-                                "{name}: process {} terminated with success exit code {}",
-                                child.id(),
-                                exit_status.code().unwrap()
-                            );
-
-                            matches!(restart_strategy, RestartStrategy::Always)
-                        } else {
-                            tracing::warn!(
-                                "{name}: process {} terminated with failure exit code {}",
-                                child.id(),
-                                exit_status.code().unwrap()
-                            );
-
-                            matches!(
-                                restart_strategy,
-                                RestartStrategy::Always | RestartStrategy::OnFailure
-                            )
-                        };
-
-                        if should_restart {
+                    if tracked() {
+                        let reason = killed.unwrap_or(ExitReason::from(exit_status));
+                        tracing::warn!("process {} terminated: {reason}", child.id());
+                        journal::record(&name, UnitEvent::Exited(reason.clone()));
+
+                        if restart_strategy.should_restart(&reason)
+                            && restart_budget::allow_restart(&name)
+                        {
                             running_thread.lock().remove(&name);
-                            tracing::info!(
-                                "{name}: restarting terminated process in {restart_sec}s"
-                            );
-
-                            std::thread::sleep(Duration::from_secs(restart_sec));
-
-                            // Send reset and start messages
-                            for message in [
-                                SocketMessage::Reset(vec![name.to_string()]),
-                                SocketMessage::Start(vec![name.to_string()]),
-                            ] {
-                                if let Err(error) = send_message("wpmd.sock", message) {
-                                    tracing::error!("{name}: {error}");
-                                }
-                            }
-
+                            schedule_restart(name, restart_sec);
                             return;
                         } else {
-                            terminated_thread.lock().insert(name.clone(), Utc::now());
+                            terminated_thread
+                                .lock()
+                                .insert(name.clone(), UnitExit::now(reason));
                         }
                     }
                 }
                 Err(error) => {
-                    tracing::error!("{name}: {error}");
+                    tracing::error!("{error}");
+
+                    if tracked() {
+                        terminated_thread.lock().insert(
+                            name.clone(),
+                            UnitExit::now(ExitReason::WaitError(error.to_string())),
+                        );
+                    }
                 }
             }
 
-            running_thread.lock().remove(&name);
+            if tracked() {
+                running_thread.lock().remove(&name);
+            }
         });
     }
 
-    pub fn log_path(&self) -> PathBuf {
-        wpm_log_dir().join(format!("{}.log", self.unit.name))
+    /// PIDs of running processes which this unit would otherwise start, in order of preference:
+    /// the process healthcheck target or pid file of a forking service, or processes running the
+    /// ExecStart executable, with processes running from the same path first
+    pub fn existing_processes(&self) -> Vec<u32> {
+        if let (ServiceKind::Forking, Some(Healthcheck::Process(healthcheck))) =
+            (self.service.kind, &self.service.healthcheck)
+        {
+            if let Some(pid_file) = &healthcheck.pid_file {
+                return read_pid_file(pid_file)
+                    .ok()
+                    .filter(|pid| process_exists(*pid))
+                    .into_iter()
+                    .collect();
+            }
+
+            if let Some(target) = &healthcheck.target {
+                let command_line = healthcheck
+                    .command_line
+                    .as_deref()
+                    .and_then(|pattern| Regex::new(pattern).ok());
+
+                return find_processes(target.file_name().unwrap_or_default())
+                    .into_iter()
+                    .filter(|process| process.matches(command_line.as_ref(), None))
+                    .map(|process| process.pid)
+                    .collect();
+            }
+        }
+
+        let Ok(executable) = self.service.exec_start.executable.pathbuf() else {
+            return vec![];
+        };
+
+        let mut processes = find_processes(executable.file_name().unwrap_or_default());
+        processes.sort_by_key(|process| process.executable.as_ref() != Some(&executable));
+        processes.into_iter().map(|process| process.pid).collect()
+    }
+
+    /// PIDs of running processes which were started for this unit, for when they are killed
+    ///
+    /// Unlike [`Definition::existing_processes`], processes are never matched by the file name of
+    /// their executable alone: they have to run from the exact path of the ExecStart executable
+    /// with the same arguments, or from the exact path of the process healthcheck target of a
+    /// forking service, respecting its CommandLine
+    pub fn orphaned_processes(&self) -> Vec<u32> {
+        if let (ServiceKind::Forking, Some(Healthcheck::Process(healthcheck))) =
+            (self.service.kind, &self.service.healthcheck)
+        {
+            if let Some(pid_file) = &healthcheck.pid_file {
+                return read_pid_file(pid_file)
+                    .ok()
+                    .filter(|pid| process_exists(*pid))
+                    .into_iter()
+                    .collect();
+            }
+
+            if let Some(target) = &healthcheck.target {
+                let command_line = healthcheck
+                    .command_line
+                    .as_deref()
+                    .and_then(|pattern| Regex::new(pattern).ok());
+
+                return find_processes(target.file_name().unwrap_or_default())
+                    .into_iter()
+                    .filter(|process| {
+                        process.runs(target) && process.matches(command_line.as_ref(), None)
+                    })
+                    .map(|process| process.pid)
+                    .collect();
+            }
+        }
+
+        let Ok(executable) = self.service.exec_start.executable.pathbuf() else {
+            return vec![];
+        };
+
+        let arguments = self
+            .service
+            .exec_start
+            .arguments
+            .clone()
+            .unwrap_or_default();
+
+        find_processes(executable.file_name().unwrap_or_default())
+            .into_iter()
+            .filter(|process| process.runs(&executable) && process.arguments == arguments)
+            .map(|process| process.pid)
+            .collect()
+    }
+
+    /// Files in the store which belong to the unit: its remote executable, its resources and
+    /// its materialized script
+    pub fn store_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![];
+
+        if let Executable::Remote(_) = &self.service.exec_start.executable {
+            paths.extend(
+                self.service
+                    .exec_start
+                    .executable
+                    .cached_executable_path()
+                    .ok(),
+            );
+        }
+
+        for url in self
+            .resources
+            .iter()
+            .flat_map(|resources| resources.values())
+        {
+            paths.extend(store_ref_for_url(url).ok());
+        }
+
+        if self.service.script.is_some() {
+            let shell = self.service.script_kind.unwrap_or(ShellKind::PowerShell);
+            paths.extend(wpm_store_dir().ok().map(|dir| {
+                dir.join(format!(
+                    "{}.script.{}",
+                    self.unit.name,
+                    shell.script_extension()
+                ))
+            }));
+        }
+
+        paths
+    }
+
+    pub fn log_path(&self) -> std::io::Result<PathBuf> {
+        Ok(wpm_log_dir()?.join(format!("{}.log", self.unit.name)))
+    }
+
+    /// A copy of the definition with everything which may hold a secret replaced, so that it can
+    /// be shared, e.g. in a support bundle, without leaking secrets
+    ///
+    /// This covers the values of environment variables, Shell command lines, inline Scripts, the
+    /// values of arguments following flags which look like they take a secret (e.g. `--token`),
+    /// and the credentials, queries and fragments of URLs
+    pub fn redacted(&self) -> Definition {
+        let mut definition = self.clone();
+        let service = &mut definition.service;
+
+        if service.script.is_some() {
+            service.script = Some(String::from(REDACTED));
+        }
+
+        let commands = service
+            .exec_start_pre
+            .iter_mut()
+            .flatten()
+            .chain(std::iter::once(&mut service.exec_start))
+            .chain(service.exec_start_post.iter_mut().flatten())
+            .chain(service.exec_stop.iter_mut().flatten())
+            .chain(service.exec_stop_post.iter_mut().flatten());
+
+        let mut environments = vec![&mut service.environment];
+        for command in commands {
+            if command.shell.is_some() {
+                command.shell = Some(String::from(REDACTED));
+            }
+
+            if let Some(arguments) = &mut command.arguments {
+                redact_arguments(arguments);
+            }
+
+            redact_executable(&mut command.executable);
+            environments.push(&mut command.environment);
+        }
+
+        if let Some(Healthcheck::Command(healthcheck)) = &mut service.healthcheck {
+            if let Some(arguments) = &mut healthcheck.arguments {
+                redact_arguments(arguments);
+            }
+
+            environments.push(&mut healthcheck.environment);
+        }
+
+        for environment in environments {
+            for (_, value) in environment.iter_mut().flatten() {
+                *value = String::from(REDACTED);
+            }
+        }
+
+        for url in definition.resources.iter_mut().flat_map(|r| r.values_mut()) {
+            redact_url(url);
+        }
+
+        definition
+    }
+}
+
+/// Joins arguments into a command line, quoting them like [`std::process::Command`] does on
+/// Windows so that arguments containing whitespace or quotes are passed on as they are
+pub fn quote_arguments(arguments: &[String]) -> String {
+    arguments
+        .iter()
+        .map(|argument| quote_argument(argument))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn quote_argument(argument: &str) -> String {
+    let quote = argument.is_empty() || argument.contains([' ', '\t']);
+    let mut quoted = String::new();
+    let mut backslashes = 0;
+
+    if quote {
+        quoted.push('"');
+    }
+
+    for c in argument.chars() {
+        if c == '\\' {
+            backslashes += 1;
+        } else {
+            // backslashes before a quote have to be escaped along with the quote
+            if c == '"' {
+                quoted.extend(std::iter::repeat_n('\\', backslashes + 1));
+            }
+
+            backslashes = 0;
+        }
+
+        quoted.push(c);
+    }
+
+    // trailing backslashes would otherwise escape the closing quote
+    if quote {
+        quoted.extend(std::iter::repeat_n('\\', backslashes));
+        quoted.push('"');
+    }
+
+    quoted
+}
+
+/// Replaces the values of arguments which look like secrets, either following a flag like
+/// `--token` or `-Password`, or given as `--api-key=value`
+fn redact_arguments(arguments: &mut [String]) {
+    let mut redact_next = false;
+
+    for argument in arguments {
+        if redact_next && !argument.starts_with('-') {
+            *argument = String::from(REDACTED);
+            redact_next = false;
+            continue;
+        }
+
+        redact_next = false;
+
+        if !argument.starts_with('-') {
+            continue;
+        }
+
+        match argument.split_once('=') {
+            Some((flag, _)) if is_sensitive_flag(flag) => {
+                *argument = format!("{flag}={REDACTED}");
+            }
+            Some(_) => {}
+            None => redact_next = is_sensitive_flag(argument),
+        }
+    }
+}
+
+fn is_sensitive_flag(flag: &str) -> bool {
+    has_sensitive_suffix(
+        &flag
+            .trim_start_matches('-')
+            .replace('-', "_")
+            .to_ascii_uppercase(),
+    )
+}
+
+fn has_sensitive_suffix(upper: &str) -> bool {
+    SENSITIVE_SUFFIXES
+        .iter()
+        .any(|suffix| upper == &suffix[1..] || upper.ends_with(suffix))
+}
+
+fn redact_executable(executable: &mut Executable) {
+    match executable {
+        Executable::Remote(remote) => redact_url(&mut remote.url),
+        Executable::Scoop(ScoopExecutable::Manifest(manifest)) => {
+            redact_url(&mut manifest.manifest)
+        }
+        Executable::Scoop(ScoopExecutable::Package(_))
+        | Executable::Local(_)
+        | Executable::Aumid(_) => {}
+    }
+}
+
+/// Removes the credentials, query and fragment of a URL, which may hold access tokens
+fn redact_url(url: &mut Url) {
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.set_query(None);
+    url.set_fragment(None);
+}
+
+/// A copy of a URL which can be written to logs, without its credentials, query and fragment
+pub(crate) fn redacted_url(url: &Url) -> Url {
+    let mut url = url.clone();
+    redact_url(&mut url);
+    url
+}
+
+/// Shown instead of the values of environment variables which shouldn't be shared
+pub const REDACTED: &str = "<redacted>";
+
+/// Endings of the names of environment variables which are treated as secrets even when they
+/// aren't listed in Sensitive, which also match a name without the leading underscore
+const SENSITIVE_SUFFIXES: [&str; 4] = ["_TOKEN", "_KEY", "_SECRET", "_PASSWORD"];
+
+/// Span that a unit's lifecycle events are emitted in, so that they can be filtered for a single
+/// unit with a directive like `RUST_LOG=info,[unit{name=komorebi}]=debug`
+pub fn unit_span(name: &str) -> tracing::Span {
+    tracing::info_span!("unit", name)
+}
+
+/// Default time in seconds that hook commands may run for before they are killed
+pub const DEFAULT_HOOK_TIMEOUT_SEC: u64 = 30;
+
+fn drain_pipe(
+    mut pipe: impl Read + Send + 'static,
+    buffer: Arc<Mutex<Vec<u8>>>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut chunk = [0; 4096];
+        while let Ok(read) = pipe.read(&mut chunk) {
+            if read == 0 {
+                break;
+            }
+
+            buffer.lock().extend_from_slice(&chunk[..read]);
+        }
+    })
+}
+
+/// Appends the captured output of a hook or healthcheck command to a unit log file,
+/// with every line prefixed by the lifecycle phase that executed the command
+pub fn log_command_output(log_path: &Path, phase: &str, output: &Output) {
+    let mut lines = vec![];
+
+    for stream in [&output.stdout, &output.stderr] {
+        for line in String::from_utf8_lossy(stream).lines() {
+            if !line.is_empty() {
+                lines.push(format!("[{phase}] {line}\n"));
+            }
+        }
+    }
+
+    if !output.status.success() {
+        lines.push(format!("[{phase}] command exited with {}\n", output.status));
+    }
+
+    if lines.is_empty() {
+        return;
+    }
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .and_then(|mut file| file.write_all(lines.concat().as_bytes()));
+
+    if let Err(error) = result {
+        tracing::warn!(
+            "could not write {phase} output to {}: {error}",
+            log_path.display()
+        );
     }
 }
 
@@ -909,7 +2364,7 @@ pub struct CommandHealthcheck {
     pub environment: Option<Vec<(String, String)>>,
     /// The number of seconds to delay before checking for liveness
     pub delay_sec: u64,
-    /// The maximum number of retries (default: 5)
+    /// The maximum number of times the command is run again after it fails, within a single start attempt (default: 5)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retry_limit: Option<u8>,
 }
@@ -936,28 +2391,16 @@ impl CommandHealthcheck {
         }
     }
 
-    pub fn to_silent_command(&self, global_environment: Option<Vec<(String, String)>>) -> Command {
+    pub fn to_silent_command(&self, environment: &ServiceEnvironment) -> Command {
         let mut command = Command::new(&self.executable);
         if let Some(arguments) = &self.arguments {
             command.args(arguments);
         }
 
-        let mut environment_variables = vec![];
+        environment.apply(&mut command, self.environment.as_deref());
 
-        if let Some(environment) = global_environment {
-            environment_variables.extend(environment.clone());
-        }
-
-        if let Some(environment) = &self.environment {
-            environment_variables.extend(environment.clone());
-        }
-
-        if !environment_variables.is_empty() {
-            command.envs(environment_variables);
-        }
-
-        command.stdout(std::process::Stdio::null());
-        command.stderr(std::process::Stdio::null());
+        command.stdout(std::process::Stdio::piped());
+        command.stderr(std::process::Stdio::piped());
 
         command
     }
@@ -970,6 +2413,16 @@ pub struct ProcessHealthcheck {
     /// An optional binary with which to check process liveness
     #[serde(skip_serializing_if = "Option::is_none")]
     pub target: Option<PathBuf>,
+    /// An optional file from which to read the PID of a forked process, used instead of Target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid_file: Option<PathBuf>,
+    /// An optional regular expression which the command line of a Target process must match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_line: Option<String>,
+    /// Only match Target processes whose parent is the process started by ExecStart
+    #[serde(default)]
+    #[serde(skip_serializing_if = "<&bool>::not")]
+    pub match_parent: bool,
     /// The number of seconds to delay before checking for liveness
     pub delay_sec: u64,
 }
@@ -978,70 +2431,61 @@ impl Default for Healthcheck {
     fn default() -> Self {
         Self::Process(ProcessHealthcheck {
             target: None,
+            pid_file: None,
+            command_line: None,
+            match_parent: false,
             delay_sec: 1,
         })
     }
 }
 
-#[derive(Default, Serialize, Deserialize, Copy, Clone, JsonSchema)]
-pub enum ServiceKind {
-    #[default]
-    Simple,
-    Oneshot,
-    Forking,
-}
-
-impl Display for ServiceKind {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ServiceKind::Simple => write!(f, "Simple"),
-            ServiceKind::Oneshot => write!(f, "Oneshot"),
-            ServiceKind::Forking => write!(f, "Forking"),
-        }
-    }
-}
-
-const CREATE_NO_WINDOW: u32 = 0x08000000;
+impl TryFrom<&Definition> for Command {
+    type Error = ProcessManagerError;
 
-impl From<&Definition> for Command {
-    fn from(value: &Definition) -> Self {
-        let file = File::create(value.log_path()).unwrap();
+    fn try_from(value: &Definition) -> Result<Self, Self::Error> {
+        let log_path = value.log_path()?;
+        let log_file_error = |error| ProcessManagerError::LogFile {
+            path: log_path.clone(),
+            error,
+        };
 
-        let stdout = file.try_clone().unwrap();
-        let stderr = stdout.try_clone().unwrap();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .map_err(log_file_error)?;
 
-        let mut command = Command::new(value.service.exec_start.executable.pathbuf().unwrap());
+        let stdout = file.try_clone().map_err(log_file_error)?;
+        let stderr = stdout.try_clone().map_err(log_file_error)?;
 
-        let mut environment_variables = vec![];
+        let mut command = Command::new(value.service.exec_start.executable.pathbuf()?);
 
-        if let Some(environment) = &value.service.environment {
-            environment_variables.extend(environment.clone());
-        }
+        value.service.command_environment().apply(
+            &mut command,
+            value.service.exec_start.environment.as_deref(),
+        );
 
-        if let Some(environment) = &value.service.exec_start.environment {
-            environment_variables.extend(environment.clone());
-        }
+        value.service.exec_start.apply_arguments(&mut command);
 
-        if !environment_variables.is_empty() {
-            command.envs(environment_variables);
+        if let Some(working_directory) = &value.service.working_directory {
+            command.current_dir(working_directory);
         }
 
-        if let Some(arguments) = &value.service.exec_start.arguments {
-            command.args(arguments);
-        }
+        CurrentPlatform::configure_command(&mut command);
 
-        if let Some(working_directory) = &value.service.working_directory {
-            command.current_dir(working_directory);
+        if value.service.relays_output() {
+            command.stdout(std::process::Stdio::piped());
+            command.stderr(std::process::Stdio::piped());
+        } else {
+            command.stdout(stdout);
+            command.stderr(stderr);
         }
 
-        command.creation_flags(CREATE_NO_WINDOW);
-        command.stdout(stdout);
-        command.stderr(stderr);
-        command
+        Ok(command)
     }
 }
 
-fn store_ref_for_url(url: &Url) -> Result<PathBuf, ProcessManagerError> {
+pub(crate) fn store_ref_for_url(url: &Url) -> Result<PathBuf, ProcessManagerError> {
     let stringified = url.to_string();
     let filename = stringified
         .split('/')
@@ -1057,7 +2501,7 @@ fn store_ref_for_url(url: &Url) -> Result<PathBuf, ProcessManagerError> {
     stringified_parent = stringified_parent.replace("/", "_").to_string();
     stringified_parent = stringified_parent.trim_end_matches("_").to_string();
 
-    let cache_parent_dir = wpm_store_dir().join(&stringified_parent);
+    let cache_parent_dir = wpm_store_dir()?.join(&stringified_parent);
     std::fs::create_dir_all(&cache_parent_dir)?;
 
     Ok(cache_parent_dir.join(filename))
@@ -1,34 +1,100 @@
+use crate::clock;
+use crate::time_format::Timestamp;
 use crate::unit::ServiceKind;
+use chrono::DateTime;
+use chrono::Utc;
+use std::cmp::Ordering;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::process::ExitStatus;
+use std::process::Output;
+use std::time::Duration;
+use tabled::builder::Builder;
 use tabled::Tabled;
 
+pub use wpm_protocol::RestartBranch;
+pub use wpm_protocol::StateColumn;
+pub use wpm_protocol::StateOptions;
+pub use wpm_protocol::UnitResult;
+pub use wpm_protocol::UnitState;
+pub use wpm_protocol::UnitStateEntry;
+pub use wpm_protocol::UnitStateReport;
+pub use wpm_protocol::MAX_STATE_LOG_LINES;
+
 #[derive(Tabled)]
 pub struct UnitStatus {
     pub name: String,
-    pub kind: ServiceKind,
+    pub kind: DisplayedOption<ServiceKind>,
     pub state: UnitState,
     pub pid: DisplayedOption<u32>,
-    pub timestamp: DisplayedOption<String>,
+    pub timestamp: DisplayedOption<Timestamp>,
+    pub reason: DisplayedOption<ExitReason>,
+    pub uptime: DisplayedOption<Uptime>,
 }
 
-#[derive(Tabled)]
-pub enum UnitState {
-    Running,
-    Stopped,
-    Completed,
-    Failed,
-    Terminated,
+impl UnitStatus {
+    /// The value shown for this unit in the given column of the state table
+    pub fn cell(&self, column: StateColumn) -> String {
+        match column {
+            StateColumn::Name => self.name.clone(),
+            StateColumn::Kind => self.kind.to_string(),
+            StateColumn::State => self.state.to_string(),
+            StateColumn::Pid => self.pid.to_string(),
+            StateColumn::Timestamp => self.timestamp.to_string(),
+            StateColumn::Reason => self.reason.to_string(),
+            StateColumn::Uptime => self.uptime.to_string(),
+        }
+    }
+
+    /// Orders units by the given column, comparing pids, timestamps and uptimes by value and placing
+    /// units without a value in the column last
+    pub fn compare(&self, other: &Self, column: StateColumn) -> Ordering {
+        fn empty_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+
+        match column {
+            StateColumn::Pid => empty_last(self.pid.0, other.pid.0),
+            StateColumn::Timestamp => empty_last(self.timestamp.0, other.timestamp.0),
+            StateColumn::Uptime => empty_last(
+                self.uptime.0.as_ref().map(|uptime| uptime.0),
+                other.uptime.0.as_ref().map(|uptime| uptime.0),
+            ),
+            column => {
+                let (a, b) = (self.cell(column), other.cell(column));
+                empty_last((!a.is_empty()).then_some(a), (!b.is_empty()).then_some(b))
+            }
+        }
+    }
 }
 
-impl Display for UnitState {
+/// How long a unit has been running for, displayed in its two most significant units
+#[derive(Debug, Clone, Copy)]
+pub struct Uptime(pub Duration);
+
+impl Display for Uptime {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            UnitState::Running => write!(f, "Running"),
-            UnitState::Stopped => write!(f, "Stopped"),
-            UnitState::Completed => write!(f, "Completed"),
-            UnitState::Failed => write!(f, "Failed"),
-            UnitState::Terminated => write!(f, "Terminated"),
+        let seconds = self.0.as_secs();
+        let (days, hours, minutes, seconds) = (
+            seconds / 86400,
+            seconds % 86400 / 3600,
+            seconds % 3600 / 60,
+            seconds % 60,
+        );
+
+        if days > 0 {
+            write!(f, "{days}d {hours}h")
+        } else if hours > 0 {
+            write!(f, "{hours}h {minutes}m")
+        } else if minutes > 0 {
+            write!(f, "{minutes}m {seconds}s")
+        } else {
+            write!(f, "{seconds}s")
         }
     }
 }
@@ -43,3 +109,150 @@ impl<T: Display> Display for DisplayedOption<T> {
         }
     }
 }
+
+/// Number of lines of each output stream kept from a failed healthcheck command
+const HEALTHCHECK_OUTPUT_LINES: usize = 20;
+
+/// The result of the final attempt of a command healthcheck which exhausted its retries
+#[derive(Debug, Clone)]
+pub struct HealthcheckFailure {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+fn last_lines(output: &[u8]) -> String {
+    let output = String::from_utf8_lossy(output);
+    let lines = output
+        .lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+
+    lines[lines.len().saturating_sub(HEALTHCHECK_OUTPUT_LINES)..].join("\n")
+}
+
+impl From<&Output> for HealthcheckFailure {
+    fn from(output: &Output) -> Self {
+        Self {
+            exit_code: output.status.code(),
+            stdout: last_lines(&output.stdout),
+            stderr: last_lines(&output.stderr),
+        }
+    }
+}
+
+impl HealthcheckFailure {
+    /// The exit code and the last line written by the command, preferring stderr
+    pub fn summary(&self) -> String {
+        let last_line = [&self.stderr, &self.stdout]
+            .into_iter()
+            .find_map(|output| output.lines().next_back());
+
+        match last_line {
+            None => self.to_string(),
+            Some(line) => format!("{self}: {line}"),
+        }
+    }
+}
+
+impl Display for HealthcheckFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.exit_code {
+            Some(code) => write!(f, "exit code {code}"),
+            None => write!(f, "killed"),
+        }
+    }
+}
+
+/// Why a unit stopped running
+#[derive(Debug, Clone)]
+pub enum ExitReason {
+    /// The unit did not pass its healthcheck, with the result of the final command healthcheck
+    FailedHealthcheck(Option<HealthcheckFailure>),
+    /// The unit's process exited with an exit code
+    ExitCode(i32),
+    /// The unit's process was killed by wpm, or exited without an exit code
+    Killed,
+    /// The unit's process was ended by an NTSTATUS error such as an access violation or
+    /// Ctrl+C, which Windows reports as the exit code of the process
+    Aborted(u32),
+    /// The unit's process could not be spawned
+    SpawnError(String),
+    /// The unit's process could not be waited on
+    WaitError(String),
+}
+
+impl ExitReason {
+    /// The reason for an exit code, or for a process which exited without one
+    pub fn from_code(code: Option<i32>) -> Self {
+        match code {
+            None => ExitReason::Killed,
+            // NTSTATUS values with the error severity have both of their highest bits set
+            Some(code) if code as u32 & NTSTATUS_ERROR == NTSTATUS_ERROR => {
+                ExitReason::Aborted(code as u32)
+            }
+            Some(code) => ExitReason::ExitCode(code),
+        }
+    }
+}
+
+/// Severity bits of NTSTATUS error values, like STATUS_CONTROL_C_EXIT (0xC000013A)
+const NTSTATUS_ERROR: u32 = 0xC000_0000;
+
+impl From<ExitStatus> for ExitReason {
+    fn from(status: ExitStatus) -> Self {
+        ExitReason::from_code(status.code())
+    }
+}
+
+impl Display for ExitReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitReason::FailedHealthcheck(None) => write!(f, "failed healthcheck"),
+            ExitReason::FailedHealthcheck(Some(failure)) => {
+                write!(f, "failed healthcheck ({failure})")
+            }
+            ExitReason::ExitCode(code) => write!(f, "exited with code {code}"),
+            ExitReason::Killed => write!(f, "killed"),
+            ExitReason::Aborted(status) => write!(f, "aborted with status {status:#010X}"),
+            ExitReason::SpawnError(error) => write!(f, "failed to spawn: {error}"),
+            ExitReason::WaitError(error) => write!(f, "failed to wait: {error}"),
+        }
+    }
+}
+
+/// When and why a unit entered the Failed or Terminated state
+#[derive(Debug, Clone)]
+pub struct UnitExit {
+    pub timestamp: DateTime<Utc>,
+    pub reason: ExitReason,
+}
+
+impl UnitExit {
+    pub fn now(reason: ExitReason) -> Self {
+        Self {
+            timestamp: clock::now(),
+            reason,
+        }
+    }
+}
+
+/// A table of the result of an action for each unit it was applied to
+pub fn results_table(results: &[UnitResult]) -> String {
+    let mut builder = Builder::default();
+    builder.push_record(["unit", "result", "reason"]);
+    for result in results {
+        builder.push_record([
+            result.name.clone(),
+            match (&result.error, result.restart) {
+                (Some(_), _) => "error".to_string(),
+                (None, Some(branch)) => branch.to_string(),
+                (None, None) => "ok".to_string(),
+            },
+            result.error.clone().unwrap_or_default(),
+        ]);
+    }
+
+    builder.build().to_string()
+}
@@ -0,0 +1,32 @@
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+
+pub use wpm_protocol::UsageSample;
+
+/// Number of samples kept for each unit, which is ten minutes of samples taken every 10 seconds
+pub const MAX_SAMPLES_PER_UNIT: usize = 60;
+
+static USAGE_HISTORY: Mutex<BTreeMap<String, VecDeque<UsageSample>>> = Mutex::new(BTreeMap::new());
+
+/// Records a usage sample for a unit, dropping its oldest sample once the history is full
+pub fn record(name: &str, sample: UsageSample) {
+    let mut history = USAGE_HISTORY.lock();
+    let samples = history.entry(name.to_string()).or_default();
+
+    if samples.len() == MAX_SAMPLES_PER_UNIT {
+        samples.pop_front();
+    }
+
+    samples.push_back(sample);
+}
+
+/// The recorded samples of the given units, or of all units if none are given, oldest first
+pub fn samples(names: &[String]) -> BTreeMap<String, Vec<UsageSample>> {
+    USAGE_HISTORY
+        .lock()
+        .iter()
+        .filter(|(name, _)| names.is_empty() || names.contains(name))
+        .map(|(name, samples)| (name.clone(), samples.iter().cloned().collect()))
+        .collect()
+}
@@ -0,0 +1,194 @@
+use crate::unit::Definition;
+use crate::unit::Healthcheck;
+use crate::unit::ServiceCommand;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// Maximum length of a unit name, which is also used as the name of the unit's log file
+pub const MAX_UNIT_NAME_LENGTH: usize = 64;
+
+/// A problem with a unit definition which doesn't prevent it from being loaded
+#[derive(Debug, Clone)]
+pub struct UnitWarning {
+    pub unit: String,
+    pub field: String,
+    pub message: String,
+}
+
+impl Display for UnitWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.unit, self.field, self.message)
+    }
+}
+
+/// Checks that a unit name can be used as a log file name and passed to wpmctl without quoting
+pub fn validate_unit_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("unit names cannot be empty".to_string());
+    }
+
+    if name.len() > MAX_UNIT_NAME_LENGTH {
+        return Err(format!(
+            "unit names cannot be longer than {MAX_UNIT_NAME_LENGTH} characters"
+        ));
+    }
+
+    if name.contains(['/', '\\']) {
+        return Err("unit names cannot contain path separators".to_string());
+    }
+
+    if name.starts_with('.') {
+        return Err("unit names cannot start with '.'".to_string());
+    }
+
+    if let Some(invalid) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+    {
+        return Err(format!(
+            "unit names can only contain ASCII letters, digits, '-', '_' and '.' (found '{invalid}')"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks a set of unit definitions for references to unknown units and empty arguments
+pub fn validate_definitions(definitions: &[Definition]) -> Vec<UnitWarning> {
+    let names = definitions
+        .iter()
+        .map(|definition| definition.unit.name.as_str())
+        .collect::<HashSet<_>>();
+
+    let mut warnings = vec![];
+
+    for definition in definitions {
+        let unit = &definition.unit.name;
+
+        for deprecation in &definition.deprecations {
+            warnings.push(UnitWarning {
+                unit: unit.clone(),
+                field: deprecation.field.clone(),
+                message: deprecation.message.clone(),
+            });
+        }
+
+        for dependency in definition.unit.requires.iter().flatten() {
+            if dependency == unit {
+                warnings.push(UnitWarning {
+                    unit: unit.clone(),
+                    field: "Unit.Requires".to_string(),
+                    message: "unit requires itself".to_string(),
+                });
+            } else if !names.contains(dependency.as_str()) {
+                warnings.push(UnitWarning {
+                    unit: unit.clone(),
+                    field: "Unit.Requires".to_string(),
+                    message: format!("{dependency} is not a registered unit"),
+                });
+            }
+        }
+
+        if let Some(target) = definition
+            .path
+            .as_ref()
+            .and_then(|trigger| trigger.unit.as_ref())
+        {
+            if !names.contains(target.as_str()) {
+                warnings.push(UnitWarning {
+                    unit: unit.clone(),
+                    field: "Path.Unit".to_string(),
+                    message: format!("{target} is not a registered unit"),
+                });
+            }
+        }
+
+        if let Some(socket) = &definition.socket {
+            if socket.listen == socket.target {
+                warnings.push(UnitWarning {
+                    unit: unit.clone(),
+                    field: "Socket.Target".to_string(),
+                    message: "is the same address as Socket.Listen".to_string(),
+                });
+            }
+        }
+
+        let service = &definition.service;
+
+        if service.autostart_on.is_some() && !service.autostart {
+            warnings.push(UnitWarning {
+                unit: unit.clone(),
+                field: "Service.AutostartOn".to_string(),
+                message: "has no effect without Service.Autostart".to_string(),
+            });
+        }
+
+        let commands = [
+            ("Service.ExecStartPre", service.exec_start_pre.as_deref()),
+            (
+                "Service.ExecStart",
+                Some(std::slice::from_ref(&service.exec_start)),
+            ),
+            ("Service.ExecStartPost", service.exec_start_post.as_deref()),
+            ("Service.ExecStop", service.exec_stop.as_deref()),
+            ("Service.ExecStopPost", service.exec_stop_post.as_deref()),
+        ];
+
+        for (field, commands) in commands {
+            for command in commands.into_iter().flatten() {
+                if has_empty_argument(command) {
+                    warnings.push(UnitWarning {
+                        unit: unit.clone(),
+                        field: format!("{field}.Arguments"),
+                        message: "contains an empty argument".to_string(),
+                    });
+                }
+
+                if command.retry_limit.is_some() && field != "Service.ExecStart" {
+                    warnings.push(UnitWarning {
+                        unit: unit.clone(),
+                        field: format!("{field}.RetryLimit"),
+                        message: "is only used for Service.ExecStart".to_string(),
+                    });
+                }
+            }
+        }
+
+        if let Some(Healthcheck::Process(process)) = &service.healthcheck {
+            if process.target.is_none() && (process.command_line.is_some() || process.match_parent)
+            {
+                warnings.push(UnitWarning {
+                    unit: unit.clone(),
+                    field: "Service.Healthcheck.Process".to_string(),
+                    message: "CommandLine and MatchParent are only used with Target".to_string(),
+                });
+            }
+        }
+
+        if let Some(Healthcheck::Command(command)) = &service.healthcheck {
+            if command
+                .arguments
+                .iter()
+                .flatten()
+                .any(|argument| argument.trim().is_empty())
+            {
+                warnings.push(UnitWarning {
+                    unit: unit.clone(),
+                    field: "Service.Healthcheck.Command.Arguments".to_string(),
+                    message: "contains an empty argument".to_string(),
+                });
+            }
+        }
+    }
+
+    warnings
+}
+
+fn has_empty_argument(command: &ServiceCommand) -> bool {
+    command
+        .arguments
+        .iter()
+        .flatten()
+        .any(|argument| argument.trim().is_empty())
+}
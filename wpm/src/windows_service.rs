@@ -0,0 +1,116 @@
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::time::Duration;
+use std::time::Instant;
+use windows_sys::Win32::Foundation::ERROR_SERVICE_ALREADY_RUNNING;
+use windows_sys::Win32::System::Services::CloseServiceHandle;
+use windows_sys::Win32::System::Services::OpenSCManagerW;
+use windows_sys::Win32::System::Services::OpenServiceW;
+use windows_sys::Win32::System::Services::QueryServiceStatus;
+use windows_sys::Win32::System::Services::StartServiceW;
+use windows_sys::Win32::System::Services::SC_HANDLE;
+use windows_sys::Win32::System::Services::SC_MANAGER_CONNECT;
+use windows_sys::Win32::System::Services::SERVICE_QUERY_STATUS;
+use windows_sys::Win32::System::Services::SERVICE_RUNNING;
+use windows_sys::Win32::System::Services::SERVICE_START;
+use windows_sys::Win32::System::Services::SERVICE_STATUS;
+use windows_sys::Win32::System::Services::SERVICE_STOPPED;
+
+/// Time to wait for a Windows service to report that it is running after starting it
+pub const WINDOWS_SERVICE_START_TIMEOUT: Duration = Duration::from_secs(30);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Closes a service control manager handle when dropped
+struct ServiceHandle(SC_HANDLE);
+
+impl Drop for ServiceHandle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseServiceHandle(self.0);
+        }
+    }
+}
+
+fn query_state(service: &ServiceHandle) -> std::io::Result<u32> {
+    unsafe {
+        let mut status = std::mem::zeroed::<SERVICE_STATUS>();
+        if QueryServiceStatus(service.0, &mut status) == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(status.dwCurrentState)
+    }
+}
+
+/// Starts a Windows service through the service control manager if it isn't already running,
+/// and waits until it reports that it is running
+pub fn ensure_running(name: &str) -> std::io::Result<()> {
+    let wide_name = OsStr::new(name)
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<_>>();
+
+    let service = unsafe {
+        let manager = OpenSCManagerW(std::ptr::null(), std::ptr::null(), SC_MANAGER_CONNECT);
+        if manager.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let manager = ServiceHandle(manager);
+        let service = OpenServiceW(
+            manager.0,
+            wide_name.as_ptr(),
+            SERVICE_QUERY_STATUS | SERVICE_START,
+        );
+
+        if service.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        ServiceHandle(service)
+    };
+
+    let deadline = Instant::now() + WINDOWS_SERVICE_START_TIMEOUT;
+    let mut started = false;
+
+    loop {
+        match query_state(&service)? {
+            SERVICE_RUNNING => {
+                if !started {
+                    tracing::debug!("windows service {name} is already running");
+                }
+
+                return Ok(());
+            }
+            SERVICE_STOPPED if started => {
+                return Err(std::io::Error::other("service stopped while starting"));
+            }
+            SERVICE_STOPPED => {
+                tracing::info!("starting windows service {name}");
+                started = true;
+
+                unsafe {
+                    if StartServiceW(service.0, 0, std::ptr::null()) == 0 {
+                        let error = std::io::Error::last_os_error();
+                        if error.raw_os_error() != Some(ERROR_SERVICE_ALREADY_RUNNING as i32) {
+                            return Err(error);
+                        }
+                    }
+                }
+            }
+            // pending states are waited out, including a pending stop, after which the
+            // service is started again
+            _ => {}
+        }
+
+        if Instant::now() >= deadline {
+            return Err(std::io::Error::other(format!(
+                "service did not start within {}s",
+                WINDOWS_SERVICE_START_TIMEOUT.as_secs()
+            )));
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
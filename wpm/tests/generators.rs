@@ -0,0 +1,63 @@
+use wpm::generators::with_schema_pointer;
+use wpm::generators::ExampleFormat;
+use wpm::generators::SCHEMA_URL;
+use wpm::unit::Definition;
+
+#[test]
+fn toml_schema_pointer_keeps_crlf_line_endings() {
+    let contents = "[Unit]\r\nName = \"crlf\"\r\n";
+    let formatted = with_schema_pointer(contents, ExampleFormat::Toml);
+
+    assert_eq!(
+        formatted,
+        format!("#:schema {SCHEMA_URL}\r\n\r\n[Unit]\r\nName = \"crlf\"\r\n")
+    );
+
+    // formatting is idempotent, so fmt doesn't rewrite files which are already formatted
+    assert_eq!(
+        with_schema_pointer(&formatted, ExampleFormat::Toml),
+        formatted
+    );
+}
+
+#[test]
+fn toml_schema_pointer_replaces_an_existing_directive() {
+    let contents = "#:schema ./schema.unit.json\n\n[Unit]\nName = \"existing\"\n";
+
+    assert_eq!(
+        with_schema_pointer(contents, ExampleFormat::Toml),
+        format!("#:schema {SCHEMA_URL}\n\n[Unit]\nName = \"existing\"\n")
+    );
+}
+
+#[test]
+fn json_schema_pointer_is_added_when_missing() {
+    let contents = "{\r\n    \"Unit\": {\r\n        \"Name\": \"json\"\r\n    }\r\n}\r\n";
+
+    assert_eq!(
+        with_schema_pointer(contents, ExampleFormat::Json),
+        format!(
+            "{{\r\n    \"$schema\": \"{SCHEMA_URL}\",\r\n    \"Unit\": {{\r\n        \"Name\": \"json\"\r\n    }}\r\n}}\r\n"
+        )
+    );
+}
+
+#[test]
+fn unknown_example_names_list_the_available_ones() {
+    let directory = std::env::temp_dir().join(format!("wpm-examplegen-{}", std::process::id()));
+    let error = Definition::examplegen(Some(directory.clone()), &[String::from("missing")])
+        .unwrap_err()
+        .to_string();
+
+    let available = Definition::examples()
+        .into_iter()
+        .map(|example| example.unit.name)
+        .collect::<Vec<_>>();
+
+    assert!(
+        error.starts_with("missing is not an example unit"),
+        "{error}"
+    );
+    assert!(available.iter().all(|name| error.contains(name.as_str())));
+    assert!(!directory.exists());
+}
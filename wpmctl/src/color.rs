@@ -0,0 +1,89 @@
+use std::io::IsTerminal;
+pub use wpm::strip_ansi;
+
+const RESET: &str = "\x1b[0m";
+
+/// Returns whether output should be colored, which is only the case when stdout is a terminal,
+/// --no-color was not passed and NO_COLOR is not set to a non-empty value
+pub fn enabled(no_color: bool) -> bool {
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color && !no_color_env && std::io::stdout().is_terminal()
+}
+
+fn state_color(state: &str) -> Option<&'static str> {
+    match state {
+        "Running" => Some("\x1b[32m"),
+        "Completed" => Some("\x1b[34m"),
+        "Terminated" => Some("\x1b[33m"),
+        "Failed" | "LoadError" => Some("\x1b[31m"),
+        _ => None,
+    }
+}
+
+/// Colors the cells of the state column in a state table
+pub fn state_table(table: &str) -> String {
+    let mut state_column = None;
+    let mut lines = vec![];
+
+    for line in table.lines() {
+        if !line.starts_with('|') {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let cells = line.split('|').collect::<Vec<_>>();
+
+        let Some(column) = state_column else {
+            state_column = cells.iter().position(|cell| cell.trim() == "state");
+            lines.push(line.to_string());
+            continue;
+        };
+
+        let colored = cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| match state_color(cell.trim()) {
+                Some(color) if index == column => {
+                    let state = cell.trim();
+                    cell.replacen(state, &format!("{color}{state}{RESET}"), 1)
+                }
+                _ => cell.to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        lines.push(colored.join("|"));
+    }
+
+    let mut output = lines.join("\n");
+    if table.ends_with('\n') {
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Colors the state line and heading of a unit status
+pub fn unit_status(status: &str) -> String {
+    let state = status
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("State: "))
+        .and_then(|state| state.split_whitespace().next());
+
+    let Some(color) = state.and_then(state_color) else {
+        return status.to_string();
+    };
+
+    status
+        .lines()
+        .map(|line| {
+            if let Some(heading) = line.strip_prefix('●') {
+                format!("{color}●{RESET}{heading}")
+            } else if let Some(state) = line.strip_prefix("  State: ") {
+                format!("  State: {color}{state}{RESET}")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
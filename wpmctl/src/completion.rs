@@ -0,0 +1,77 @@
+use crate::request_within;
+use clap_complete::CompletionCandidate;
+use std::time::Duration;
+use wpm::communication::set_socket_name;
+use wpm::process_manager::ProcessManager;
+use wpm::unit_status::StateOptions;
+use wpm::unit_status::UnitState;
+use wpm::unit_status::UnitStateEntry;
+use wpm::SocketMessage;
+
+/// Completions shouldn't keep the shell waiting on an unresponsive daemon for long
+const COMPLETION_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub fn all_units() -> Vec<CompletionCandidate> {
+    candidates(&[])
+}
+
+pub fn running_units() -> Vec<CompletionCandidate> {
+    candidates(&[UnitState::Running])
+}
+
+pub fn inactive_units() -> Vec<CompletionCandidate> {
+    candidates(&[
+        UnitState::Stopped,
+        UnitState::Completed,
+        UnitState::Failed,
+        UnitState::Terminated,
+    ])
+}
+
+pub fn resettable_units() -> Vec<CompletionCandidate> {
+    candidates(&[
+        UnitState::Completed,
+        UnitState::Failed,
+        UnitState::Terminated,
+    ])
+}
+
+/// Names of the units in any of the given states (or every unit if empty), as reported by wpmd,
+/// or as found in the unit directory when wpmd isn't running
+fn candidates(states: &[UnitState]) -> Vec<CompletionCandidate> {
+    // the --socket-name argument isn't available to completers, but its environment variable is
+    if let Ok(name) = std::env::var("WPM_SOCKET_NAME") {
+        set_socket_name(&name);
+    }
+
+    let names = match daemon_units(states) {
+        Some(units) => units.into_iter().map(|unit| unit.name).collect(),
+        // every unit is stopped when wpmd isn't running
+        None if states.is_empty() || states.contains(&UnitState::Stopped) => registered_units(),
+        None => vec![],
+    };
+
+    names.into_iter().map(CompletionCandidate::new).collect()
+}
+
+fn daemon_units(states: &[UnitState]) -> Option<Vec<UnitStateEntry>> {
+    let options = StateOptions {
+        states: states.to_vec(),
+        json: true,
+        ..Default::default()
+    };
+
+    let response = request_within(SocketMessage::State(options), COMPLETION_TIMEOUT)?;
+    serde_json::from_str(&response).ok()
+}
+
+fn registered_units() -> Vec<String> {
+    ProcessManager::parse_units(None, false)
+        .map(|(units, _)| {
+            units
+                .into_iter()
+                .map(|(_, definition)| definition.unit.name)
+                .collect()
+        })
+        .unwrap_or_default()
+}
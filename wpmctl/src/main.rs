@@ -1,34 +1,104 @@
 #![warn(clippy::all)]
 
-use chrono::Utc;
 use clap::CommandFactory;
 use clap::Parser;
+use clap_complete::ArgValueCandidates;
+use clap_complete::CompleteEnv;
 use fs_tail::TailedFile;
 use interprocess::local_socket::traits::Listener;
 use interprocess::local_socket::GenericNamespaced;
+use interprocess::local_socket::Listener as LocalSocketListener;
+use interprocess::local_socket::ListenerNonblockingMode;
 use interprocess::local_socket::ListenerOptions;
+use interprocess::local_socket::Stream as LocalSocketStream;
 use interprocess::local_socket::ToNsName;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::fs::File;
+use std::fs::OpenOptions;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::io::IsTerminal;
 use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::os::windows::process::CommandExt;
+use std::path::Path;
 use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use wpm::communication::daemon_socket;
+use wpm::communication::reply_socket;
 use wpm::communication::send_message;
+use wpm::communication::set_socket_name;
+use wpm::communication::DEFAULT_SOCKET_NAME;
+use wpm::generators::with_schema_pointer;
+use wpm::generators::ExampleFormat;
+use wpm::log_files::current_daemon_log;
+use wpm::log_files::log_usage;
+use wpm::log_files::rotate;
+use wpm::log_files::usage_table;
+use wpm::log_files::DAEMON_LOG;
+use wpm::log_files::DAEMON_PLAINTEXT_LOG;
 use wpm::process_manager::ProcessManager;
+use wpm::process_manager::ProcessManagerError;
+use wpm::set_directories;
+use wpm::support_bundle::SupportBundle;
+use wpm::sync;
+use wpm::sync::SyncConfig;
 use wpm::unit::Definition;
 use wpm::unit::Executable;
 use wpm::unit::ScoopExecutable;
-use wpm::wpm_data_dir;
+use wpm::unit::ServiceKind;
+use wpm::unit_status::results_table;
+use wpm::unit_status::RestartBranch;
+use wpm::unit_status::StateColumn;
+use wpm::unit_status::StateOptions;
+use wpm::unit_status::UnitResult;
+use wpm::unit_status::UnitState;
+use wpm::unit_status::UnitStateReport;
+use wpm::usage_history::UsageSample;
+use wpm::validation::validate_definitions;
+use wpm::wpm_log_dir;
 use wpm::wpm_units_dir;
+use wpm::RemoveOptions;
 use wpm::SocketMessage;
+use wpm::UnitAction;
+use wpm::PROTOCOL_VERSION;
+
+mod color;
+mod completion;
 
 shadow_rs::shadow!(build);
 
+const DETACHED_PROCESS: u32 = 0x00000008;
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
+const LAUNCH_DAEMON_TIMEOUT: Duration = Duration::from_secs(30);
+const PROTOCOL_VERSION_TIMEOUT: Duration = Duration::from_secs(1);
+const FOLLOW_LOG_INTERVAL: Duration = Duration::from_millis(100);
+const WAIT_INTERVAL: Duration = Duration::from_millis(250);
+const BUNDLE_STATE_TIMEOUT: Duration = Duration::from_secs(5);
+const DIRECTORIES_TIMEOUT: Duration = Duration::from_secs(1);
+
 #[derive(Parser)]
 #[clap(author, about, version = build::CLAP_LONG_VERSION)]
 struct Opts {
     #[clap(subcommand)]
     subcmd: SubCommand,
+    /// Launch wpmd if it is not already running
+    #[clap(long, global = true, env = "WPMCTL_LAUNCH_DAEMON", action)]
+    launch_daemon: bool,
+    /// Name of the socket of the wpmd instance to talk to
+    #[clap(long, global = true, env = "WPM_SOCKET_NAME", default_value = DEFAULT_SOCKET_NAME)]
+    socket_name: String,
+    /// Disable colored output (also disabled by setting NO_COLOR, or when not writing to a terminal)
+    #[clap(long, global = true, action)]
+    no_color: bool,
 }
 
 macro_rules! gen_unit_subcommands {
@@ -38,6 +108,7 @@ macro_rules! gen_unit_subcommands {
             #[derive(clap::Parser)]
             pub struct $name {
                 /// Target units
+                #[clap(add = ArgValueCandidates::new(completion::all_units))]
                 units: Vec<String>,
             }
         )+
@@ -45,36 +116,283 @@ macro_rules! gen_unit_subcommands {
 }
 
 gen_unit_subcommands! {
-    Start,
-    Stop,
-    Reset,
+    Isolate,
+    Adopt,
+}
+
+#[derive(clap::Parser)]
+pub struct Start {
+    /// Target units
+    #[clap(add = ArgValueCandidates::new(completion::inactive_units))]
+    units: Vec<String>,
+    /// Wait until the units and their dependencies have passed their healthchecks (or completed,
+    /// for oneshots), exiting with a non-zero code if any of them fail
+    #[clap(long, action)]
+    wait: bool,
+    /// Number of seconds to wait for with --wait before giving up
+    #[clap(long, default_value_t = 60, requires = "wait")]
+    timeout: u64,
+    /// Don't start the units required by the target units, for example when a dependency is
+    /// already running in a debugger
+    #[clap(long, action)]
+    no_deps: bool,
+    /// Print the result for each unit as JSON
+    #[clap(long, action)]
+    json: bool,
+}
+
+#[derive(clap::Parser)]
+pub struct Stop {
+    /// Target units
+    #[clap(add = ArgValueCandidates::new(completion::running_units))]
+    units: Vec<String>,
+    /// Wait until the units' processes have exited and their ExecStopPost commands have run
+    #[clap(long, action)]
+    wait: bool,
+    /// Number of seconds to wait for with --wait before giving up
+    #[clap(long, default_value_t = 60, requires = "wait")]
+    timeout: u64,
+    /// Skip ExecStop commands and kill the units' processes immediately
+    #[clap(long, action)]
+    force: bool,
+    /// Print the result for each unit as JSON
+    #[clap(long, action)]
+    json: bool,
+}
+
+#[derive(clap::Parser)]
+pub struct Reset {
+    /// Target units
+    #[clap(add = ArgValueCandidates::new(completion::resettable_units))]
+    units: Vec<String>,
+    /// Resume automatic restarts after they were paused for exceeding the restart budget
+    #[clap(long, action)]
+    restart_budget: bool,
+    /// Also kill running processes of the units which wpm doesn't track, for example after wpmd
+    /// crashed
+    #[clap(long, action)]
+    kill_orphans: bool,
 }
 
 #[derive(clap::Parser)]
 pub struct Restart {
     /// Target units
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
     units: Vec<String>,
     #[clap(long, short = 'd', action)]
     /// Restart dependents of target units
     with_dependents: bool,
+    /// Print the result for each unit as JSON
+    #[clap(long, action)]
+    json: bool,
 }
 
 #[derive(Parser)]
 struct Status {
     /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
     unit: String,
+    /// Number of recent log lines to show
+    #[clap(long, short = 'n', default_value_t = 10)]
+    lines: usize,
 }
 
 #[derive(Parser)]
 struct Log {
     /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
     unit: Option<String>,
 }
 
+#[derive(Parser)]
+struct History {
+    /// Target units (default: all units)
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
+    units: Vec<String>,
+    /// Print the samples as JSON
+    #[clap(long, action)]
+    json: bool,
+}
+
+#[derive(Parser)]
+struct Why {
+    /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
+    unit: String,
+}
+
+#[derive(Parser)]
+struct Show {
+    /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
+    unit: String,
+}
+
+#[derive(Parser)]
+struct Docs {
+    /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
+    unit: String,
+}
+
+#[derive(Parser)]
+struct Logpath {
+    /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
+    unit: String,
+}
+
+#[derive(Parser)]
+struct Storepath {
+    /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
+    unit: String,
+}
+
+#[derive(Parser)]
+struct Run {
+    /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::inactive_units))]
+    unit: String,
+}
+
 #[derive(Parser)]
 struct Examplegen {
     /// Target path
     path: Option<PathBuf>,
+    /// Names of the example units to generate (default: all)
+    #[clap(long, value_delimiter = ',')]
+    names: Vec<String>,
+}
+
+#[derive(Parser)]
+struct Examples {
+    /// Names of the example units to write (default: all)
+    names: Vec<String>,
+    /// Target path (default: $Env:WPM_CONFIG_HOME, or $Env:USERPROFILE/.config/wpm)
+    #[clap(long)]
+    path: Option<PathBuf>,
+    /// Write TOML unit files instead of JSON unit files
+    #[clap(long, action)]
+    toml: bool,
+    /// Overwrite existing unit files
+    #[clap(long, action)]
+    force: bool,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum SchemaFormat {
+    /// JSON Schema for JSON and TOML unit files
+    Json,
+    /// taplo configuration associating TOML unit files with the JSON Schema
+    Toml,
+}
+
+#[derive(Parser)]
+struct Schema {
+    /// Schema format
+    #[clap(long, value_enum, default_value = "json")]
+    format: SchemaFormat,
+}
+
+#[derive(Parser)]
+struct LogLevel {
+    /// Log filter, using the same syntax as RUST_LOG (e.g. "info,wpm=debug")
+    filter: String,
+}
+
+#[derive(Parser)]
+enum DaemonSubCommand {
+    /// Change the log filter of the running daemon
+    LogLevel(LogLevel),
+}
+
+#[derive(Parser)]
+struct SnapshotName {
+    /// Snapshot name
+    name: String,
+}
+
+#[derive(Parser)]
+enum SnapshotSubCommand {
+    /// Save the set of running units
+    Save(SnapshotName),
+    /// Start the units in a snapshot and stop every other running unit
+    Restore(SnapshotName),
+}
+
+#[derive(Parser)]
+struct Snapshot {
+    #[clap(subcommand)]
+    subcmd: SnapshotSubCommand,
+}
+
+#[derive(Parser)]
+struct RotateLogs {
+    /// Target unit (default: wpmd and all units)
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
+    unit: Option<String>,
+}
+
+#[derive(Parser)]
+enum LogsSubCommand {
+    /// Move the current contents of logs into timestamped copies next to them
+    Rotate(RotateLogs),
+    /// Show the disk space taken up by the logs of wpmd and of each unit
+    Size,
+}
+
+#[derive(Parser)]
+struct Logs {
+    #[clap(subcommand)]
+    subcmd: LogsSubCommand,
+}
+
+#[derive(Parser)]
+struct Bundle {
+    /// Path of the zip file to write (default: wpm-bundle-<TIME>.zip in the current directory)
+    #[clap(long, short = 'o')]
+    output: Option<PathBuf>,
+    /// Number of recent lines to include from the log of each unit
+    #[clap(long, short = 'n', default_value_t = 200)]
+    lines: usize,
+}
+
+#[derive(Parser)]
+struct Daemon {
+    #[clap(subcommand)]
+    subcmd: DaemonSubCommand,
+}
+
+#[derive(Parser)]
+struct State {
+    /// Comma-separated columns to show (name, kind, state, pid, timestamp, reason, uptime)
+    #[clap(long, value_delimiter = ',')]
+    columns: Vec<StateColumn>,
+    /// Column to sort units by
+    #[clap(long)]
+    sort: Option<StateColumn>,
+    /// Re-render the table every N seconds until interrupted with Ctrl-C
+    #[clap(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "2", value_parser = clap::value_parser!(u64).range(1..))]
+    watch: Option<u64>,
+    /// Only show failed units (can be combined with the other state filters)
+    #[clap(long, action)]
+    failed: bool,
+    /// Only show running units (can be combined with the other state filters)
+    #[clap(long, action)]
+    running: bool,
+    /// Only show stopped units (can be combined with the other state filters)
+    #[clap(long, action)]
+    stopped: bool,
+    /// Only print the names of the units, one per line, for use in shell pipelines
+    #[clap(long, short = 'q', action, conflicts_with = "json")]
+    quiet: bool,
+    /// Print the units as JSON, with every column regardless of --columns
+    #[clap(long, action, conflicts_with = "watch")]
+    json: bool,
+    /// Number of recent log lines to include for each unit in JSON output (at most 50)
+    #[clap(long, default_value_t = 0, requires = "json")]
+    log_lines: usize,
 }
 
 #[derive(Parser)]
@@ -83,6 +401,72 @@ struct Reload {
     path: Option<PathBuf>,
 }
 
+#[derive(Parser)]
+struct Add {
+    /// URL of a .json or .toml unit file
+    url: String,
+    /// Treat unknown fields in the unit file as errors
+    #[clap(long, action)]
+    strict: bool,
+    /// Replace an existing unit file with the same name
+    #[clap(long, action)]
+    force: bool,
+}
+
+#[derive(Parser)]
+struct Remove {
+    /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
+    unit: String,
+    /// Keep the unit file, so that the unit is registered again on the next reload
+    #[clap(long, action)]
+    keep_file: bool,
+    /// Delete the log of the unit
+    #[clap(long, action)]
+    purge_logs: bool,
+    /// Delete the files in the store which belong to the unit and aren't used by other units
+    #[clap(long, action)]
+    purge_store: bool,
+}
+
+#[derive(Parser)]
+struct Mask {
+    /// Target unit
+    #[clap(add = ArgValueCandidates::new(completion::all_units))]
+    unit: String,
+}
+
+#[derive(Parser)]
+struct Unmask {
+    /// Target unit
+    unit: String,
+}
+
+#[derive(Parser)]
+struct Sync {
+    /// URL of the git repository to sync units from (default: the repository of the last sync)
+    #[clap(long)]
+    repository: Option<String>,
+    /// Branch to sync units from
+    #[clap(long, requires = "repository")]
+    branch: Option<String>,
+}
+
+#[derive(Parser)]
+struct Validate {
+    /// Target path
+    path: Option<PathBuf>,
+    /// Treat unknown fields in unit files as errors
+    #[clap(long, action)]
+    strict: bool,
+}
+
+#[derive(Parser)]
+struct Fmt {
+    /// Target path
+    path: Option<PathBuf>,
+}
+
 #[derive(Parser)]
 struct Rebuild {
     /// Target path
@@ -109,26 +493,137 @@ enum SubCommand {
     /// Restart units
     #[clap(arg_required_else_help = true)]
     Restart(Restart),
+    /// Start a unit and stream its logs until Ctrl-C, which stops the unit
+    #[clap(arg_required_else_help = true)]
+    Run(Run),
     /// Reset units
     #[clap(arg_required_else_help = true)]
     Reset(Reset),
+    /// Start units and their dependencies, and stop every other running unit
+    #[clap(arg_required_else_help = true)]
+    Isolate(Isolate),
+    /// Save and restore sets of running units
+    Snapshot(Snapshot),
+    /// Track already running processes for units instead of starting new ones
+    #[clap(arg_required_else_help = true)]
+    Adopt(Adopt),
     /// Show the state of the process manager
-    State,
+    State(State),
     /// Show status of a unit
     #[clap(arg_required_else_help = true)]
     Status(Status),
+    /// Explain how a unit ended up in its current state
+    #[clap(arg_required_else_help = true)]
+    Why(Why),
+    /// Show the environment a unit is started with, including the variables inherited from wpmd
+    #[clap(arg_required_else_help = true)]
+    Show(Show),
+    /// Show the recent memory and CPU usage of units
+    History(History),
+    /// Show aggregate statistics of the daemon and all units
+    Stats,
+    /// Open the documentation of a unit in the default browser
+    #[clap(arg_required_else_help = true)]
+    Docs(Docs),
     /// Reload all unit definitions
     Reload(Reload),
+    /// Download a unit file into the unit directory and load it
+    #[clap(arg_required_else_help = true)]
+    Add(Add),
+    /// Stop and unregister a unit, and delete its unit file
+    #[clap(arg_required_else_help = true)]
+    Remove(Remove),
+    /// Rename the unit file of a unit to .disabled so that it is no longer loaded, and stop and
+    /// unregister the unit
+    #[clap(arg_required_else_help = true)]
+    Mask(Mask),
+    /// Rename the .disabled unit file of a masked unit back and load it again
+    #[clap(arg_required_else_help = true)]
+    Unmask(Unmask),
+    /// Sync units from a git repository into the unit directory and reload them
+    Sync(Sync),
+    /// Manage the running daemon
+    Daemon(Daemon),
+    /// Write starter units for common tools to the wpm unit directory
+    Examples(Examples),
+    /// Validate unit definitions without loading them
+    Validate(Validate),
+    /// Print the schema for wpm units, or a taplo configuration for TOML unit files
+    Schema(Schema),
+    /// Add or update the $schema field of JSON unit files and the taplo schema directive of TOML
+    /// unit files, so that editors keep completing and validating them
+    Fmt(Fmt),
     /// Tail the logs of a unit or of the process manager
     Log(Log),
+    /// Rotate logs and show how much space they take up
+    Logs(Logs),
+    /// Collect logs, unit definitions and the state of the process manager into a zip file to
+    /// attach to bug reports
+    Bundle(Bundle),
     /// Ensure all remote dependencies are downloaded and built
     Rebuild(Rebuild),
     /// Print the path to the wpm global unit definition directory
     Units,
+    /// Print the path to the log file of a unit
+    #[clap(arg_required_else_help = true)]
+    Logpath(Logpath),
+    /// Print the paths to the files in the store which belong to a unit, such as its remote
+    /// executable and resources
+    #[clap(arg_required_else_help = true)]
+    Storepath(Storepath),
+}
+
+impl SubCommand {
+    fn requires_daemon(&self) -> bool {
+        matches!(
+            self,
+            SubCommand::Start(_)
+                | SubCommand::Run(_)
+                | SubCommand::Stop(_)
+                | SubCommand::Restart(_)
+                | SubCommand::Reset(_)
+                | SubCommand::Isolate(_)
+                | SubCommand::Snapshot(_)
+                | SubCommand::Adopt(_)
+                | SubCommand::State(_)
+                | SubCommand::Status(_)
+                | SubCommand::Why(_)
+                | SubCommand::Show(_)
+                | SubCommand::History(_)
+                | SubCommand::Stats
+                | SubCommand::Reload(_)
+                | SubCommand::Remove(_)
+                | SubCommand::Daemon(_)
+        )
+    }
+
+    /// Whether the subcommand reads unit files, unit logs or the store, which have to be the
+    /// ones wpmd uses if it was started with other directories
+    fn reads_daemon_directories(&self) -> bool {
+        matches!(
+            self,
+            SubCommand::Run(_)
+                | SubCommand::Log(_)
+                | SubCommand::Logs(_)
+                | SubCommand::Logpath(_)
+                | SubCommand::Storepath(_)
+                | SubCommand::Docs(_)
+                | SubCommand::Bundle(_)
+                | SubCommand::Units
+                | SubCommand::Examples(_)
+                | SubCommand::Fmt(_)
+                | SubCommand::Validate(_)
+                | SubCommand::Rebuild(_)
+                | SubCommand::Sync(_)
+                | SubCommand::Mask(_)
+                | SubCommand::Unmask(_)
+                | SubCommand::Add(_)
+        )
+    }
 }
 
 fn listen_for_response() -> Result<String, Box<dyn std::error::Error>> {
-    let name = "wpmctl.sock".to_ns_name::<GenericNamespaced>()?;
+    let name = reply_socket().to_ns_name::<GenericNamespaced>()?;
     let opts = ListenerOptions::new().name(name);
 
     let listener = match opts.create_sync() {
@@ -155,8 +650,436 @@ fn listen_for_response() -> Result<String, Box<dyn std::error::Error>> {
     Ok(buf)
 }
 
+/// Prints lines appended to a log file from `position` onwards until `interrupted` is set,
+/// starting over from the beginning of the file whenever it is truncated
+fn follow_log(
+    path: &Path,
+    mut position: u64,
+    interrupted: &AtomicBool,
+    color: bool,
+) -> std::io::Result<()> {
+    let mut pending = String::new();
+
+    while !interrupted.load(Ordering::SeqCst) {
+        let length = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+        if length < position {
+            position = 0;
+            pending.clear();
+        }
+
+        if length > position {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(position))?;
+
+            let mut buf = vec![];
+            position += file.read_to_end(&mut buf)? as u64;
+            pending.push_str(&String::from_utf8_lossy(&buf));
+
+            while let Some(index) = pending.find('\n') {
+                let line = pending.drain(..=index).collect::<String>();
+                let line = line.trim_end_matches(['\r', '\n']);
+                if color {
+                    println!("{line}");
+                } else {
+                    println!("{}", color::strip_ansi(line));
+                }
+            }
+        }
+
+        std::thread::sleep(FOLLOW_LOG_INTERVAL);
+    }
+
+    Ok(())
+}
+
+/// Sends a message to wpmd and waits for its response, listening for the response before
+/// sending so that a quick response can't be missed
+fn request(message: SocketMessage, launch: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let name = reply_socket().to_ns_name::<GenericNamespaced>()?;
+    let listener = ListenerOptions::new().name(name).create_sync()?;
+
+    send_to_daemon(message, launch)?;
+
+    let stream = listener.accept()?;
+    let mut buf = String::new();
+    BufReader::new(&stream).read_to_string(&mut buf)?;
+
+    Ok(buf)
+}
+
+/// Applies an action to units and returns the result for each of them
+fn batch(
+    action: UnitAction,
+    units: &[String],
+    launch: bool,
+) -> Result<Vec<UnitResult>, Box<dyn std::error::Error>> {
+    let response = request(SocketMessage::Batch(action, units.to_vec()), launch)?;
+    Ok(serde_json::from_str(&response)?)
+}
+
+/// Prints the results of a batch as JSON, as a table when there is more than one unit, or as the
+/// error of a single unit which failed (or how it was restarted)
+fn print_results(results: &[UnitResult], json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(results)?);
+    } else if results.len() > 1 {
+        println!("{}", results_table(results));
+    } else {
+        for result in results {
+            match (&result.error, result.restart) {
+                (Some(error), _) => println!("[{}]: {error}", result.name),
+                (None, Some(RestartBranch::Started)) => {
+                    println!("[{}]: Started, as it wasn't running", result.name)
+                }
+                (None, Some(RestartBranch::Restarted)) => println!("[{}]: Restarted", result.name),
+                (None, None) => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The units of a batch which the action was applied to successfully
+fn succeeded(results: &[UnitResult]) -> Vec<String> {
+    results
+        .iter()
+        .filter(|result| result.error.is_none())
+        .map(|result| result.name.clone())
+        .collect()
+}
+
+/// The given units and all of the units they require, directly or transitively, as registered
+/// with wpmd
+fn with_dependencies(
+    units: &[String],
+    launch: bool,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut closure = units.to_vec();
+    let mut queue = units.to_vec();
+
+    while !queue.is_empty() {
+        let response = request(SocketMessage::UnitStates(queue.clone()), launch)?;
+        let reports = serde_json::from_str::<Vec<UnitStateReport>>(&response)?;
+
+        queue.clear();
+        for dependency in reports.into_iter().flat_map(|report| report.requires) {
+            if !closure.contains(&dependency) {
+                closure.push(dependency.clone());
+                queue.push(dependency);
+            }
+        }
+    }
+
+    Ok(closure)
+}
+
+/// Whether a unit which is waiting for its dependencies will never be started, because a unit it
+/// requires, directly or through other stopped units, has failed
+fn has_failed_dependency(report: &UnitStateReport, reports: &[UnitStateReport]) -> bool {
+    let mut queue = report.requires.clone();
+    let mut seen = HashSet::new();
+
+    while let Some(name) = queue.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+
+        if let Some(dependency) = reports.iter().find(|report| report.name == name) {
+            match dependency.state {
+                UnitState::Failed | UnitState::Terminated | UnitState::LoadError => return true,
+                UnitState::Stopped => queue.extend(dependency.requires.iter().cloned()),
+                UnitState::Running | UnitState::Completed => {}
+            }
+        }
+    }
+
+    false
+}
+
+/// Draws values as a line of block characters scaled between zero and the largest value
+fn sparkline(values: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let max = values.iter().copied().fold(0.0, f64::max);
+
+    values
+        .iter()
+        .map(|value| {
+            if max <= 0.0 {
+                BLOCKS[0]
+            } else {
+                BLOCKS[((value / max) * (BLOCKS.len() - 1) as f64).round() as usize]
+            }
+        })
+        .collect()
+}
+
+/// Polls wpmd until `settled` reports an outcome for every unit or the timeout elapses, printing
+/// the final state of each unit and returning whether all of them settled successfully; `settled`
+/// is also given the latest reports of all of the units, so that it can look at their dependencies
+fn wait_for_units(
+    units: &[String],
+    timeout: Duration,
+    launch: bool,
+    settled: impl Fn(&UnitStateReport, &[UnitStateReport]) -> Option<bool>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let deadline = Instant::now() + timeout;
+    let mut pending = units.to_vec();
+    let mut success = true;
+
+    loop {
+        let response = request(SocketMessage::UnitStates(units.to_vec()), launch)?;
+        let reports = serde_json::from_str::<Vec<UnitStateReport>>(&response)?;
+
+        pending.retain(|name| {
+            let Some(report) = reports.iter().find(|report| &report.name == name) else {
+                println!("[{name}]: Not a registered unit");
+                success = false;
+                return false;
+            };
+
+            match settled(report, &reports) {
+                None => true,
+                Some(ok) => {
+                    println!("[{name}]: {}", report.state);
+                    success &= ok;
+                    false
+                }
+            }
+        });
+
+        if pending.is_empty() {
+            return Ok(success);
+        }
+
+        if Instant::now() >= deadline {
+            for report in reports
+                .iter()
+                .filter(|report| pending.contains(&report.name))
+            {
+                println!(
+                    "[{}]: Timed out waiting, last state was {}",
+                    report.name, report.state
+                );
+            }
+
+            return Ok(false);
+        }
+
+        std::thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+fn launch_daemon() -> std::io::Result<()> {
+    let wpmd = std::env::current_exe()
+        .ok()
+        .map(|exe| exe.with_file_name("wpmd.exe"))
+        .filter(|exe| exe.is_file())
+        .unwrap_or_else(|| PathBuf::from("wpmd.exe"));
+
+    eprintln!("wpmd is not running, launching {}", wpmd.display());
+
+    Command::new(wpmd)
+        .args(["--socket-name", daemon_socket()])
+        .creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    Ok(())
+}
+
+fn is_daemon_unavailable(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused
+    )
+}
+
+fn send_to_daemon(message: SocketMessage, launch: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match send_message(daemon_socket(), message.clone()) {
+        Err(error) if launch && is_daemon_unavailable(&error) => {
+            launch_daemon()?;
+
+            // wpmd only creates its socket after autostarting units, so keep
+            // retrying the original message until the socket is available
+            let deadline = Instant::now() + LAUNCH_DAEMON_TIMEOUT;
+            loop {
+                match send_message(daemon_socket(), message.clone()) {
+                    Ok(()) => return Ok(()),
+                    Err(error) if is_daemon_unavailable(&error) && Instant::now() < deadline => {
+                        std::thread::sleep(Duration::from_millis(250));
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+            }
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Asks wpmd to reload the unit directory after wpmctl changed unit files, which is skipped when
+/// wpmd isn't running as the units will be loaded when it starts
+fn reload_units(launch: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match send_to_daemon(SocketMessage::Reload(None), launch) {
+        Ok(()) => print!("{}", listen_for_response()?),
+        Err(error)
+            if error
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(is_daemon_unavailable) =>
+        {
+            println!("wpmd is not running, the units will be loaded when it starts");
+        }
+        Err(error) => return Err(error),
+    }
+
+    Ok(())
+}
+
+/// Asks wpmd to stop and unregister a unit whose unit file was masked, which is skipped when
+/// wpmd isn't running as the unit won't be loaded when it starts
+fn unregister_unit(name: String) -> Result<(), Box<dyn std::error::Error>> {
+    let options = RemoveOptions {
+        keep_file: true,
+        ..Default::default()
+    };
+
+    match send_to_daemon(SocketMessage::Remove(name, options), false) {
+        Ok(()) => print!("{}", listen_for_response()?),
+        Err(error)
+            if error
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(is_daemon_unavailable) => {}
+        Err(error) => return Err(error),
+    }
+
+    Ok(())
+}
+
+/// The definition of a unit in the unit directory, exiting if there is no such unit
+fn registered_unit(name: &str) -> Result<Definition, Box<dyn std::error::Error>> {
+    let (units, _) = ProcessManager::parse_units(None, false)?;
+
+    match units
+        .into_iter()
+        .find(|(_, definition)| definition.unit.name == name)
+    {
+        Some((_, definition)) => Ok(definition),
+        None => {
+            eprintln!("{name} is not a registered unit");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Waits for wpmd to connect to a non-blocking listener, returning `None` if it doesn't respond
+/// before the timeout
+fn accept_within(
+    listener: &LocalSocketListener,
+    timeout: Duration,
+) -> std::io::Result<Option<LocalSocketStream>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match listener.accept() {
+            Ok(stream) => return Ok(Some(stream)),
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Ok(None);
+                }
+
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Sends a message to wpmd without launching it and returns its response, or `None` if wpmd isn't
+/// running or doesn't respond before the timeout
+fn request_within(message: SocketMessage, timeout: Duration) -> Option<String> {
+    let name = reply_socket().to_ns_name::<GenericNamespaced>().ok()?;
+    let listener = ListenerOptions::new()
+        .name(name)
+        .nonblocking(ListenerNonblockingMode::Accept)
+        .create_sync()
+        .ok()?;
+
+    send_message(daemon_socket(), message).ok()?;
+    let stream = accept_within(&listener, timeout).ok()??;
+
+    let mut buf = String::new();
+    BufReader::new(&stream).read_to_string(&mut buf).ok()?;
+
+    Some(buf)
+}
+
+/// Uses the directories wpmd was started with, keeping the ones from the environment or the
+/// defaults if wpmd isn't running
+fn use_daemon_directories() -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(response) = request_within(SocketMessage::Directories, DIRECTORIES_TIMEOUT) {
+        set_directories(serde_json::from_str(&response)?)?;
+    }
+
+    Ok(())
+}
+
+fn check_protocol_version(launch: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let name = reply_socket().to_ns_name::<GenericNamespaced>()?;
+    let listener = ListenerOptions::new()
+        .name(name)
+        .nonblocking(ListenerNonblockingMode::Accept)
+        .create_sync()?;
+
+    send_to_daemon(SocketMessage::ProtocolVersion, launch)?;
+
+    let Some(stream) = accept_within(&listener, PROTOCOL_VERSION_TIMEOUT)? else {
+        eprintln!("warning: wpmd did not report a protocol version; it was likely built from an older version of wpm and should be restarted");
+        return Ok(());
+    };
+
+    let mut buf = String::new();
+    BufReader::new(&stream).read_to_string(&mut buf)?;
+
+    match buf.trim().parse::<u32>() {
+        Ok(version) if version == PROTOCOL_VERSION => Ok(()),
+        Ok(version) => Err(format!(
+            "wpmd uses protocol version {version} but wpmctl uses protocol version {PROTOCOL_VERSION}; restart wpmd so that both are running the same version of wpm"
+        )
+        .into()),
+        Err(_) => Err(format!("wpmd sent an invalid protocol version: {buf}").into()),
+    }
+}
+
+/// Exits with the names of the available example units when an unknown one was requested
+fn exit_on_unknown_example<T>(
+    result: Result<T, ProcessManagerError>,
+) -> Result<T, ProcessManagerError> {
+    if let Err(error @ ProcessManagerError::UnknownExample { .. }) = &result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+
+    result
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    CompleteEnv::with_factory(Opts::command).complete();
+
     let opts: Opts = Opts::parse();
+    set_socket_name(&opts.socket_name);
+    let launch = opts.launch_daemon;
+    let color = color::enabled(opts.no_color);
+
+    if opts.subcmd.requires_daemon() {
+        check_protocol_version(launch)?;
+    }
+
+    if opts.subcmd.reads_daemon_directories() {
+        use_daemon_directories()?;
+    }
+
     match opts.subcmd {
         SubCommand::Docgen => {
             let mut cli = Opts::command();
@@ -180,63 +1103,476 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         SubCommand::Schemagen => {
             println!("{}", Definition::schemagen());
         }
+        SubCommand::Examples(args) => {
+            let directory = args.path.unwrap_or_else(wpm_units_dir);
+            std::fs::create_dir_all(&directory)?;
+
+            let format = if args.toml {
+                ExampleFormat::Toml
+            } else {
+                ExampleFormat::Json
+            };
+
+            for example in exit_on_unknown_example(Definition::named_examples(&args.names))? {
+                let name = &example.unit.name;
+                let path = directory.join(format!("{name}.{format}"));
+                if path.exists() && !args.force {
+                    println!(
+                        "[{name}]: Skipping, {} already exists (use --force to overwrite)",
+                        path.display()
+                    );
+                    continue;
+                }
+
+                let path = example.write_example(&directory, format)?;
+                println!("[{name}]: Written to {}", path.display());
+            }
+        }
+        SubCommand::Schema(args) => match args.format {
+            SchemaFormat::Json => println!("{}", Definition::schemagen()),
+            SchemaFormat::Toml => print!("{}", Definition::taplogen()),
+        },
+        SubCommand::Fmt(args) => {
+            let (units, load_errors) = ProcessManager::parse_units(args.path, false)?;
+            let sync_dir = sync::sync_dir();
+
+            for (path, _) in units {
+                if path.starts_with(&sync_dir) {
+                    println!(
+                        "Skipped {}, which is synced from a git repository",
+                        path.display()
+                    );
+                    continue;
+                }
+
+                let format = if path
+                    .extension()
+                    .is_some_and(|extension| extension == "toml")
+                {
+                    ExampleFormat::Toml
+                } else {
+                    ExampleFormat::Json
+                };
+
+                let contents = std::fs::read_to_string(&path)?;
+                let formatted = with_schema_pointer(&contents, format);
+                if formatted != contents {
+                    std::fs::write(&path, formatted)?;
+                    println!("Updated {}", path.display());
+                }
+            }
+
+            for error in load_errors {
+                eprintln!(
+                    "Skipped {}, which could not be loaded: {}",
+                    error.path.display(),
+                    error.error
+                );
+            }
+        }
         SubCommand::Examplegen(args) => {
-            Definition::examplegen(args.path);
+            exit_on_unknown_example(Definition::examplegen(args.path, &args.names))?;
         }
         SubCommand::Start(args) => {
-            send_message("wpmd.sock", SocketMessage::Start(args.units))?;
+            let action = if args.no_deps {
+                UnitAction::StartWithoutDependencies
+            } else {
+                UnitAction::Start
+            };
+
+            let results = batch(action, &args.units, launch)?;
+            print_results(&results, args.json)?;
+
+            // units which failed to start have already been reported
+            let started = succeeded(&results);
+
+            if args.wait {
+                let timeout = Duration::from_secs(args.timeout);
+
+                // dependents are only started once the oneshots they require have completed
+                let waiting = if args.no_deps {
+                    started.clone()
+                } else {
+                    with_dependencies(&started, launch)?
+                };
+
+                let ready = wait_for_units(&waiting, timeout, launch, |report, reports| {
+                    match report.state {
+                        UnitState::Completed => Some(true),
+                        // oneshots are only ready once they have completed
+                        UnitState::Running => match report.kind {
+                            Some(ServiceKind::Oneshot) => None,
+                            _ => Some(true),
+                        },
+                        UnitState::Failed | UnitState::Terminated | UnitState::LoadError => {
+                            Some(false)
+                        }
+                        // a unit is never started once a unit it requires has failed
+                        UnitState::Stopped => {
+                            has_failed_dependency(report, reports).then_some(false)
+                        }
+                    }
+                })?;
+
+                if !ready {
+                    std::process::exit(1);
+                }
+            }
+
+            if started.len() < results.len() {
+                std::process::exit(1);
+            }
         }
         SubCommand::Stop(args) => {
-            send_message("wpmd.sock", SocketMessage::Stop(args.units))?;
+            let action = if args.force {
+                UnitAction::ForceStop
+            } else {
+                UnitAction::Stop
+            };
+
+            let results = batch(action, &args.units, launch)?;
+            print_results(&results, args.json)?;
+
+            let stopping = succeeded(&results);
+
+            if args.wait {
+                let timeout = Duration::from_secs(args.timeout);
+                let stopped = wait_for_units(&stopping, timeout, launch, |report, _| {
+                    (!matches!(report.state, UnitState::Running)).then_some(true)
+                })?;
+
+                if !stopped {
+                    std::process::exit(1);
+                }
+            }
+
+            if stopping.len() < results.len() {
+                std::process::exit(1);
+            }
+        }
+        SubCommand::Run(args) => {
+            let log_path = wpm_log_dir()?.join(format!("{}.log", args.unit));
+
+            // skip output from previous runs, the log is truncated when the unit starts
+            let position = std::fs::metadata(&log_path).map_or(0, |metadata| metadata.len());
+
+            let interrupted = Arc::new(AtomicBool::new(false));
+            let handler_interrupted = interrupted.clone();
+            ctrlc::set_handler(move || handler_interrupted.store(true, Ordering::SeqCst))?;
+
+            let results = batch(UnitAction::Start, std::slice::from_ref(&args.unit), launch)?;
+            if results.iter().any(|result| result.error.is_some()) {
+                print_results(&results, false)?;
+                std::process::exit(1);
+            }
+
+            follow_log(&log_path, position, &interrupted, color)?;
+
+            println!("Stopping {}", args.unit);
+            let results = batch(UnitAction::Stop, &[args.unit], launch)?;
+            print_results(&results, false)?;
         }
         SubCommand::Restart(args) => {
-            if args.with_dependents {
-                send_message(
-                    "wpmd.sock",
-                    SocketMessage::RestartWithDependents(args.units),
-                )?;
+            let action = if args.with_dependents {
+                UnitAction::RestartWithDependents
             } else {
-                send_message("wpmd.sock", SocketMessage::Restart(args.units))?;
+                UnitAction::Restart
+            };
+
+            let results = batch(action, &args.units, launch)?;
+            print_results(&results, args.json)?;
+
+            if results.iter().any(|result| result.error.is_some()) {
+                std::process::exit(1);
             }
         }
         SubCommand::Reset(args) => {
-            send_message("wpmd.sock", SocketMessage::Reset(args.units))?;
+            if args.kill_orphans && !args.units.is_empty() {
+                let message = SocketMessage::KillOrphans(args.units.clone());
+                print!("{}", request(message, launch)?);
+            }
+
+            if !args.units.is_empty() {
+                send_to_daemon(SocketMessage::Reset(args.units), launch)?;
+            }
+
+            if args.restart_budget {
+                send_to_daemon(SocketMessage::ResetRestartBudget, launch)?;
+            }
+        }
+        SubCommand::Isolate(args) => {
+            send_to_daemon(SocketMessage::Isolate(args.units), launch)?;
+        }
+        SubCommand::Adopt(args) => {
+            send_to_daemon(SocketMessage::Adopt(args.units), launch)?;
+            print!("{}", listen_for_response()?);
+        }
+        SubCommand::Snapshot(args) => {
+            let message = match args.subcmd {
+                SnapshotSubCommand::Save(args) => SocketMessage::SnapshotSave(args.name),
+                SnapshotSubCommand::Restore(args) => SocketMessage::SnapshotRestore(args.name),
+            };
+
+            send_to_daemon(message, launch)?;
+            print!("{}", listen_for_response()?);
         }
         SubCommand::Status(args) => {
-            send_message("wpmd.sock", SocketMessage::Status(args.unit.clone()))?;
+            send_to_daemon(SocketMessage::Status(args.unit.clone(), args.lines), launch)?;
             let response = listen_for_response()?;
-            println!("{}", response);
+            if color {
+                println!("{}", color::unit_status(&response));
+            } else {
+                println!("{}", color::strip_ansi(&response));
+            }
+        }
+        SubCommand::Why(args) => {
+            send_to_daemon(SocketMessage::Why(args.unit.clone()), launch)?;
+            let response = listen_for_response()?;
+            println!("{}", color::strip_ansi(&response));
+        }
+        SubCommand::Show(args) => {
+            send_to_daemon(SocketMessage::Show(args.unit.clone()), launch)?;
+            let response = listen_for_response()?;
+            println!("{response}");
+        }
+        SubCommand::Stats => {
+            send_to_daemon(SocketMessage::Stats, launch)?;
+            let response = listen_for_response()?;
+            println!("{response}");
+        }
+        SubCommand::History(args) => {
+            let response = request(SocketMessage::UsageHistory(args.units), launch)?;
+
+            if args.json {
+                println!("{response}");
+                return Ok(());
+            }
+
+            let history = serde_json::from_str::<BTreeMap<String, Vec<UsageSample>>>(&response)?;
+
+            if history.is_empty() {
+                println!("No usage samples have been recorded yet");
+            }
+
+            for (name, samples) in history {
+                let Some(latest) = samples.last() else {
+                    continue;
+                };
+
+                let cpu = samples
+                    .iter()
+                    .map(|sample| f64::from(sample.cpu_percent))
+                    .collect::<Vec<_>>();
+
+                let memory = samples
+                    .iter()
+                    .map(|sample| sample.memory as f64)
+                    .collect::<Vec<_>>();
+
+                println!("{name}:");
+                println!("  CPU:    {} {:.0}%", sparkline(&cpu), latest.cpu_percent);
+                println!(
+                    "  Memory: {} {}MB",
+                    sparkline(&memory),
+                    latest.memory / (1024 * 1024)
+                );
+            }
         }
-        SubCommand::State => {
-            send_message("wpmd.sock", SocketMessage::State)?;
-            println!("{}", listen_for_response()?);
+        SubCommand::State(args) => {
+            let states = [
+                (args.failed, UnitState::Failed),
+                (args.running, UnitState::Running),
+                (args.stopped, UnitState::Stopped),
+            ]
+            .into_iter()
+            .filter_map(|(requested, state)| requested.then_some(state))
+            .collect();
+
+            let options = StateOptions {
+                columns: args.columns,
+                sort: args.sort,
+                states,
+                quiet: args.quiet,
+                json: args.json,
+                log_lines: args.log_lines,
+            };
+
+            loop {
+                send_to_daemon(SocketMessage::State(options.clone()), launch)?;
+                let response = listen_for_response()?;
+                let table = if args.json {
+                    response
+                } else if color {
+                    color::state_table(&response)
+                } else {
+                    color::strip_ansi(&response)
+                };
+
+                let Some(interval) = args.watch else {
+                    // quiet output already ends with a newline unless there are no units at all
+                    if args.quiet {
+                        print!("{table}");
+                    } else {
+                        println!("{table}");
+                    }
+
+                    break;
+                };
+
+                // clear the screen and move the cursor to the top left before re-rendering
+                if std::io::stdout().is_terminal() {
+                    print!("\x1b[2J\x1b[H");
+                }
+
+                println!("Every {interval}s: wpmctl state (Ctrl-C to exit)\n");
+                println!("{table}");
+                std::thread::sleep(Duration::from_secs(interval));
+            }
         }
+        SubCommand::Daemon(args) => match args.subcmd {
+            DaemonSubCommand::LogLevel(args) => {
+                send_to_daemon(SocketMessage::LogLevel(args.filter), launch)?;
+                print!("{}", listen_for_response()?);
+            }
+        },
         SubCommand::Reload(args) => {
-            send_message("wpmd.sock", SocketMessage::Reload(args.path))?;
+            send_to_daemon(SocketMessage::Reload(args.path), launch)?;
+            print!("{}", listen_for_response()?);
+        }
+        SubCommand::Sync(args) => {
+            let config = args
+                .repository
+                .map(|repository| SyncConfig::new(repository, args.branch));
+
+            let commit = sync::sync(config)?;
+            println!(
+                "Synced units at {commit} into {}",
+                sync::sync_dir().display()
+            );
+
+            reload_units(launch)?;
+        }
+        SubCommand::Remove(args) => {
+            let options = RemoveOptions {
+                keep_file: args.keep_file,
+                purge_logs: args.purge_logs,
+                purge_store: args.purge_store,
+            };
+
+            send_to_daemon(SocketMessage::Remove(args.unit, options), launch)?;
+            print!("{}", listen_for_response()?);
+        }
+        SubCommand::Mask(args) => {
+            let path = ProcessManager::mask(&args.unit)?;
+            println!("Masked {}", path.display());
+            unregister_unit(args.unit)?;
+        }
+        SubCommand::Unmask(args) => {
+            let path = ProcessManager::unmask(&args.unit)?;
+            println!("Unmasked {}", path.display());
+            reload_units(launch)?;
+        }
+        SubCommand::Add(args) => {
+            let path = ProcessManager::add_unit(&args.url, args.strict, args.force)?;
+            println!("Added {}", path.display());
+            reload_units(launch)?;
         }
         SubCommand::Log(args) => match args.unit {
             None => {
-                let timestamp = Utc::now().format("%Y-%m-%d").to_string();
-                let color_log = std::env::temp_dir().join(format!("wpmd.log.{timestamp}"));
-                let file = TailedFile::new(File::open(color_log)?);
+                let file = TailedFile::new(File::open(current_daemon_log(DAEMON_LOG))?);
                 let locked = file.lock();
                 #[allow(clippy::significant_drop_in_scrutinee, clippy::lines_filter_map_ok)]
                 for line in locked.lines().flatten() {
-                    println!("{line}");
+                    if color {
+                        println!("{line}");
+                    } else {
+                        println!("{}", color::strip_ansi(&line));
+                    }
                 }
             }
             Some(unit) => {
-                let dir = wpm_data_dir().join("logs");
-                let file = File::open(dir.join(format!("{}.log", unit))).unwrap();
+                let path = wpm_log_dir()?.join(format!("{unit}.log"));
+                if !path.is_file() {
+                    eprintln!("[{unit}]: No logs yet, waiting for output");
+                }
+
+                // created here if the unit has never been started, so that its output is
+                // followed once it is
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .read(true)
+                    .open(&path)?;
 
                 let file = TailedFile::new(file);
                 let locked = file.lock();
                 #[allow(clippy::significant_drop_in_scrutinee, clippy::lines_filter_map_ok)]
                 for line in locked.lines().flatten() {
-                    println!("{line}");
+                    if color {
+                        println!("{line}");
+                    } else {
+                        println!("{}", color::strip_ansi(&line));
+                    }
+                }
+            }
+        },
+        SubCommand::Logs(args) => match args.subcmd {
+            LogsSubCommand::Rotate(args) => {
+                let logs = match args.unit {
+                    Some(unit) => vec![registered_unit(&unit)?.log_path()?],
+                    None => {
+                        let mut logs = vec![
+                            current_daemon_log(DAEMON_LOG),
+                            current_daemon_log(DAEMON_PLAINTEXT_LOG),
+                        ];
+
+                        for entry in std::fs::read_dir(wpm_log_dir()?)?.flatten() {
+                            let path = entry.path();
+                            if path.extension().is_some_and(|extension| extension == "log") {
+                                logs.push(path);
+                            }
+                        }
+
+                        logs
+                    }
+                };
+
+                for log in logs {
+                    match rotate(&log) {
+                        Ok(Some(rotated)) => {
+                            println!("Rotated {} to {}", log.display(), rotated.display())
+                        }
+                        Ok(None) => {
+                            println!("Skipped {}, there is nothing to rotate", log.display())
+                        }
+                        Err(error) => eprintln!("Failed to rotate {}: {error}", log.display()),
+                    }
                 }
             }
+            LogsSubCommand::Size => println!("{}", usage_table(&log_usage()?)),
         },
+        SubCommand::Bundle(args) => {
+            let output = args
+                .output
+                .unwrap_or_else(|| PathBuf::from(SupportBundle::default_file_name()));
+
+            let options = StateOptions {
+                json: true,
+                ..Default::default()
+            };
+
+            let bundle = SupportBundle {
+                version: build::CLAP_LONG_VERSION.to_string(),
+                state: request_within(SocketMessage::State(options), BUNDLE_STATE_TIMEOUT),
+                log_lines: args.lines,
+            };
+
+            for entry in bundle.write(&output)? {
+                println!("  {entry}");
+            }
+
+            println!("Wrote the support bundle to {}", output.display());
+        }
         SubCommand::Rebuild(args) => {
             let mut units = ProcessManager::retrieve_units(args.path)?;
             for definition in &mut units {
@@ -265,9 +1601,69 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
         }
+        SubCommand::Docs(args) => {
+            let definition = registered_unit(&args.unit)?;
+
+            match definition.unit.documentation.iter().flatten().next() {
+                None => {
+                    eprintln!("{} does not have any documentation", args.unit);
+                    std::process::exit(1);
+                }
+                Some(url) => {
+                    println!("Opening {url}");
+                    Command::new("explorer").arg(url).spawn()?;
+                }
+            }
+        }
+        SubCommand::Validate(args) => {
+            let (units, load_errors) = ProcessManager::parse_units(args.path, args.strict)?;
+            let definitions = units
+                .into_iter()
+                .map(|(_, definition)| definition)
+                .collect::<Vec<_>>();
+
+            for error in &load_errors {
+                println!("[{}]: Failed to load {}", error.name, error.path.display());
+                for line in error.error.lines() {
+                    println!("  {line}");
+                }
+            }
+
+            for warning in validate_definitions(&definitions) {
+                println!(
+                    "[{}]: Warning: {}: {}",
+                    warning.unit, warning.field, warning.message
+                );
+            }
+
+            println!(
+                "{} valid units, {} units failed to load",
+                definitions.len(),
+                load_errors.len()
+            );
+
+            if !load_errors.is_empty() {
+                std::process::exit(1);
+            }
+        }
         SubCommand::Units => {
             println!("{}", wpm_units_dir().display());
         }
+        SubCommand::Logpath(args) => {
+            println!("{}", registered_unit(&args.unit)?.log_path()?.display());
+        }
+        SubCommand::Storepath(args) => {
+            let paths = registered_unit(&args.unit)?.store_paths();
+
+            if paths.is_empty() {
+                eprintln!("{} does not have any files in the store", args.unit);
+                std::process::exit(1);
+            }
+
+            for path in paths {
+                println!("{}", path.display());
+            }
+        }
     }
 
     Ok(())
@@ -7,33 +7,170 @@ use interprocess::local_socket::ListenerOptions;
 use interprocess::local_socket::Stream;
 use interprocess::local_socket::ToNsName;
 use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::path::PathBuf;
 use std::process::exit;
 use std::sync::mpsc;
 use std::sync::Arc;
-use sysinfo::Process;
-use sysinfo::ProcessesToUpdate;
-use sysinfo::System;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
 use thiserror::Error;
+#[cfg(not(feature = "otel"))]
+use tracing_subscriber::layer::Identity;
 use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::reload;
 use tracing_subscriber::EnvFilter;
+use tracing_subscriber::Layer;
+use tracing_subscriber::Registry;
+use windows_sys::Win32::Foundation::GetLastError;
+use windows_sys::Win32::Foundation::ERROR_ALREADY_EXISTS;
+use windows_sys::Win32::System::Threading::CreateMutexW;
+use wpm::communication::client_socket;
+use wpm::communication::daemon_socket;
 use wpm::communication::send_str;
+use wpm::communication::set_socket_name;
+use wpm::communication::DEFAULT_SOCKET_NAME;
+use wpm::log_files::daemon_log_dir;
+use wpm::log_files::DAEMON_LOG;
+use wpm::log_files::DAEMON_PLAINTEXT_LOG;
 use wpm::process_manager::ProcessManager;
 use wpm::process_manager::ProcessManagerError;
-use wpm::unit_status::UnitState;
+use wpm::process_manager::SESSION_SNAPSHOT;
+use wpm::restart_budget;
+use wpm::set_data_dir;
+use wpm::set_log_dir;
+use wpm::set_store_dir;
+use wpm::set_units_dir;
+use wpm::startup_slots;
+use wpm::state_hooks::set_state_hooks;
+use wpm::state_hooks::StateHooks;
+use wpm::time_format;
+use wpm::time_format::set_time_format;
+use wpm::time_format::DisplayTimeZone;
+use wpm::time_format::TimeFormat;
+use wpm::unit::unit_span;
+use wpm::unit_status::UnitResult;
+use wpm::unit_status::UnitStateReport;
+use wpm::usage_history;
+use wpm::wpm_data_dir;
+use wpm::wpm_directories;
+use wpm::wpm_log_dir;
+use wpm::wpm_store_dir;
+use wpm::SocketEnvelope;
 use wpm::SocketMessage;
+use wpm::UnitAction;
+use wpm::PROTOCOL_VERSION;
+
+#[cfg(feature = "otel")]
+mod otel;
+mod path_watcher;
+mod resource_monitor;
+mod socket_activation;
 
 shadow_rs::shadow!(build);
 
-static SOCKET_NAME: &str = "wpmd.sock";
+/// Handle used to replace the log filter at runtime
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// When wpmd started, for the daemon uptime in `wpmctl stats`
+static STARTED_AT: OnceLock<Instant> = OnceLock::new();
 
 #[derive(Parser)]
 #[clap(author, about, version = build::CLAP_LONG_VERSION)]
 struct Args {
-    /// Path to unit files (default: $Env:USERPROFILE/.config/wpm)
+    /// Path to unit files, the same as --units-dir
+    #[clap(conflicts_with = "units_dir")]
     path: Option<PathBuf>,
+    /// Path to unit files (default: $Env:WPM_CONFIG_HOME, or $Env:USERPROFILE/.config/wpm)
+    #[clap(long)]
+    units_dir: Option<PathBuf>,
+    /// Log human-readable colored output to the console as well as to the log files
+    #[clap(long, action)]
+    foreground: bool,
+    /// Fail to load unit files which contain unknown fields
+    #[clap(long, env = "WPM_STRICT", action)]
+    strict: bool,
+    /// Seconds to wait for units to stop when shutting down before killing the remaining units
+    #[clap(long, env = "WPM_SHUTDOWN_TIMEOUT_SEC", default_value_t = 30)]
+    shutdown_timeout: u64,
+    /// Automatic restarts allowed across all units within a minute before automatic restarts are paused
+    #[clap(long, env = "WPM_MAX_RESTARTS_PER_MINUTE", default_value_t = restart_budget::DEFAULT_MAX_RESTARTS_PER_MINUTE)]
+    max_restarts_per_minute: usize,
+    /// Units which can be spawning or running their healthchecks at the same time (default: number of logical processors)
+    #[clap(long, env = "WPM_MAX_CONCURRENT_STARTS")]
+    max_concurrent_starts: Option<usize>,
+    /// Save the running units when shutting down, and restore them on the next start
+    #[clap(long, env = "WPM_RESTORE_SESSION", action)]
+    restore_session: bool,
+    /// Command to run with the name of a unit and its state as arguments when the unit fails
+    #[clap(long, env = "WPM_ON_UNIT_FAILED")]
+    on_unit_failed: Option<PathBuf>,
+    /// Command to run with the name of a unit and its state as arguments when the unit has been started
+    #[clap(long, env = "WPM_ON_UNIT_STARTED")]
+    on_unit_started: Option<PathBuf>,
+    /// strftime-style format of the timestamps shown in the state table, unit status and journal
+    #[clap(long, env = "WPM_TIME_FORMAT", default_value = time_format::DEFAULT_TIME_FORMAT, value_parser = time_format::parse_format)]
+    time_format: String,
+    /// Time zone of the timestamps shown in the state table, unit status and journal (local or utc)
+    #[clap(long, env = "WPM_TIME_ZONE", default_value_t = DisplayTimeZone::Local)]
+    time_zone: DisplayTimeZone,
+    /// Directory to keep wpm data in (default: $Env:WPM_DATA_HOME, or $Env:LOCALAPPDATA/wpm)
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+    /// Directory to store remote executables and scripts in (default: $Env:WPM_STORE_DIR, or the store directory in the data directory)
+    #[clap(long)]
+    store_dir: Option<PathBuf>,
+    /// Directory to write unit logs to (default: $Env:WPM_LOG_DIR, or the logs directory in the data directory)
+    #[clap(long)]
+    log_dir: Option<PathBuf>,
+    /// Name of the socket to listen on, so that multiple independent wpmd instances can run side by side
+    #[clap(long, env = "WPM_SOCKET_NAME", default_value = DEFAULT_SOCKET_NAME)]
+    socket_name: String,
+    /// Export unit lifecycle traces over OTLP, configured with the OTEL_EXPORTER_OTLP_* environment variables
+    #[cfg(feature = "otel")]
+    #[clap(long, action)]
+    otlp: bool,
+}
+
+/// Name identifying this wpmd instance, which is only qualified with the socket name when a
+/// custom socket name is used
+fn instance_name() -> String {
+    match daemon_socket() {
+        DEFAULT_SOCKET_NAME => String::from("wpmd"),
+        socket_name => format!("wpmd.{socket_name}"),
+    }
+}
+
+/// Claims the single-instance mutex, returning false if another wpmd instance with the same
+/// socket name already holds it in the current session
+fn claim_single_instance() -> bool {
+    let name = format!("Local\\{}", instance_name())
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect::<Vec<_>>();
+
+    // the handle is never closed so that the mutex is held until this process exits
+    let handle = unsafe { CreateMutexW(std::ptr::null(), 0, name.as_ptr()) };
+    if handle.is_null() {
+        tracing::warn!(
+            "failed to create the single-instance mutex: {}",
+            std::io::Error::last_os_error()
+        );
+
+        return true;
+    }
+
+    let error = unsafe { GetLastError() };
+    error != ERROR_ALREADY_EXISTS
+}
+
+/// File containing the PID of the running wpmd instance, used to identify it in errors
+fn pid_file() -> std::io::Result<PathBuf> {
+    Ok(wpm_data_dir()?.join(format!("{}.pid", instance_name())))
 }
 
 #[derive(Error, Debug)]
@@ -46,8 +183,48 @@ pub enum WpmdError {
     Io(#[from] std::io::Error),
 }
 
+/// Applies an action to each of the given units, continuing past failures
+fn apply(pm: &mut ProcessManager, action: UnitAction, names: &[String]) -> Vec<UnitResult> {
+    match action {
+        UnitAction::Start => pm.start_each(names),
+        UnitAction::StartWithoutDependencies => pm.start_each_without_dependencies(names),
+        UnitAction::Stop => pm.stop_each(names, false),
+        UnitAction::ForceStop => pm.stop_each(names, true),
+        UnitAction::Restart => pm.restart_each(names, false),
+        UnitAction::RestartWithDependents => pm.restart_each(names, true),
+    }
+}
+
+/// Applies an action to units and sends the result for each of them to the client, along with
+/// the results of the units which were coalesced with a start attempt in flight
+///
+/// The results are returned even when the client has gone away, as they are still used to answer
+/// the start requests which were coalesced with this one
+fn answer(
+    pm: &mut ProcessManager,
+    action: UnitAction,
+    names: &[String],
+    coalesced: Vec<UnitResult>,
+    reply_to: &str,
+) -> Vec<UnitResult> {
+    let mut results = apply(pm, action, names);
+    results.extend(coalesced);
+
+    let sent = serde_json::to_string(&results)
+        .map_err(WpmdError::from)
+        .and_then(|response| Ok(send_str(reply_to, &response)?));
+
+    if let Err(error) = sent {
+        tracing::error!("failed to send the results to {reply_to}: {error}");
+    }
+
+    results
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Args = Args::parse();
+    set_socket_name(&args.socket_name);
+    let _ = STARTED_AT.set(Instant::now());
 
     if std::env::var("RUST_LIB_BACKTRACE").is_err() {
         std::env::set_var("RUST_LIB_BACKTRACE", "1");
@@ -75,15 +252,44 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("\n# Documentation");
     println!("* Read the docs https://lgug2z.github.io/wpm\n");
 
-    let appender = tracing_appender::rolling::daily(std::env::temp_dir(), "wpmd_plaintext.log");
-    let color_appender = tracing_appender::rolling::daily(std::env::temp_dir(), "wpmd.log");
+    let appender = tracing_appender::rolling::daily(daemon_log_dir(), DAEMON_PLAINTEXT_LOG);
+    let color_appender = tracing_appender::rolling::daily(daemon_log_dir(), DAEMON_LOG);
     let (non_blocking, _guard) = tracing_appender::non_blocking(appender);
     let (color_non_blocking, _color_guard) = tracing_appender::non_blocking(color_appender);
 
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::from_default_env());
+    FILTER_HANDLE
+        .set(filter_handle)
+        .expect("the log filter handle should only be set once");
+
+    // in foreground mode the console gets multi-line human-readable output with colors, which is
+    // easier to follow while developing unit files than the compact output written to log files
+    let console_layer = if args.foreground {
+        tracing_subscriber::fmt::Layer::default()
+            .pretty()
+            .with_ansi(true)
+            .boxed()
+    } else {
+        tracing_subscriber::fmt::Layer::default().boxed()
+    };
+
+    #[cfg(feature = "otel")]
+    let tracer_provider = if args.otlp {
+        Some(otel::tracer_provider()?)
+    } else {
+        None
+    };
+
+    #[cfg(feature = "otel")]
+    let otel_layer = tracer_provider.as_ref().map(otel::layer);
+    #[cfg(not(feature = "otel"))]
+    let otel_layer = None::<Identity>;
+
     tracing::subscriber::set_global_default(
-        tracing_subscriber::fmt::Subscriber::builder()
-            .with_env_filter(EnvFilter::from_default_env())
-            .finish()
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(console_layer)
+            .with(otel_layer)
             .with(
                 tracing_subscriber::fmt::Layer::default()
                     .with_writer(non_blocking)
@@ -96,37 +302,85 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             ),
     )?;
 
-    if args.path.is_none() {
-        let mut system = System::new_all();
-        system.refresh_processes(ProcessesToUpdate::All, true);
-        let matched_procs: Vec<&Process> = system.processes_by_name("wpmd.exe".as_ref()).collect();
-        if matched_procs.len() > 1 {
-            let mut len = matched_procs.len();
-            for proc in matched_procs {
-                if let Some(executable_path) = proc.exe() {
-                    if executable_path.to_string_lossy().contains("shims") {
-                        len -= 1;
-                    }
-                }
-            }
+    if let Some(data_dir) = args.data_dir {
+        set_data_dir(data_dir)?;
+    }
 
-            if len > 1 {
-                tracing::error!("wpmd.exe is already running, please exit the existing process before starting a new one");
-                exit(1);
-            }
+    if let Some(store_dir) = args.store_dir {
+        set_store_dir(store_dir)?;
+    }
+
+    if let Some(log_dir) = args.log_dir {
+        set_log_dir(log_dir)?;
+    }
+
+    // resolved up front so that a directory which can't be created stops wpmd from starting
+    // instead of failing units later on
+    wpm_store_dir()?;
+    wpm_log_dir()?;
+    let pid_file = pid_file()?;
+
+    if !claim_single_instance() {
+        let pid = std::fs::read_to_string(&pid_file)
+            .map(|pid| format!(" (PID {})", pid.trim()))
+            .unwrap_or_default();
+
+        tracing::error!("wpmd is already running{pid}, please exit the existing process before starting a new one");
+        exit(1);
+    }
+
+    if let Err(error) = std::fs::write(&pid_file, std::process::id().to_string()) {
+        tracing::warn!("failed to write {}: {error}", pid_file.display());
+    }
+
+    restart_budget::set_limit(args.max_restarts_per_minute);
+    set_state_hooks(StateHooks {
+        on_unit_failed: args.on_unit_failed.clone(),
+        on_unit_started: args.on_unit_started.clone(),
+    });
+    set_time_format(TimeFormat {
+        format: args.time_format.clone(),
+        time_zone: args.time_zone,
+    });
+    startup_slots::set_limit(Some(args.max_concurrent_starts.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, |parallelism| parallelism.get())
+    })));
+
+    // the unit directory is set for the whole process so that reloads without a path and the
+    // path watcher use the same directory as the initial load
+    if let Some(units_dir) = args.units_dir.or(args.path) {
+        set_units_dir(units_dir);
+    }
+
+    let mut process_manager = ProcessManager::init(None, args.strict)?;
+
+    if args.restore_session {
+        match process_manager.restore_snapshot(SESSION_SNAPSHOT) {
+            Ok(_) | Err(ProcessManagerError::UnknownSnapshot(_)) => {}
+            Err(error) => tracing::error!("failed to restore the previous session: {error}"),
         }
     }
 
-    let process_manager = ProcessManager::init(args.path)?;
+    // socket activations are only set up when wpmd starts, as their listeners can't be moved
+    // to other addresses when units are reloaded
+    for (name, activation) in process_manager.socket_activations() {
+        if let Err(error) = socket_activation::spawn(name.clone(), activation) {
+            let _span = unit_span(&name).entered();
+            tracing::error!("failed to set up socket activation: {error}");
+        }
+    }
 
     let process_manager_arc = Arc::new(Mutex::new(process_manager));
     let loop_arc = process_manager_arc.clone();
     let ctrlc_arc = process_manager_arc.clone();
 
-    let name = SOCKET_NAME.to_ns_name::<GenericNamespaced>()?;
+    path_watcher::spawn(process_manager_arc.clone());
+    resource_monitor::spawn(process_manager_arc.clone());
+
+    let name = daemon_socket().to_ns_name::<GenericNamespaced>()?;
     let opts = ListenerOptions::new().name(name.clone());
 
-    let (tx, rx) = mpsc::channel::<SocketMessage>();
+    let (tx, rx) = mpsc::channel::<(SocketMessage, Vec<String>, String)>();
 
     let listener = match opts.create_sync() {
         Err(error) if error.kind() == std::io::ErrorKind::AddrInUse => {
@@ -136,7 +390,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         x => x?,
     };
 
-    tracing::info!("listening on {SOCKET_NAME}");
+    tracing::info!("listening on {}", daemon_socket());
+
+    // units with a start request which is queued or being handled
+    let starting = Arc::new(Mutex::new(HashSet::new()));
+    let listener_starting = starting.clone();
 
     std::thread::spawn(move || loop {
         let conn = match listener.accept() {
@@ -147,8 +405,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         };
 
-        if let Ok(socket_message) = extract_socket_message(conn) {
-            match tx.send(socket_message) {
+        if let Ok((socket_message, reply_to)) = extract_socket_message(conn) {
+            // answered directly so that version checks don't queue behind slow unit starts
+            if matches!(socket_message, SocketMessage::ProtocolVersion) {
+                if let Err(error) = send_str(&reply_to, &PROTOCOL_VERSION.to_string()) {
+                    tracing::error!("failed to send protocol version: {error}");
+                }
+
+                continue;
+            }
+
+            // answered directly so that wpmctl can resolve paths while units are starting
+            if matches!(socket_message, SocketMessage::Directories) {
+                if let Err(error) = send_directories(&reply_to) {
+                    tracing::error!("failed to send directories: {error}");
+                }
+
+                continue;
+            }
+
+            let (socket_message, coalesced) = coalesce_start(socket_message, &listener_starting);
+
+            match tx.send((socket_message, coalesced, reply_to)) {
                 Ok(_) => {
                     tracing::info!("successfully queued socket message");
                 }
@@ -160,10 +438,47 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     std::thread::spawn(move || {
-        while let Ok(message) = rx.recv() {
+        // the result of the latest start attempt of each unit, for the start requests which were
+        // coalesced with it
+        let mut latest_starts = HashMap::new();
+
+        while let Ok((mut message, coalesced, reply_to)) = rx.recv() {
+            let started = start_request(&mut message).cloned().unwrap_or_default();
+
+            // the attempts these requests were coalesced with were queued before them, so they
+            // are over by now
+            let coalesced = coalesced
+                .into_iter()
+                .map(|name| {
+                    latest_starts
+                        .get(&name)
+                        .cloned()
+                        .unwrap_or_else(|| UnitResult {
+                            error: Some(String::from(
+                                "the start attempt this request was coalesced with did not finish",
+                            )),
+                            name,
+                            restart: None,
+                        })
+                })
+                .collect();
+
             let pm = loop_arc.clone();
-            if let Err(error) = handle_socket_message(pm, message) {
-                tracing::error!("{error}")
+            match handle_socket_message(pm, message, coalesced, &reply_to) {
+                Ok(results) => {
+                    for result in results {
+                        if started.contains(&result.name) {
+                            latest_starts.insert(result.name.clone(), result);
+                        }
+                    }
+                }
+                Err(error) => tracing::error!("{error}"),
+            }
+
+            // the start attempt is over, so later start requests for these units are queued again
+            let mut starting = starting.lock();
+            for name in &started {
+                starting.remove(name);
             }
         }
     });
@@ -179,95 +494,330 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .recv()
         .expect("could not receive signal on ctrl-c channel");
 
-    ctrlc_arc.lock().shutdown()?;
+    if args.restore_session {
+        if let Err(error) = ctrlc_arc.lock().save_snapshot(SESSION_SNAPSHOT) {
+            tracing::error!("failed to save the session: {error}");
+        }
+    }
+
+    ctrlc_arc
+        .lock()
+        .shutdown(Duration::from_secs(args.shutdown_timeout))?;
+    let _ = std::fs::remove_file(&pid_file);
+
+    #[cfg(feature = "otel")]
+    if let Some(tracer_provider) = tracer_provider {
+        if let Err(error) = tracer_provider.shutdown() {
+            tracing::warn!("failed to flush traces: {error}");
+        }
+    }
 
     Ok(())
 }
 
-fn extract_socket_message(conn: Stream) -> Result<SocketMessage, WpmdError> {
+/// Reads a socket message along with the name of the socket its response is sent to
+fn extract_socket_message(conn: Stream) -> Result<(SocketMessage, String), WpmdError> {
     let mut conn = BufReader::new(&conn);
     let mut buf = String::new();
     conn.read_line(&mut buf)?;
 
-    match serde_json::from_str::<SocketMessage>(&buf) {
+    match serde_json::from_str::<SocketEnvelope>(&buf) {
+        Ok(envelope) => {
+            if envelope.protocol_version != PROTOCOL_VERSION {
+                tracing::warn!(
+                    "received socket message using protocol version {} (wpmd uses protocol version {PROTOCOL_VERSION}); restart wpmd after upgrading wpm",
+                    envelope.protocol_version
+                );
+            }
+
+            tracing::info!("received socket message: {:?}", envelope.message);
+            let reply_to = envelope.reply_to.unwrap_or_else(client_socket);
+            Ok((envelope.message, reply_to))
+        }
         Err(error) => {
-            tracing::error!("failed to deserialize socket message: {error}");
+            // clients built before the protocol was versioned send bare messages
+            if let Ok(socket_message) = serde_json::from_str::<SocketMessage>(&buf) {
+                tracing::warn!("received socket message without a protocol version (wpmd uses protocol version {PROTOCOL_VERSION}); restart wpmd after upgrading wpm");
+                tracing::info!("received socket message: {socket_message:?}");
+                return Ok((socket_message, client_socket()));
+            }
+
+            let protocol_version = serde_json::from_str::<serde_json::Value>(&buf)
+                .ok()
+                .and_then(|value| value.get("protocol_version")?.as_u64());
+
+            match protocol_version {
+                Some(version) if version != u64::from(PROTOCOL_VERSION) => {
+                    tracing::error!(
+                        "failed to deserialize socket message using protocol version {version} (wpmd uses protocol version {PROTOCOL_VERSION}): {error}"
+                    );
+                }
+                _ => {
+                    tracing::error!("failed to deserialize socket message: {error}");
+                }
+            }
+
             Err(WpmdError::SerdeJson(error))
         }
-        Ok(socket_message) => {
-            tracing::info!("received socket message: {socket_message:?}");
-            Ok(socket_message)
-        }
     }
 }
 
+/// The units of a message which starts them, which can be coalesced with a start attempt in
+/// flight
+fn start_request(message: &mut SocketMessage) -> Option<&mut Vec<String>> {
+    match message {
+        SocketMessage::Start(names)
+        | SocketMessage::StartWithoutDependencies(names)
+        | SocketMessage::Batch(UnitAction::Start | UnitAction::StartWithoutDependencies, names)
+        | SocketMessage::Scheduled(
+            UnitAction::Start | UnitAction::StartWithoutDependencies,
+            names,
+        ) => Some(names),
+        _ => None,
+    }
+}
+
+/// Takes units out of a start request which already have a start request queued or in progress,
+/// so that repeated requests attach to the attempt in flight instead of failing with RunningUnit
+/// once it completes, and marks the remaining units as starting
+///
+/// Returns the message along with the units which were taken out of it, which are answered with
+/// the result of the attempt in flight
+fn coalesce_start(
+    mut message: SocketMessage,
+    starting: &Mutex<HashSet<String>>,
+) -> (SocketMessage, Vec<String>) {
+    let mut coalesced = vec![];
+
+    if let Some(names) = start_request(&mut message) {
+        let mut starting = starting.lock();
+
+        names.retain(|name| {
+            let is_new = starting.insert(name.clone());
+            if !is_new {
+                let _span = unit_span(name).entered();
+                tracing::info!("coalescing start request with the start attempt in flight");
+                coalesced.push(name.clone());
+            }
+
+            is_new
+        });
+    }
+
+    (message, coalesced)
+}
+
+/// Handles a socket message, returning the results of the units it acted on, where units which
+/// were coalesced with a start attempt in flight are answered with the given results
 fn handle_socket_message(
     pm: Arc<Mutex<ProcessManager>>,
     socket_message: SocketMessage,
-) -> Result<(), WpmdError> {
+    coalesced: Vec<UnitResult>,
+    reply_to: &str,
+) -> Result<Vec<UnitResult>, WpmdError> {
     let mut pm = pm.lock();
+    let mut results = vec![];
 
     match socket_message {
         SocketMessage::Start(arg) => {
-            for name in arg {
-                pm.start(&name)?;
-            }
+            results = answer(&mut pm, UnitAction::Start, &arg, coalesced, reply_to);
+        }
+        SocketMessage::StartWithoutDependencies(arg) => {
+            results = answer(
+                &mut pm,
+                UnitAction::StartWithoutDependencies,
+                &arg,
+                coalesced,
+                reply_to,
+            );
         }
         SocketMessage::Stop(arg) => {
-            for name in arg {
-                pm.stop(&name)?;
-            }
+            results = answer(&mut pm, UnitAction::Stop, &arg, coalesced, reply_to);
         }
-        SocketMessage::Restart(arg) => {
+        SocketMessage::ForceStop(arg) => {
+            results = answer(&mut pm, UnitAction::ForceStop, &arg, coalesced, reply_to);
+        }
+        SocketMessage::Batch(action, arg) => {
+            results = answer(&mut pm, action, &arg, coalesced, reply_to);
+        }
+        // failures are logged by the process manager, as no client is waiting for the results
+        SocketMessage::Scheduled(action, arg) => {
+            results = apply(&mut pm, action, &arg);
+            results.extend(coalesced);
+        }
+        SocketMessage::Adopt(arg) => {
+            let mut response = String::new();
             for name in arg {
-                if let Err(error) = pm.stop(&name) {
-                    tracing::warn!("{error}");
+                match pm.adopt(&name) {
+                    Ok(pid) => response.push_str(&format!("[{name}]: Adopted process {pid}\n")),
+                    Err(error) => response.push_str(&format!("[{name}]: {error}\n")),
                 }
-
-                pm.start(&name)?;
             }
+
+            send_str(reply_to, &response)?;
+        }
+        SocketMessage::Restart(arg) => {
+            results = answer(&mut pm, UnitAction::Restart, &arg, coalesced, reply_to);
         }
         SocketMessage::RestartWithDependents(arg) => {
-            for name in arg {
-                if let Err(error) = pm.stop(&name) {
-                    tracing::warn!("{error}");
-                }
-
-                pm.start(&name)?;
-
-                for dependent in pm.dependents(&name) {
-                    for (definition, status) in pm.state().0 {
-                        if definition.unit.name.eq(&dependent)
-                            && matches!(status.state, UnitState::Running)
-                        {
-                            tracing::info!("{dependent}: restarting as a dependent of {name}");
-                            if let Err(error) = pm.stop(&dependent) {
-                                tracing::warn!("{error}");
-                            }
+            results = answer(
+                &mut pm,
+                UnitAction::RestartWithDependents,
+                &arg,
+                coalesced,
+                reply_to,
+            );
+        }
+        SocketMessage::Status(arg, log_lines) => {
+            let status_message = pm.state().unit_status(&arg, log_lines)?;
+            send_str(reply_to, &status_message)?;
+        }
+        SocketMessage::Why(arg) => {
+            send_str(reply_to, &pm.state().explain(&arg))?;
+        }
+        SocketMessage::Show(arg) => {
+            send_str(reply_to, &pm.state().show(&arg))?;
+        }
+        SocketMessage::Stats => {
+            let uptime = STARTED_AT.get().map(Instant::elapsed).unwrap_or_default();
+            send_str(reply_to, &pm.state().stats(uptime))?;
+        }
+        SocketMessage::State(options) if options.json => {
+            send_str(reply_to, &pm.state().as_json(&options)?)?;
+        }
+        SocketMessage::State(options) => {
+            let mut table = pm.state().as_table(&options);
+            if !table.is_empty() {
+                table.push('\n');
+            }
 
-                            pm.start(&dependent)?;
-                        }
-                    }
-                }
+            // quiet output is meant to be piped into other commands, so it only holds names
+            if !options.quiet && restart_budget::is_exhausted() {
+                table.push_str("Automatic restarts are paused after too many restarts within a minute; run `wpmctl reset --restart-budget` to resume them\n");
             }
+
+            send_str(reply_to, &table)?;
         }
-        SocketMessage::Status(arg) => {
-            let status_message = pm.state().unit_status(&arg)?;
-            send_str("wpmctl.sock", &status_message)?;
+        SocketMessage::UnitStates(names) => {
+            let reports = pm
+                .state()
+                .0
+                .into_iter()
+                .filter(|(definition, _)| names.contains(&definition.unit.name))
+                .map(|(definition, status)| UnitStateReport {
+                    name: status.name,
+                    kind: status.kind.0,
+                    state: status.state,
+                    requires: definition.unit.requires.unwrap_or_default(),
+                })
+                .collect::<Vec<_>>();
+
+            send_str(reply_to, &serde_json::to_string(&reports)?)?;
         }
-        SocketMessage::State => {
-            let table = format!("{}\n", pm.state().as_table());
-            send_str("wpmctl.sock", &table)?;
+        SocketMessage::UsageHistory(names) => {
+            let history = usage_history::samples(&names);
+            send_str(reply_to, &serde_json::to_string(&history)?)?;
         }
         SocketMessage::Reload(arg) => {
-            pm.load_units(arg)?;
+            // always respond so that wpmctl isn't left waiting if the unit directory can't be read
+            let summary = match pm.load_units(arg) {
+                Ok(()) => format!("{}\n", pm.state().load_summary()),
+                Err(error) => {
+                    tracing::error!("failed to reload units: {error}");
+                    format!("Failed to reload units: {error}\n")
+                }
+            };
+
+            send_str(reply_to, &summary)?;
+        }
+        SocketMessage::Isolate(arg) => {
+            pm.isolate(&arg)?;
+        }
+        SocketMessage::Remove(name, options) => {
+            let response = match pm.remove(&name, &options) {
+                Ok(output) => format!("{}\n", output.join("\n")),
+                Err(error) => format!("Failed to remove {name}: {error}\n"),
+            };
+
+            send_str(reply_to, &response)?;
+        }
+        SocketMessage::SnapshotSave(name) => {
+            let response = match pm.save_snapshot(&name) {
+                Ok(snapshot) => format!(
+                    "Saved snapshot {name} with {} running units\n",
+                    snapshot.units.len()
+                ),
+                Err(error) => format!("Failed to save snapshot {name}: {error}\n"),
+            };
+
+            send_str(reply_to, &response)?;
+        }
+        SocketMessage::SnapshotRestore(name) => {
+            let response = match pm.restore_snapshot(&name) {
+                Ok(snapshot) => format!(
+                    "Restored snapshot {name} with {} units\n",
+                    snapshot.units.len()
+                ),
+                Err(error) => format!("Failed to restore snapshot {name}: {error}\n"),
+            };
+
+            send_str(reply_to, &response)?;
         }
         SocketMessage::Reset(arg) => {
             for name in arg {
                 pm.reset(&name);
             }
         }
+        SocketMessage::KillOrphans(arg) => {
+            let mut response = String::new();
+            for name in arg {
+                match pm.kill_orphans(&name) {
+                    Ok(pids) if pids.is_empty() => {
+                        response.push_str(&format!("[{name}]: No orphaned processes\n"));
+                    }
+                    Ok(pids) => {
+                        let pids = pids.iter().map(ToString::to_string).collect::<Vec<_>>();
+                        response.push_str(&format!(
+                            "[{name}]: Killed orphaned processes {}\n",
+                            pids.join(", ")
+                        ));
+                    }
+                    Err(error) => response.push_str(&format!("[{name}]: {error}\n")),
+                }
+            }
+
+            send_str(reply_to, &response)?;
+        }
+        SocketMessage::ResetRestartBudget => {
+            restart_budget::reset();
+        }
+        SocketMessage::LogLevel(filter) => {
+            let response = match EnvFilter::try_new(&filter) {
+                Ok(env_filter) => match FILTER_HANDLE.get() {
+                    Some(handle) => match handle.reload(env_filter) {
+                        Ok(()) => {
+                            tracing::info!("log filter set to {filter}");
+                            format!("Log filter set to {filter}\n")
+                        }
+                        Err(error) => format!("Failed to set log filter: {error}\n"),
+                    },
+                    None => "Log filter cannot be changed at runtime\n".to_string(),
+                },
+                Err(error) => format!("Invalid log filter {filter}: {error}\n"),
+            };
+
+            send_str(reply_to, &response)?;
+        }
+        SocketMessage::ProtocolVersion => {
+            send_str(reply_to, &PROTOCOL_VERSION.to_string())?;
+        }
+        SocketMessage::Directories => send_directories(reply_to)?,
     }
 
+    Ok(results)
+}
+
+fn send_directories(reply_to: &str) -> Result<(), WpmdError> {
+    send_str(reply_to, &serde_json::to_string(&wpm_directories()?)?)?;
     Ok(())
 }
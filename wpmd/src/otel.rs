@@ -0,0 +1,30 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::ExporterBuildError;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Builds a tracer provider which exports spans over OTLP/HTTP, configured with the standard
+/// OTEL_EXPORTER_OTLP_* environment variables (default endpoint: http://localhost:4318)
+pub fn tracer_provider() -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = SpanExporter::builder().with_http().build()?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("wpmd").build())
+        .build())
+}
+
+/// A tracing layer which turns wpm spans into OpenTelemetry spans, so that each unit start
+/// becomes a trace with child spans for its pre-start, spawn and healthcheck phases
+pub fn layer<S>(
+    provider: &SdkTracerProvider,
+) -> OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("wpmd"))
+}
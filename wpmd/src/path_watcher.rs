@@ -0,0 +1,90 @@
+use parking_lot::Mutex;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use wpm::communication::daemon_socket;
+use wpm::communication::send_message;
+use wpm::process_manager::ProcessManager;
+use wpm::SocketMessage;
+use wpm::UnitAction;
+
+/// Interval at which watched paths are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Paths, modification times and sizes of a watched file, or of the files directly inside a
+/// watched directory; a path which doesn't exist has an empty fingerprint
+type Fingerprint = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+fn fingerprint(path: &Path) -> Fingerprint {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return vec![];
+    };
+
+    if !metadata.is_dir() {
+        return vec![(path.to_path_buf(), metadata.modified().ok(), metadata.len())];
+    }
+
+    let mut entries = std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((entry.path(), metadata.modified().ok(), metadata.len()))
+        })
+        .collect::<Vec<_>>();
+
+    entries.sort();
+    entries
+}
+
+/// Polls the paths of all path triggers and starts their units when the paths change; the
+/// triggers are read on every poll so that reloaded units are picked up
+pub fn spawn(pm: Arc<Mutex<ProcessManager>>) {
+    std::thread::spawn(move || {
+        let mut fingerprints: HashMap<PathBuf, Fingerprint> = HashMap::new();
+
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+
+            let triggers = pm.lock().path_triggers();
+            let mut current = HashMap::new();
+            let mut targets = BTreeSet::new();
+
+            for (target, trigger) in triggers {
+                for path in trigger.paths {
+                    let fingerprint = current
+                        .entry(path.clone())
+                        .or_insert_with(|| fingerprint(&path));
+
+                    // paths seen for the first time only set the baseline
+                    if fingerprints
+                        .get(&path)
+                        .is_some_and(|previous| previous != fingerprint)
+                    {
+                        tracing::info!("{} changed, starting {target}", path.display());
+                        targets.insert(target.clone());
+                    }
+                }
+            }
+
+            fingerprints = current;
+
+            // oneshot units have to be reset to be started again
+            for target in targets {
+                for message in [
+                    SocketMessage::Reset(vec![target.clone()]),
+                    SocketMessage::Scheduled(UnitAction::Start, vec![target.clone()]),
+                ] {
+                    if let Err(error) = send_message(daemon_socket(), message) {
+                        tracing::error!("{error}");
+                    }
+                }
+            }
+        }
+    });
+}
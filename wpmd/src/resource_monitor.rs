@@ -0,0 +1,162 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use wpm::communication::daemon_socket;
+use wpm::communication::send_message;
+use wpm::journal;
+use wpm::journal::UnitEvent;
+use wpm::process_manager::process_usage;
+use wpm::process_manager::ProcessManager;
+use wpm::restart_budget;
+use wpm::unit::unit_span;
+use wpm::usage_history;
+use wpm::usage_history::UsageSample;
+use wpm::SocketMessage;
+use wpm::UnitAction;
+
+/// Interval at which the resource usage of running units is sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+const DEFAULT_CONSECUTIVE_SAMPLES: u32 = 3;
+
+const BYTES_PER_MEGABYTE: u64 = 1024 * 1024;
+
+/// Consecutive samples above the thresholds of a unit, along with the pid they were taken for
+/// so that the counts start over when a unit is restarted
+#[derive(Default)]
+struct ExceededSamples {
+    pid: u32,
+    memory: u32,
+    cpu: u32,
+}
+
+fn restart(name: &str) {
+    if !restart_budget::allow_restart(name) {
+        return;
+    }
+
+    if let Err(error) = send_message(
+        daemon_socket(),
+        SocketMessage::Scheduled(UnitAction::Restart, vec![name.to_string()]),
+    ) {
+        tracing::error!("{error}");
+    }
+}
+
+/// Samples the working sets and CPU usage of running units for their usage history, restarting
+/// a unit once it has been above its memory threshold for enough consecutive
+/// samples, and recording an event (and optionally restarting it) when the same happens for
+/// its CPU threshold
+pub fn spawn(pm: Arc<Mutex<ProcessManager>>) {
+    std::thread::spawn(move || {
+        let mut exceeded: HashMap<String, ExceededSamples> = HashMap::new();
+
+        loop {
+            std::thread::sleep(SAMPLE_INTERVAL);
+
+            let monitored = pm.lock().monitored_units();
+            exceeded.retain(|name, _| monitored.iter().any(|unit| &unit.name == name));
+
+            for unit in monitored {
+                let Some(usage) = process_usage(unit.pid) else {
+                    continue;
+                };
+
+                let name = unit.name;
+                let _span = unit_span(&name).entered();
+
+                usage_history::record(&name, UsageSample::now(usage.memory, usage.cpu_percent));
+
+                let samples = exceeded.entry(name.clone()).or_default();
+
+                if samples.pid != unit.pid {
+                    *samples = ExceededSamples {
+                        pid: unit.pid,
+                        ..Default::default()
+                    };
+                }
+
+                if let Some(threshold) = unit.cpu {
+                    let percent = usage.cpu_percent;
+
+                    if percent <= threshold.percent {
+                        samples.cpu = 0;
+                    } else {
+                        samples.cpu += 1;
+                        tracing::debug!(
+                            "cpu usage of {percent:.0}% is above {}% ({} consecutive samples)",
+                            threshold.percent,
+                            samples.cpu
+                        );
+
+                        let required = threshold
+                            .consecutive_samples
+                            .unwrap_or(DEFAULT_CONSECUTIVE_SAMPLES);
+
+                        // the event is only recorded once for every period above the threshold
+                        if samples.cpu == required {
+                            tracing::warn!(
+                                "cpu usage of {percent:.0}% has been above {}% for {required} consecutive samples",
+                                threshold.percent
+                            );
+
+                            journal::record(
+                                &name,
+                                UnitEvent::CpuThresholdExceeded {
+                                    percent,
+                                    threshold: threshold.percent,
+                                },
+                            );
+
+                            if threshold.restart {
+                                restart(&name);
+                            }
+                        }
+                    }
+                }
+
+                if let Some(threshold) = unit.memory {
+                    let megabytes = usage.memory / BYTES_PER_MEGABYTE;
+
+                    if megabytes <= threshold.megabytes {
+                        samples.memory = 0;
+                        continue;
+                    }
+
+                    samples.memory += 1;
+                    tracing::debug!(
+                        "working set of {megabytes}MB is above {}MB ({} consecutive samples)",
+                        threshold.megabytes,
+                        samples.memory
+                    );
+
+                    let required = threshold
+                        .consecutive_samples
+                        .unwrap_or(DEFAULT_CONSECUTIVE_SAMPLES);
+
+                    if samples.memory < required {
+                        continue;
+                    }
+
+                    samples.memory = 0;
+
+                    tracing::warn!(
+                        "working set of {megabytes}MB has been above {}MB for {required} consecutive samples, restarting",
+                        threshold.megabytes
+                    );
+
+                    journal::record(
+                        &name,
+                        UnitEvent::MemoryThresholdExceeded {
+                            megabytes,
+                            threshold: threshold.megabytes,
+                        },
+                    );
+
+                    restart(&name);
+                }
+            }
+        }
+    });
+}
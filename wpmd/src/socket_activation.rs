@@ -0,0 +1,109 @@
+use std::net::Shutdown;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::time::Duration;
+use std::time::Instant;
+use wpm::communication::daemon_socket;
+use wpm::communication::send_message;
+use wpm::unit::unit_span;
+use wpm::unit::SocketActivation;
+use wpm::SocketMessage;
+use wpm::UnitAction;
+
+/// Default time to wait for a unit to accept connections after starting it
+const DEFAULT_TIMEOUT_SEC: u64 = 30;
+
+const CONNECT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Connects to the unit, starting it first if nothing is listening on its target address yet
+fn connect_to_unit(name: &str, activation: &SocketActivation) -> std::io::Result<TcpStream> {
+    if let Ok(stream) = TcpStream::connect(activation.target) {
+        return Ok(stream);
+    }
+
+    tracing::info!(
+        "connection received on {}, starting unit",
+        activation.listen
+    );
+
+    // concurrent requests for the same unit are coalesced by the socket listener
+    send_message(
+        daemon_socket(),
+        SocketMessage::Scheduled(UnitAction::Start, vec![name.to_string()]),
+    )?;
+
+    let timeout = Duration::from_secs(activation.timeout_sec.unwrap_or(DEFAULT_TIMEOUT_SEC));
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        match TcpStream::connect(activation.target) {
+            Ok(stream) => return Ok(stream),
+            Err(error) if Instant::now() >= deadline => return Err(error),
+            Err(_) => std::thread::sleep(CONNECT_INTERVAL),
+        }
+    }
+}
+
+/// Copies data between the client and the unit in both directions until either side closes
+fn proxy(client: TcpStream, unit: TcpStream) -> std::io::Result<()> {
+    let mut client_reader = client.try_clone()?;
+    let mut unit_writer = unit.try_clone()?;
+
+    let upstream = std::thread::spawn(move || {
+        let _ = std::io::copy(&mut client_reader, &mut unit_writer);
+        let _ = unit_writer.shutdown(Shutdown::Write);
+    });
+
+    let mut unit_reader = unit;
+    let mut client_writer = client;
+    let _ = std::io::copy(&mut unit_reader, &mut client_writer);
+    let _ = client_writer.shutdown(Shutdown::Write);
+
+    let _ = upstream.join();
+    Ok(())
+}
+
+/// Listens on the socket activation address of a unit, starting the unit on the first
+/// connection and proxying every connection to the address the unit listens on
+pub fn spawn(name: String, activation: SocketActivation) -> std::io::Result<()> {
+    let _span = unit_span(&name).entered();
+    let listener = TcpListener::bind(activation.listen)?;
+    tracing::info!("listening on {} for socket activation", activation.listen);
+
+    std::thread::spawn(move || {
+        let _span = unit_span(&name).entered();
+
+        for client in listener.incoming() {
+            let client = match client {
+                Ok(client) => client,
+                Err(error) => {
+                    tracing::error!("failed to accept connection: {error}");
+                    continue;
+                }
+            };
+
+            let name = name.clone();
+            let activation = activation.clone();
+
+            std::thread::spawn(move || {
+                let _span = unit_span(&name).entered();
+
+                match connect_to_unit(&name, &activation) {
+                    Ok(unit) => {
+                        if let Err(error) = proxy(client, unit) {
+                            tracing::warn!("failed to proxy connection: {error}");
+                        }
+                    }
+                    Err(error) => {
+                        tracing::error!(
+                            "could not connect to {} after starting the unit: {error}",
+                            activation.target
+                        );
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(())
+}